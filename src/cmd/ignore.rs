@@ -1,8 +1,9 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use crate::config::Config;
 use crate::error::Result;
 use crate::local_storage::LocalStorage;
+use crate::scan::IgnoreRules;
 
 pub fn cmd_ignore_add(
     config: &Config,
@@ -10,7 +11,8 @@ pub fn cmd_ignore_add(
     paths: impl IntoIterator<Item = PathBuf>,
 ) -> Result {
     for path in paths.into_iter() {
-        local_storage.ignored.insert(path.canonicalize()?);
+        // the path is kept as given, links are not resolved
+        local_storage.ignored.insert(std::path::absolute(path)?);
     }
     local_storage.save(config.local_storage_path())?;
     Ok(())
@@ -22,7 +24,10 @@ pub fn cmd_ignore_remove(
     paths: impl IntoIterator<Item = PathBuf>,
 ) -> Result {
     for path in paths.into_iter() {
-        local_storage.ignored.remove(&path.canonicalize()?);
+        local_storage.ignored.remove(&std::path::absolute(&path)?);
+        if let Ok(path) = path.canonicalize() {
+            local_storage.ignored.remove(&path);
+        }
     }
     local_storage.save(config.local_storage_path())?;
     Ok(())
@@ -33,3 +38,58 @@ pub fn cmd_ignore_list(local_storage: LocalStorage) {
         println!("{}", path.display());
     }
 }
+
+/// Explain why a path would be ignored by a scan of `root`.
+pub fn cmd_ignore_check(config: &Config, local_storage: &LocalStorage, path: &Path, root: &Path) -> Result {
+    let path = std::path::absolute(path)?;
+    let root = std::path::absolute(root)?;
+    if !path.starts_with(&root) || path == root {
+        println!(
+            "{} is not below the scanned directory {}",
+            path.display(),
+            root.display()
+        );
+        return Ok(());
+    }
+
+    let mut rules = IgnoreRules::new();
+    rules.add_file(config.ignore_file_path(), &root)?;
+    rules.add_dir(&root)?;
+
+    // the path is checked the way a scan walks to it, an ignored directory hides its descendants
+    let mut ancestors: Vec<_> = path.ancestors().take_while(|a| *a != root).collect();
+    ancestors.reverse();
+
+    for ancestor in ancestors {
+        let hidden = if ancestor == path {
+            String::new()
+        } else {
+            format!(" because its directory {} is ignored", ancestor.display())
+        };
+
+        if local_storage.ignored.contains(ancestor) {
+            println!("{} is ignored{} in the list of ignored paths", path.display(), hidden);
+            return Ok(());
+        }
+
+        let is_dir = ancestor != path || ancestor.is_dir();
+        match rules.matching(ancestor, is_dir) {
+            Some(rule) if !rule.negated => {
+                println!("{} is ignored{} by {}", path.display(), hidden, rule);
+                return Ok(());
+            }
+            Some(rule) if ancestor == path => {
+                println!("{} is not ignored, it is included again by {}", path.display(), rule);
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        if ancestor != path {
+            rules.add_dir(ancestor)?;
+        }
+    }
+
+    println!("{} is not ignored", path.display());
+    Ok(())
+}
@@ -18,7 +18,7 @@ pub fn cmd_images(config: Config, library: &mut Library) -> Result {
 
             if let Some(tmdb_title) = tmdb.find(movie.imdb_id)? {
                 if let Some(poster_path) = &tmdb_title.poster_path {
-                    let rel_path = RelPath::new(movie.file.path.with_file_name("poster.jpg")).unwrap();
+                    let rel_path = RelPath::new(movie.file.path.with_file_name("poster.jpg"));
                     let abs_path = root_path.join(&rel_path);
 
                     tmdb.get_save_image(&poster_path, &abs_path)?;
@@ -34,7 +34,7 @@ pub fn cmd_images(config: Config, library: &mut Library) -> Result {
                 }
 
                 if let Some(backdrop_path) = &tmdb_title.backdrop_path {
-                    let rel_path = RelPath::new(movie.file.path.with_file_name("backdrop.jpg")).unwrap();
+                    let rel_path = RelPath::new(movie.file.path.with_file_name("backdrop.jpg"));
                     let abs_path = root_path.join(&rel_path);
 
                     tmdb.get_save_image(&backdrop_path, root_path.join(root_path.join(&rel_path)))?;
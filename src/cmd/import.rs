@@ -1,10 +1,14 @@
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::{
     atomic::{AtomicBool, Ordering},
     Arc,
 };
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+use encoding_rs::Encoding;
 use lazy_static::lazy_static;
 use signal_hook::flag as signal;
 use signal_hook::{SIGINT, SIGTERM};
@@ -13,23 +17,108 @@ use super::view::Classified;
 use crate::cmd::scan::Report;
 use crate::config::Config;
 use crate::error::Result;
-use crate::io::transfer::{Manager, Transfer};
+use crate::index::TitleId;
+use crate::io::fingerprint;
+use crate::io::transfer::{Conversion, Manager, Transfer};
 use crate::library::{self, Library};
-use crate::scan::{RelPath, SubtitleFile};
-use crate::utils::clean_path;
+use crate::scan::{
+    alpha2, probe_streams, rank_subtitles, ExtraFile, MovieFile, Quality, RelPath, SubFormat, SubtitleFile,
+};
+use crate::service::ffprobe::FFPROBE;
+use crate::utils::{clean_path, decode_file_name};
+
+/// Editions are named as in `Title (Year) {edition-Director's Cut}`, the versions of a movie share its folder.
+fn edition_tag(edition: Option<&str>) -> String {
+    edition
+        .map(|edition| format!(" {{edition-{}}}", clean_path(edition)))
+        .unwrap_or_default()
+}
 
-fn make_movie_path(primary_title: &str, year: u16, ext: &str) -> RelPath {
+/// Versions are named with their resolution, as in `Title (Year) - 2160p`, so that copies of an edition in
+/// different resolutions do not overwrite each other.
+fn resolution_tag(resolution: Option<u32>) -> String {
+    resolution
+        .map(|resolution| format!(" - {}p", resolution))
+        .unwrap_or_default()
+}
+
+fn make_movie_path(
+    primary_title: &str,
+    year: u16,
+    edition: Option<&str>,
+    resolution: Option<u32>,
+    ext: &str,
+) -> RelPath {
     let mut path = PathBuf::new();
     let cleaned_name = clean_path(&format!("{} ({})", primary_title, year));
     let dotted_name = cleaned_name.replace(" ", ".");
     path.push(&dotted_name);
-    path.push(format!("{}.{}", dotted_name, ext.to_lowercase()));
-    RelPath::new(path).unwrap()
+    path.push(format!(
+        "{}{}{}.{}",
+        dotted_name,
+        edition_tag(edition),
+        resolution_tag(resolution),
+        ext.to_lowercase()
+    ));
+    RelPath::new(path)
 }
 
-fn make_subtitle_path(movie_path: &Path, subtitle: &SubtitleFile) -> RelPath {
-    let ext = format!("{}.{}", subtitle.lang, &subtitle.ext);
-    RelPath::new(movie_path.with_extension(&ext)).unwrap()
+fn make_movie_part_path(primary_title: &str, year: u16, edition: Option<&str>, part: usize, ext: &str) -> RelPath {
+    let mut path = PathBuf::new();
+    let cleaned_name = clean_path(&format!("{} ({})", primary_title, year));
+    path.push(cleaned_name.replace(" ", "."));
+    path.push(format!(
+        "{}{} - part{}.{}",
+        cleaned_name,
+        edition_tag(edition),
+        part,
+        ext.to_lowercase()
+    ));
+    RelPath::new(path)
+}
+
+fn make_episode_path(series_title: &str, year: u16, season: u16, episode: u16, ext: &str) -> RelPath {
+    let mut path = PathBuf::new();
+    let dotted_name = clean_path(series_title).replace(" ", ".");
+    path.push(clean_path(&format!("{} ({})", series_title, year)));
+    path.push(format!("Season {:02}", season));
+    path.push(format!(
+        "{}.S{:02}E{:02}.{}",
+        dotted_name,
+        season,
+        episode,
+        ext.to_lowercase()
+    ));
+    RelPath::new(path)
+}
+
+/// Subtitles are named `Title.en.forced.srt` so that players find their language and flags.
+///
+/// Subtitles ranked below the best one of their language get a number, as in `Title.en.2.srt`.
+fn make_subtitle_path(movie_path: &Path, subtitle: &SubtitleFile, rank: usize) -> RelPath {
+    let mut ext = alpha2(&subtitle.lang).unwrap_or(&subtitle.lang).to_owned();
+    if subtitle.forced {
+        ext.push_str(".forced");
+    }
+    if subtitle.sdh {
+        ext.push_str(".sdh");
+    }
+    if rank > 0 {
+        ext.push_str(&format!(".{}", rank + 1));
+    }
+    ext.push('.');
+    ext.push_str(&subtitle.ext);
+    RelPath::new(movie_path.with_extension(&ext))
+}
+
+fn make_extra_path(movie_path: &Path, extra: &ExtraFile) -> RelPath {
+    let mut path = movie_path.parent().map(Path::to_path_buf).unwrap_or_default();
+    path.push("extras");
+    // names which are not UTF-8 are decoded, library names are always UTF-8
+    path.push(clean_path(
+        &extra.path().file_name().map(decode_file_name).unwrap_or_default(),
+    ));
+    RelPath::new(path)
 }
 
 fn print_transfer(transfer: &Transfer) {
@@ -43,14 +132,383 @@ lazy_static! {
     static ref QUIT: Arc<AtomicBool> = Arc::new(AtomicBool::new(false));
 }
 
-pub fn cmd_import(config: Config, path: impl AsRef<Path>, library: &mut Library) -> Result {
+/// Options of the import command.
+#[derive(Debug, Default)]
+pub struct ImportOptions {
+    /// Re-encode text subtitles to UTF-8.
+    pub utf8_subtitles: bool,
+    /// Write a byte order mark at the start of re-encoded subtitles.
+    pub bom: bool,
+    /// Convert MicroDVD subtitles, whose timings are in frames, to SRT.
+    pub srt_subtitles: bool,
+    /// Only import the best subtitle of each language, instead of numbering the others.
+    pub skip_duplicate_subtitles: bool,
+    /// Replace the library copy of duplicate movies by better ones.
+    pub upgrade: bool,
+    /// Edition of the imported movie, instead of the one found in its name. The scan must hold a single movie.
+    pub edition: Option<String>,
+    /// ffprobe binary comparing the copies of upgraded movies, the one of the PATH if `None`.
+    pub ffprobe: Option<PathBuf>,
+}
+
+/// Add the transfers of a movie file and its parts, returns the path of the movie and its parts.
+///
+/// The resolution is added to the name of versions.
+fn add_movie_transfers(
+    manager: &mut Manager,
+    movie: &MovieFile,
+    resolution: Option<u32>,
+    root_path: &Path,
+) -> (RelPath, Vec<library::Part>) {
+    let identity = movie.identity().expect("match identity should never be None");
+    let title = &identity.title;
+    let edition = movie.release.edition.as_deref();
+
+    let ext = movie.path().extension().map(decode_file_name).unwrap_or_default();
+    let movie_path = match &identity.episode {
+        Some(episode) => make_episode_path(&title.primary_title, title.year, episode.season, episode.episode, &ext),
+        None if !movie.parts.is_empty() => make_movie_part_path(&title.primary_title, title.year, edition, 1, &ext),
+        None => make_movie_path(&title.primary_title, title.year, edition, resolution, &ext),
+    };
+
+    manager.add_transfer(movie.path(), root_path.join(&movie_path));
+
+    let mut parts = vec![];
+
+    // parts are numbered from their position, the movie file being the first part
+    for (index, part) in movie.parts.iter().enumerate() {
+        let ext = part.path().extension().map(decode_file_name).unwrap_or_default();
+        let part_path = make_movie_part_path(&title.primary_title, title.year, edition, index + 2, &ext);
+
+        manager.add_transfer(part.path(), root_path.join(&part_path));
+
+        parts.push(library::Part::new(
+            library::File::new(part_path, part.fingerprint.clone()),
+            index as u16 + 2,
+        ));
+    }
+
+    (movie_path, parts)
+}
+
+/// Add the transfers of the subtitles of a movie, except the ones of a language in `skip_langs`.
+fn add_subtitle_transfers(
+    manager: &mut Manager,
+    movie: &MovieFile,
+    movie_path: &Path,
+    root_path: &Path,
+    options: &ImportOptions,
+    skip_langs: &[String],
+) -> Vec<library::Subtitle> {
+    let identity = movie.identity().expect("match identity should never be None");
+
+    let mut subtitles = vec![];
+    let runtime = identity.title.runtime;
+    let ranks = rank_subtitles(&movie.subtitles, Some(runtime));
+
+    for (sub, &rank) in movie.subtitles.iter().zip(ranks.iter()) {
+        if rank > 0 && options.skip_duplicate_subtitles {
+            println!("Skipping duplicate subtitle {}", sub.path().display());
+            continue;
+        }
+        if skip_langs.contains(&sub.lang) {
+            println!("Skipping subtitle {}, the library has one", sub.path().display());
+            continue;
+        }
+
+        let mut subtitle_path = make_subtitle_path(movie_path, sub, rank);
+        let mut format = sub.format;
+
+        // frame rate is only known for frame-based subtitles
+        let srt_frame_rate = sub.frame_rate.filter(|_| options.srt_subtitles);
+        let encoding = sub.encoding.as_ref().and_then(|e| Encoding::for_label(e.as_bytes()));
+        let conversion = match encoding {
+            Some(encoding) if options.utf8_subtitles || srt_frame_rate.is_some() => Some(Conversion {
+                encoding,
+                bom: options.bom,
+                srt_frame_rate,
+            }),
+            _ => None,
+        };
+
+        if conversion.is_some() && srt_frame_rate.is_some() {
+            subtitle_path = RelPath::new(subtitle_path.with_extension("srt"));
+            format = SubFormat::SubRip;
+        }
+
+        match conversion {
+            Some(conversion) => manager.add_conversion(sub.path(), root_path.join(&subtitle_path), conversion),
+            None => manager.add_transfer(sub.path(), root_path.join(&subtitle_path)),
+        }
+        if let Some(data) = &sub.data {
+            manager.add_transfer(&data.path, root_path.join(subtitle_path.with_extension("sub")));
+        }
+
+        let mut subtitle = library::Subtitle::new(
+            library::File::new(subtitle_path, sub.fingerprint.clone()),
+            sub.lang.clone(),
+            format.as_str(),
+        );
+        subtitle.forced = sub.forced;
+        subtitle.sdh = sub.sdh;
+        subtitle.confidence = sub.confidence;
+        if conversion.is_some() {
+            // the fingerprint of the converted file is known once it is written
+            subtitle.source_fingerprint = Some(sub.fingerprint.clone());
+        }
+        subtitles.push(subtitle);
+    }
+
+    subtitles
+}
+
+/// Run the transfers of a manager, returns false if they were cancelled by a quit signal.
+fn run_transfers(manager: &mut Manager) -> Result<bool> {
+    let mut last = Instant::now();
+    loop {
+        if QUIT.load(Ordering::Relaxed) {
+            // received SIGINT or SIGTERM, remove incomplete transfer
+            println!("\nReceived quit signal, cancelling current transfer.");
+            manager.try_cancel();
+            return Ok(false);
+        }
+        match manager.step() {
+            Ok(Some(transfer)) => {
+                if last.elapsed() > Duration::from_secs(1) {
+                    print_transfer(transfer);
+                    last = Instant::now();
+                }
+            }
+            Ok(None) => break,
+            Err(err) => {
+                // IO error occured
+                println!("IO error {}, cancelling current transfer.", err);
+                manager.try_cancel();
+                return Err(err);
+            }
+        }
+    }
+
+    println!("Transfer status");
+    println!("===============");
+
+    for transfer in manager.transfers() {
+        print_transfer(transfer);
+    }
+
+    Ok(true)
+}
+
+/// Move files of the library to the trash, keeping their path relative to the library.
+fn move_to_trash(root_path: &Path, trash_path: &Path, paths: &[&RelPath]) -> Result<Vec<(PathBuf, PathBuf)>> {
+    let mut moved = vec![];
+    for path in paths {
+        let src = root_path.join(path);
+        let dst = trash_path.join(path);
+        let result = dst
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::rename(&src, &dst));
+        if let Err(err) = result {
+            restore_from_trash(&moved);
+            return Err(err.into());
+        }
+        moved.push((src, dst));
+    }
+    Ok(moved)
+}
+
+fn restore_from_trash(moved: &[(PathBuf, PathBuf)]) {
+    for (src, dst) in moved {
+        if let Err(err) = fs::rename(dst, src) {
+            println!("Unable to restore {} from the trash: {}", src.display(), err);
+        }
+    }
+}
+
+/// Replace the library copy of a movie by a file of better quality, moving the previous files to the trash.
+///
+/// The movie keeps its id, subtitles, images and extras. Returns false if the transfer was cancelled.
+fn upgrade_movie(
+    library: &mut Library,
+    movie: &MovieFile,
+    root_path: &Path,
+    trash_path: &Path,
+    options: &ImportOptions,
+) -> Result<bool> {
+    let identity = movie.identity().expect("duplicate identity should never be None");
+    if identity.episode.is_some() {
+        println!("Skipping {}, only movies can be upgraded", movie.path().display());
+        return Ok(true);
+    }
+
+    let mut lib_movie = match library.find_movie(identity.title.title_id)? {
+        Some(lib_movie) => lib_movie,
+        None => return Ok(true),
+    };
+    if lib_movie.edition != movie.release.edition {
+        println!(
+            "Skipping {}, only the main version of a movie can be upgraded",
+            movie.path().display()
+        );
+        return Ok(true);
+    }
+
+    let ffprobe = options.ffprobe.as_deref().map_or(OsStr::new(FFPROBE), Path::as_os_str);
+    let old_quality = match Quality::probe(ffprobe, root_path.join(&lib_movie.file.path)) {
+        Ok(quality) => quality,
+        Err(err) => {
+            println!("Unable to probe {}: {}", lib_movie.file.path, err);
+            return Ok(true);
+        }
+    };
+    // movies were already probed by scans run with --probe
+    let (video, audio, duration) = if movie.video.is_empty() {
+        match probe_streams(ffprobe, movie.path()) {
+            Ok(streams) => streams,
+            Err(err) => {
+                println!("Unable to probe {}: {}", movie.path().display(), err);
+                return Ok(true);
+            }
+        }
+    } else {
+        (movie.video.clone(), movie.audio.clone(), movie.duration)
+    };
+    let new_quality = Quality::new(&video, duration, movie.size());
+
+    if !new_quality.is_better_than(&old_quality) {
+        println!(
+            "Keeping {} ({}), {} is not better ({})",
+            lib_movie.file.path,
+            old_quality,
+            movie.path().display(),
+            new_quality
+        );
+        return Ok(true);
+    }
+
+    println!(
+        "Upgrading {} ({}) with {} ({})",
+        lib_movie.file.path,
+        old_quality,
+        movie.path().display(),
+        new_quality
+    );
+    println!();
+
+    library.load_parts(&mut lib_movie)?;
+    library.load_subtitles(&mut lib_movie)?;
+
+    let mut old_paths = vec![&lib_movie.file.path];
+    old_paths.extend(lib_movie.parts.iter().map(|part| &part.file.path));
+    let trashed = move_to_trash(root_path, trash_path, &old_paths)?;
+
+    let mut manager = Manager::new();
+    let (movie_path, parts) = add_movie_transfers(&mut manager, movie, None, root_path);
+    // subtitles are named after the movie without its extension, the ones of the library still fit
+    let skip_langs: Vec<String> = lib_movie.subtitles.iter().map(|s| s.lang.clone()).collect();
+    let mut subtitles = add_subtitle_transfers(&mut manager, movie, &movie_path, root_path, options, &skip_langs);
+
+    match run_transfers(&mut manager) {
+        Ok(true) => {}
+        Ok(false) => {
+            restore_from_trash(&trashed);
+            return Ok(false);
+        }
+        Err(err) => {
+            restore_from_trash(&trashed);
+            return Err(err);
+        }
+    }
+    println!();
+
+    for subtitle in subtitles.iter_mut().filter(|s| s.source_fingerprint.is_some()) {
+        subtitle.file.fingerprint = fingerprint::file(root_path.join(&subtitle.file.path))?;
+    }
+
+    for part in &lib_movie.parts {
+        library.delete_part(&lib_movie.id, part)?;
+    }
+    // the movie keeps its file entry, which can keep its path too
+    library.delete_streams(&lib_movie.file)?;
+    lib_movie.file.path = movie_path;
+    lib_movie.file.fingerprint = movie.fingerprint.clone();
+    lib_movie.resolution = Some(new_quality.resolution).filter(|&resolution| resolution > 0);
+    lib_movie.parts = parts;
+    lib_movie.subtitles = subtitles;
+    lib_movie.video = video;
+    lib_movie.audio = audio;
+    library.save_movie(&lib_movie)?;
+
+    println!("Previous copy moved to {}", trash_path.display());
+    println!();
+
+    Ok(true)
+}
+
+/// Assign an edition to the copies of the movie of a scan, returns false if the scan holds several movies.
+///
+/// The edition is assigned before classifying, so that a copy of another edition is added as a version.
+fn assign_edition(movies: &mut [MovieFile], edition: &str) -> bool {
+    let titles: HashSet<TitleId> = movies
+        .iter()
+        .filter_map(|m| m.identity())
+        .map(|i| i.title.title_id)
+        .collect();
+    if titles.len() > 1 {
+        return false;
+    }
+
+    for movie in movies.iter_mut() {
+        movie.release.edition = Some(edition.to_owned());
+    }
+    true
+}
+
+pub fn cmd_import(config: Config, path: impl AsRef<Path>, library: &mut Library, options: ImportOptions) -> Result {
     signal::register(SIGINT, QUIT.clone()).expect("unable to setup SIGINT hook");
     signal::register(SIGTERM, QUIT.clone()).expect("unable to setup SIGTERM hook");
 
     let path = path.as_ref();
 
-    let report = Report::load(path)?;
-    let classified = Classified::classify(&library, report.movies)?;
+    let mut report = Report::load(path)?;
+    if let Some(edition) = &options.edition {
+        if !assign_edition(&mut report.movies, edition) {
+            println!(
+                "Unable to import several movies as the {} edition, scan the folder of a single movie",
+                edition
+            );
+            return Ok(());
+        }
+    }
+
+    import_movies(&config, report.movies, library, &options)
+}
+
+/// Import the movies of a scan report which are not in the library yet, or better than its copies with `--upgrade`.
+fn import_movies(config: &Config, movies: Vec<MovieFile>, library: &mut Library, options: &ImportOptions) -> Result {
+    let classified = Classified::classify(library, movies, options.upgrade)?;
+
+    if !classified.pending.is_empty() {
+        println!(
+            "Skipping {} files still being downloaded, scan again once they are complete",
+            classified.pending.len()
+        );
+    }
+
+    if !classified.broken.is_empty() {
+        println!(
+            "Skipping {} corrupt or truncated files, see the broken files of the scan report",
+            classified.broken.len()
+        );
+    }
+
+    if !classified.duplicates.is_empty() && !options.upgrade {
+        println!(
+            "Skipping {} files already in the library, use --upgrade to replace them by better copies",
+            classified.duplicates.len()
+        );
+    }
 
     let mut finished = 0;
     let len = classified.matches.len();
@@ -63,80 +521,256 @@ pub fn cmd_import(config: Config, path: impl AsRef<Path>, library: &mut Library)
         let identity = movie.identity().expect("match identity should never be None");
         let title = &identity.title;
 
+        // matches of a movie already in the library are other editions or resolutions of it, added as versions
+        let lib_movie = match &identity.episode {
+            Some(_) => None,
+            None => library.find_movie(title.title_id)?,
+        };
+        if lib_movie.is_some() && !movie.parts.is_empty() {
+            println!(
+                "Skipping {}, versions split in parts are not supported",
+                movie.path().display()
+            );
+            println!();
+            continue;
+        }
+        if lib_movie.is_some() && !movie.extras.is_empty() {
+            println!(
+                "Skipping the extras of {}, they belong to the movie",
+                movie.path().display()
+            );
+        }
+
         let mut manager = Manager::new();
 
-        let ext = movie.path().extension().and_then(|s| s.to_str()).unwrap_or("");
-        let movie_path = make_movie_path(&title.primary_title, title.year, ext);
+        let version_resolution = lib_movie.as_ref().and(movie.resolution());
+        let (movie_path, parts) = add_movie_transfers(&mut manager, &movie, version_resolution, root_path);
+        let mut subtitles = add_subtitle_transfers(&mut manager, &movie, &movie_path, root_path, options, &[]);
 
-        manager.add_transfer(&movie.path(), root_path.join(&movie_path));
+        let mut extras = vec![];
 
-        let mut lib_movie = library::Movie::new(
-            library::File::new(movie_path.clone(), movie.fingerprint.clone()),
-            title.title_id,
-            title.primary_title.clone(),
-            title.original_title.clone().unwrap_or(title.primary_title.clone()),
-            title.year,
-        );
+        for extra in movie.extras.iter().filter(|_| lib_movie.is_none()) {
+            let extra_path = make_extra_path(&movie_path, extra);
 
-        for sub in movie.subtitles.iter() {
-            let subtitle_path = make_subtitle_path(&movie_path, &sub);
+            // two extras with the same name in different folders would overwrite each other
+            if extras.iter().any(|e: &library::Extra| e.file.path == extra_path) {
+                println!("Skipping extra {}, name already used", extra.path().display());
+                continue;
+            }
 
-            manager.add_transfer(&sub.path(), root_path.join(&subtitle_path));
+            manager.add_transfer(extra.path(), root_path.join(&extra_path));
 
-            lib_movie.subtitles.push(library::Subtitle::new(
-                library::File::new(subtitle_path, sub.fingerprint.clone()),
-                sub.lang.clone(),
+            extras.push(library::Extra::new(
+                library::File::new(extra_path, extra.fingerprint.clone()),
+                extra.kind.as_str(),
             ));
+        }
 
-            // TODO: better duplicate subtitle selection
-            lib_movie
-                .subtitles
-                .sort_unstable_by(|left, right| left.file.path.cmp(&right.file.path));
+        if !run_transfers(&mut manager)? {
+            return Ok(());
+        }
+
+        finished += 1;
 
-            lib_movie
-                .subtitles
-                .dedup_by(|left, right| left.file.path == right.file.path);
+        println!("{}/{} files transfered", finished, len);
+        println!("");
+
+        for subtitle in subtitles.iter_mut().filter(|s| s.source_fingerprint.is_some()) {
+            subtitle.file.fingerprint = fingerprint::file(root_path.join(&subtitle.file.path))?;
         }
 
-        let mut last = Instant::now();
-        loop {
-            if QUIT.load(Ordering::Relaxed) {
-                // received SIGINT or SIGTERM, remove incomplete transfer
-                println!("\nReceived quit signal, cancelling current transfer.");
-                manager.try_cancel();
-                return Ok(());
+        let file = library::File::new(movie_path, movie.fingerprint.clone());
+
+        match &identity.episode {
+            Some(episode) => {
+                let mut series = match library.find_series(title.title_id)? {
+                    Some(series) => series,
+                    None => library::Series::new(
+                        title.title_id,
+                        title.primary_title.clone(),
+                        title.original_title.clone().unwrap_or(title.primary_title.clone()),
+                        title.year,
+                    ),
+                };
+
+                let mut lib_episode = library::Episode::new(file, episode.title_id, episode.season, episode.episode);
+                lib_episode.subtitles = subtitles;
+                lib_episode.video = movie.video.clone();
+                lib_episode.audio = movie.audio.clone();
+                series.episodes.push(lib_episode);
+
+                library.save_series(&series)?;
             }
-            match manager.step() {
-                Ok(Some(transfer)) => {
-                    if last.elapsed() > Duration::from_secs(1) {
-                        print_transfer(transfer);
-                        last = Instant::now();
-                    }
-                }
-                Ok(None) => break,
-                Err(err) => {
-                    // IO error occured
-                    println!("IO error {}, cancelling current transfer.", err);
-                    manager.try_cancel();
-                    return Err(err);
+            None => {
+                if let Some(lib_movie) = lib_movie {
+                    println!("Adding {} as a version of {}", file.path, lib_movie.file.path);
+                    println!();
+
+                    let mut version = library::Version::new(file, movie.release.edition.clone());
+                    version.resolution = movie.resolution();
+                    version.subtitles = subtitles;
+                    version.video = movie.video.clone();
+                    version.audio = movie.audio.clone();
+
+                    library.save_version(&lib_movie.id, &version)?;
+                    continue;
                 }
+
+                let mut lib_movie = library::Movie::new(
+                    file,
+                    title.title_id,
+                    title.primary_title.clone(),
+                    title.original_title.clone().unwrap_or(title.primary_title.clone()),
+                    title.year,
+                );
+                lib_movie.edition = movie.release.edition.clone();
+                lib_movie.resolution = movie.resolution();
+                lib_movie.parts = parts;
+                lib_movie.subtitles = subtitles;
+                lib_movie.extras = extras;
+                lib_movie.video = movie.video.clone();
+                lib_movie.audio = movie.audio.clone();
+
+                library.save_movie(&lib_movie)?;
             }
         }
+    }
 
-        finished += 1;
-
-        println!("Transfer status");
-        println!("===============");
+    if options.upgrade {
+        // the files replaced by an import are kept together in the trash
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let trash_path = config.trash_path().join(now.as_secs().to_string());
 
-        for transfer in manager.transfers() {
-            print_transfer(transfer);
+        for movie in &classified.duplicates {
+            if !upgrade_movie(library, movie, root_path, &trash_path, options)? {
+                return Ok(());
+            }
         }
+    }
 
-        println!("{}/{} files transfered", finished, len);
-        println!("");
+    Ok(())
+}
+
+#[test]
+fn test_upgrade_resolution() -> Result {
+    use std::os::unix::fs::PermissionsExt;
+
+    use crate::index::Index;
+    use crate::io::Fingerprint;
+    use crate::scan::{walk, Scanner};
+
+    /// Remove the test folder even if an assert fails.
+    struct Cleanup(PathBuf);
 
-        library.save_movie(&lib_movie)?;
+    impl Drop for Cleanup {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
     }
 
+    let dir = std::env::temp_dir().join(format!("mero-test-import-upgrade-{}", std::process::id()));
+    let _cleanup = Cleanup(dir.clone());
+    fs::create_dir_all(dir.join("bin"))?;
+    fs::create_dir_all(dir.join("downloads"))?;
+    fs::create_dir_all(dir.join("library/Heat.(1995)"))?;
+
+    let ffprobe = dir.join("bin/ffprobe");
+    fs::write(
+        &ffprobe,
+        r#"#!/bin/sh
+hd='{"codec_type": "video", "index": 0, "codec_name": "h264", "width": 1920, "height": 800}'
+uhd='{"codec_type": "video", "index": 0, "codec_name": "hevc", "width": 3840, "height": 1600}'
+for path; do :; done
+case "$(basename "$path")" in
+    "Heat.(1995).mkv") echo "{\"format\": {\"duration\": \"6000.0\"}, \"streams\": [$hd]}" ;;
+    Heat.1995.2160p.mkv) echo "{\"format\": {\"duration\": \"6000.0\"}, \"streams\": [$uhd]}" ;;
+    *) exit 1 ;;
+esac
+"#,
+    )?;
+    fs::set_permissions(&ffprobe, fs::Permissions::from_mode(0o755))?;
+
+    fs::write(dir.join("library/Heat.(1995)/Heat.(1995).mkv"), "1080p")?;
+    fs::write(dir.join("downloads/Heat.1995.2160p.mkv"), "2160p")?;
+
+    let mut library = Library::open(Path::new(":memory:"))?;
+    let mut movie = library::Movie::new(
+        library::File::new(
+            RelPath::from_string("Heat.(1995)/Heat.(1995).mkv".into()),
+            Fingerprint::from_string("a".into()),
+        ),
+        TitleId::new(113_277),
+        "Heat",
+        "Heat",
+        1995,
+    );
+    movie.resolution = Some(1080);
+    library.save_movie(&movie)?;
+
+    let index = Index::from_titles(&[(113_277, "Heat", 1995)]);
+    let root = walk(
+        dir.join("downloads"),
+        &Default::default(),
+        &Default::default(),
+        Default::default(),
+    )?;
+    let mut scanner = Scanner::new();
+    scanner.set_quiet_period(Duration::from_secs(0));
+    let movies = scanner.scan_movies(&root, &index)?.movies;
+
+    let options = ImportOptions {
+        upgrade: true,
+        ffprobe: Some(ffprobe),
+        ..Default::default()
+    };
+    import_movies(&Config::new(dir.join("library")), movies, &mut library, &options)?;
+
+    // the 2160p copy replaces the 1080p one instead of being added as a version
+    let mut upgraded = library.all_movies()?.remove(0);
+    library.load_versions(&mut upgraded)?;
+    assert_eq!(upgraded.id, movie.id);
+    assert_eq!(upgraded.resolution, Some(2160));
+    assert!(upgraded.versions.is_empty());
+    assert_eq!(
+        fs::read_to_string(dir.join("library/Heat.(1995)/Heat.(1995).mkv"))?,
+        "2160p"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_assign_edition() -> Result {
+    use crate::index::Index;
+    use crate::scan::{walk, Scanner};
+
+    let dir = std::env::temp_dir().join(format!("mero-test-assign-edition-{}", std::process::id()));
+    fs::create_dir_all(dir.join("heat"))?;
+    fs::create_dir_all(dir.join("alien"))?;
+    fs::write(dir.join("heat/Heat.1995.1080p.mkv"), "1080p")?;
+    fs::write(dir.join("heat/Heat.1995.2160p.mkv"), "2160p")?;
+    fs::write(dir.join("alien/Alien.1979.mkv"), "alien")?;
+
+    let index = Index::from_titles(&[(113_277, "Heat", 1995), (78_748, "Alien", 1979)]);
+    let scan = |path: &Path| -> Result<Vec<MovieFile>> {
+        let root = walk(path, &Default::default(), &Default::default(), Default::default())?;
+        let mut scanner = Scanner::new();
+        scanner.set_quiet_period(Duration::from_secs(0));
+        Ok(scanner.scan_movies(&root, &index)?.movies)
+    };
+
+    // every copy of a single movie gets the edition
+    let mut movies = scan(&dir.join("heat"))?;
+    assert!(assign_edition(&mut movies, "Director's Cut"));
+    assert!(movies
+        .iter()
+        .all(|m| m.release.edition.as_deref() == Some("Director's Cut")));
+
+    // a folder of several movies is refused
+    let mut movies = scan(&dir)?;
+    assert_eq!(movies.len(), 3);
+    assert!(!assign_edition(&mut movies, "Director's Cut"));
+    assert!(movies.iter().all(|m| m.release.edition.is_none()));
+
+    fs::remove_dir_all(&dir)?;
     Ok(())
 }
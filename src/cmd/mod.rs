@@ -6,16 +6,18 @@ mod query;
 mod rehash;
 mod scan;
 mod stats;
+mod subs;
 mod sync;
 mod view;
 
-pub use self::ignore::{cmd_ignore_add, cmd_ignore_list, cmd_ignore_remove};
+pub use self::ignore::{cmd_ignore_add, cmd_ignore_check, cmd_ignore_list, cmd_ignore_remove};
 pub use self::images::cmd_images;
-pub use self::import::cmd_import;
+pub use self::import::{cmd_import, ImportOptions};
 pub use self::init::cmd_init;
 pub use self::query::cmd_query;
 pub use self::rehash::cmd_rehash;
-pub use self::scan::cmd_scan;
+pub use self::scan::{cmd_scan, CacheMode, ScanOptions};
 pub use self::stats::cmd_stats;
+pub use self::subs::{cmd_subs_shift, parse_offset};
 pub use self::sync::cmd_sync;
 pub use self::view::cmd_view;
@@ -1,5 +1,6 @@
 use crate::error::Result;
 use crate::library::Library;
+use crate::scan::{parse_lang_tag, AudioInfo, VideoInfo};
 
 pub fn cmd_query(
     library: &Library,
@@ -7,48 +8,130 @@ pub fn cmd_query(
     year: Option<u16>,
     mut year_gte: Option<u16>,
     mut year_lte: Option<u16>,
+    resolution: Option<u32>,
+    audio_lang: Option<String>,
 ) -> Result {
     if let Some(year) = year {
         year_gte = Some(year);
         year_lte = Some(year);
     }
 
+    let audio_lang = match audio_lang {
+        Some(lang) => match parse_lang_tag(&lang) {
+            Some(lang) => Some(lang),
+            None => {
+                println!("Unknown language {}", lang);
+                return Ok(());
+            }
+        },
+        None => None,
+    };
+    let has_stream_filter = resolution.is_some() || audio_lang.is_some();
+
+    let is_stream_match = |video: &[VideoInfo], audio: &[AudioInfo]| {
+        if let Some(resolution) = resolution {
+            if !video.iter().any(|v| v.resolution() == resolution) {
+                return false;
+            }
+        }
+
+        if let Some(audio_lang) = audio_lang {
+            if !audio.iter().any(|a| a.lang.as_deref() == Some(audio_lang)) {
+                return false;
+            }
+        }
+
+        true
+    };
+
     let title = title.map(|t| t.to_lowercase());
 
-    let mut movies = library.all_movies()?;
-    movies.retain(|m| {
+    let is_match = |primary_title: &str, original_title: &str, year: u16| {
         if let Some(title) = &title {
-            if !m.primary_title.to_lowercase().contains(&title[..])
-                && !m.original_title.to_lowercase().contains(&title[..])
+            if !primary_title.to_lowercase().contains(&title[..]) && !original_title.to_lowercase().contains(&title[..])
             {
                 return false;
             }
         }
-        if let Some(year) = year_gte {
-            if m.year < year {
+        if let Some(year_gte) = year_gte {
+            if year < year_gte {
                 return false;
             }
         }
 
-        if let Some(year) = year_lte {
-            if m.year > year {
+        if let Some(year_lte) = year_lte {
+            if year > year_lte {
                 return false;
             }
         }
 
         true
+    };
+
+    let mut movies = library.all_movies()?;
+    movies.retain(|m| is_match(&m.primary_title, &m.original_title, m.year));
+    for m in movies.iter_mut() {
+        library.load_streams(m)?;
+        library.load_versions(m)?;
+    }
+    // movies are kept if one of their versions matches the streams filters
+    movies.retain(|m| {
+        is_stream_match(&m.video, &m.audio) || m.versions.iter().any(|v| is_stream_match(&v.video, &v.audio))
     });
 
+    let mut series = library.all_series()?;
+    series.retain(|s| is_match(&s.primary_title, &s.original_title, s.year));
+
     movies.sort_by_key(|m| (m.year, m.primary_title.clone())); // TODO remove clone
 
     for m in &movies {
         println!("Primary title: {}", m.primary_title);
         println!("Year: {}", m.year);
         println!("URL: https://imdb.com/title/{}/", m.imdb_id.full());
+        if let Some(edition) = &m.edition {
+            println!("Edition: {}", edition);
+        }
+        for video in &m.video {
+            println!("Video: {}", video);
+        }
+        for audio in &m.audio {
+            println!("Audio: {}", audio);
+        }
+        for v in &m.versions {
+            println!("Version: {}", v.file.path);
+            for video in &v.video {
+                println!("  Video: {}", video);
+            }
+            for audio in &v.audio {
+                println!("  Audio: {}", audio);
+            }
+        }
+        println!();
+    }
+
+    // series are kept with the episodes matching the streams filters
+    for s in series.iter_mut() {
+        library.load_episodes(s)?;
+        for e in s.episodes.iter_mut() {
+            library.load_episode_streams(e)?;
+        }
+        s.episodes.retain(|e| is_stream_match(&e.video, &e.audio));
+    }
+    series.retain(|s| !has_stream_filter || !s.episodes.is_empty());
+
+    series.sort_by_key(|s| (s.year, s.primary_title.clone()));
+
+    for s in &series {
+        println!("Primary title: {}", s.primary_title);
+        println!("Year: {}", s.year);
+        println!("URL: https://imdb.com/title/{}/", s.imdb_id.full());
+        for e in &s.episodes {
+            println!("S{:02}E{:02}: {}", e.season, e.number, e.file.path);
+        }
         println!();
     }
 
-    println!("{} results.", movies.len());
+    println!("{} results.", movies.len() + series.len());
 
     Ok(())
 }
@@ -1,20 +1,44 @@
+use std::path::Path;
+
 use crate::config::Config;
 use crate::error::Result;
 use crate::io::fingerprint;
-use crate::library::Library;
+use crate::library::{File, Library};
+
+/// Fingerprint a file of the library again, saving its new fingerprint if it changed.
+fn rehash_file(root_path: &Path, library: &Library, kind: &str, file: &mut File) -> Result {
+    println!("Checking {} {}", kind, file.path);
+    let fp = fingerprint::file(root_path.join(&file.path))?;
+
+    if fp != file.fingerprint {
+        println!("{:?} => {:?}", file.fingerprint, fp);
+        println!();
+        file.fingerprint = fp;
+        library.save_file(file)?;
+    }
+
+    Ok(())
+}
 
 pub fn cmd_rehash(config: Config, library: &mut Library) -> Result {
     let root_path = config.root_path();
 
     for mut movie in library.all_movies()? {
-        println!("Checking movie {}", movie.file.path);
-        let fp = fingerprint::file(root_path.join(&movie.file.path))?;
-
-        if fp != movie.file.fingerprint {
-            println!("{:?} => {:?}", movie.file.fingerprint, fp);
-            println!();
-            movie.file.fingerprint = fp;
-            library.save_file(&movie.file)?;
+        rehash_file(root_path, library, "movie", &mut movie.file)?;
+
+        library.load_parts(&mut movie)?;
+        for part in movie.parts.iter_mut() {
+            rehash_file(root_path, library, "part", &mut part.file)?;
+        }
+
+        library.load_extras(&mut movie)?;
+        for extra in movie.extras.iter_mut() {
+            rehash_file(root_path, library, "extra", &mut extra.file)?;
+        }
+
+        library.load_versions(&mut movie)?;
+        for version in movie.versions.iter_mut() {
+            rehash_file(root_path, library, "version", &mut version.file)?;
         }
 
         // for sub in movie.subtitles.iter_mut() {
@@ -29,5 +53,53 @@ pub fn cmd_rehash(config: Config, library: &mut Library) -> Result {
         // }
     }
 
+    for mut series in library.all_series()? {
+        library.load_episodes(&mut series)?;
+        for episode in series.episodes.iter_mut() {
+            rehash_file(root_path, library, "episode", &mut episode.file)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_rehash_parts_and_episodes() -> Result {
+    use std::fs;
+
+    use crate::index::TitleId;
+    use crate::io::Fingerprint;
+    use crate::library::{Episode, Movie, Part, Series};
+    use crate::scan::RelPath;
+
+    let dir = std::env::temp_dir().join("mero-test-rehash");
+    fs::create_dir_all(&dir)?;
+    for name in &["part1.avi", "part2.avi", "S01E01.mkv"] {
+        fs::write(dir.join(name), name)?;
+    }
+    let file = |name: &str| {
+        File::new(
+            RelPath::from_string(name.into()),
+            Fingerprint::from_string(format!("old {}", name)),
+        )
+    };
+
+    let mut library = Library::open(Path::new(":memory:"))?;
+    let mut movie = Movie::new(file("part1.avi"), TitleId::new(113_277), "Heat", "Heat", 1995);
+    movie.parts.push(Part::new(file("part2.avi"), 2));
+    library.save_movie(&movie)?;
+    let mut series = Series::new(TitleId::new(903_747), "Breaking Bad", "Breaking Bad", 2008);
+    series
+        .episodes
+        .push(Episode::new(file("S01E01.mkv"), TitleId::new(959_621), 1, 1));
+    library.save_series(&series)?;
+
+    cmd_rehash(Config::new(&dir), &mut library)?;
+
+    for name in &["part2.avi", "S01E01.mkv"] {
+        assert!(library.has_fingerprint(&fingerprint::bytes(name.as_bytes()))?);
+    }
+
+    fs::remove_dir_all(&dir)?;
     Ok(())
 }
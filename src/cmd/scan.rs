@@ -1,4 +1,6 @@
+use std::fs;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
 
@@ -6,19 +8,46 @@ use crate::config::Config;
 use crate::error::Result;
 use crate::index::Index;
 use crate::local_storage::LocalStorage;
-use crate::scan::{walk, MovieFile, Scanner};
+use crate::scan::{os_path, walk, IgnoreRules, LinkFile, MovieFile, PathSize, ScanCache, Scanner, SymlinkPolicy};
 use crate::utils;
 
+/// How the scan cache is used.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CacheMode {
+    Enabled,
+    /// Forget the cached files, and fill the cache again.
+    Clear,
+    Disabled,
+}
+
+/// Options of the scan command.
+#[derive(Debug)]
+pub struct ScanOptions {
+    /// Number of files fingerprinted and analyzed at once.
+    pub jobs: usize,
+    pub cache: CacheMode,
+    pub symlinks: SymlinkPolicy,
+    /// Files modified less than this long ago are considered to be still downloading.
+    pub quiet_period: Duration,
+    /// Probe every movie with ffprobe to find the broken ones.
+    pub probe: bool,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Report {
+    #[serde(with = "os_path")]
     pub path: PathBuf,
     pub movies: Vec<MovieFile>,
+    pub unassigned_subtitles: Vec<PathSize>,
+    pub links: Vec<LinkFile>,
 }
 
 impl Report {
     pub fn new(path: impl Into<PathBuf>) -> Report {
         Report {
             movies: vec![],
+            unassigned_subtitles: vec![],
+            links: vec![],
             path: path.into(),
         }
     }
@@ -35,18 +64,41 @@ impl Report {
 pub fn cmd_scan(
     import_path: impl AsRef<Path>,
     save_path: impl Into<Option<PathBuf>>,
-    _config: Config,
+    options: ScanOptions,
+    config: Config,
     index: &Index,
     local_storage: &LocalStorage,
 ) -> Result {
     let import_path = import_path.as_ref();
     println!("Scanning import path {}", import_path.display());
 
-    let root = walk(import_path, &local_storage.ignored)?;
-    let mut scanner = Scanner::new();
+    let mut rules = IgnoreRules::new();
+    rules.add_file(config.ignore_file_path(), std::path::absolute(import_path)?)?;
+    let root = walk(import_path, &local_storage.ignored, &rules, options.symlinks)?;
+    let mut scanner = Scanner::with_jobs(options.jobs);
+    scanner.set_quiet_period(options.quiet_period);
+    scanner.set_probe(options.probe);
+
+    if options.cache != CacheMode::Disabled {
+        let mut scan_cache = ScanCache::open(config.scan_cache_path())?;
+        if options.cache == CacheMode::Clear {
+            scan_cache.clear();
+        }
+        scan_cache.set_index_mtime(fs::metadata(config.index_path()).and_then(|m| m.modified()).ok());
+        scanner.set_cache(scan_cache);
+    }
 
     let mut report = Report::new(import_path);
-    report.movies = scanner.scan_movies(&root, index)?;
+    let results = scanner.scan_movies(&root, index)?;
+    report.movies = results.movies;
+    report.unassigned_subtitles = results.unassigned_subtitles;
+    report.links = results.links;
+
+    if options.cache != CacheMode::Disabled {
+        let mut scan_cache = scanner.take_cache();
+        scan_cache.save(config.scan_cache_path())?;
+        println!("Scan cache: {}", scan_cache);
+    }
 
     let save_path = save_path.into().unwrap_or(PathBuf::from("scan-report.mero"));
     report.save(save_path)?;
@@ -4,5 +4,22 @@ use crate::library::Library;
 pub fn cmd_stats(library: &Library) -> Result {
     println!("There are {} movies in the library.", library.all_movies()?.len());
 
+    let mut series = library.all_series()?;
+    for s in series.iter_mut() {
+        library.load_episodes(s)?;
+    }
+    series.sort_by_key(|s| (s.primary_title.clone(), s.year));
+
+    let episodes: usize = series.iter().map(|s| s.episodes.len()).sum();
+    println!(
+        "There are {} episodes of {} shows in the library.",
+        episodes,
+        series.len()
+    );
+
+    for s in &series {
+        println!("{} ({}): {} episodes", s.primary_title, s.year, s.episodes.len());
+    }
+
     Ok(())
 }
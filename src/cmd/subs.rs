@@ -0,0 +1,366 @@
+use std::fs;
+use std::mem;
+use std::path::Path;
+
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE};
+use subparse::timetypes::{TimeDelta, TimePoint};
+use subparse::SubtitleFormat;
+
+use crate::config::Config;
+use crate::error::Result;
+use crate::index::TitleId;
+use crate::io::fingerprint;
+use crate::library::{Episode, File, Library, Movie, Subtitle, Version};
+use crate::scan::parse_lang_tag;
+use crate::service::ffprobe;
+
+/// Parse an offset such as `1.5s`, `-500ms` or `2` into milliseconds. Offsets without a unit are in seconds.
+pub fn parse_offset(offset: &str) -> std::result::Result<i64, String> {
+    let offset = offset.trim();
+    let (value, scale) = if let Some(value) = offset.strip_suffix("ms") {
+        (value, 1.0)
+    } else if let Some(value) = offset.strip_suffix('s') {
+        (value, 1000.0)
+    } else {
+        (offset, 1000.0)
+    };
+
+    match value.trim().parse::<f64>() {
+        Ok(value) if value.is_finite() => Ok((value * scale).round() as i64),
+        _ => Err(format!(
+            "invalid offset {}, expected a duration such as 1.5s or -500ms",
+            offset
+        )),
+    }
+}
+
+/// Copy of a title in the library whose subtitles are shifted.
+enum Target {
+    Movie(Movie),
+    Version(Version),
+    Episode(Episode),
+}
+
+impl Target {
+    fn file(&self) -> &File {
+        match self {
+            Target::Movie(movie) => &movie.file,
+            Target::Version(version) => &version.file,
+            Target::Episode(episode) => &episode.file,
+        }
+    }
+
+    fn take_subtitles(&mut self) -> Vec<Subtitle> {
+        match self {
+            Target::Movie(movie) => mem::take(&mut movie.subtitles),
+            Target::Version(version) => mem::take(&mut version.subtitles),
+            Target::Episode(episode) => mem::take(&mut episode.subtitles),
+        }
+    }
+
+    fn save_subtitle(&self, library: &Library, subtitle: &Subtitle) -> Result<()> {
+        match self {
+            Target::Movie(movie) => library.save_subtitle(&movie.id, subtitle),
+            Target::Version(version) => library.save_version_subtitle(&version.id, subtitle),
+            Target::Episode(episode) => library.save_episode_subtitle(&episode.id, subtitle),
+        }
+    }
+}
+
+/// Find a movie, a version or an episode of the library by its IMDB id or the path of its file, with its subtitles.
+///
+/// Versions share the IMDB id of their movie, they are only found by their path.
+fn find_target(config: &Config, library: &Library, target: &str) -> Result<Option<Target>> {
+    let path = Path::new(target);
+    let path = path.strip_prefix(config.root_path()).unwrap_or(path);
+    let matches = |imdb_id: TitleId, file: &File| imdb_id.full() == target || file.path.as_path() == path;
+
+    for mut movie in library.all_movies()? {
+        if matches(movie.imdb_id, &movie.file) {
+            library.load_subtitles(&mut movie)?;
+            return Ok(Some(Target::Movie(movie)));
+        }
+
+        library.load_versions(&mut movie)?;
+        if let Some(mut version) = movie.versions.into_iter().find(|v| v.file.path.as_path() == path) {
+            library.load_version_subtitles(&mut version)?;
+            return Ok(Some(Target::Version(version)));
+        }
+    }
+
+    for mut series in library.all_series()? {
+        library.load_episodes(&mut series)?;
+        if let Some(mut episode) = series.episodes.into_iter().find(|e| matches(e.imdb_id, &e.file)) {
+            library.load_episode_subtitles(&mut episode)?;
+            return Ok(Some(Target::Episode(episode)));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Encode a text with an optional byte order mark, `Encoding::encode` writes UTF-16 texts as UTF-8.
+fn encode_text(text: &str, encoding: &'static Encoding, bom: bool) -> Vec<u8> {
+    let mut data = vec![];
+    if encoding == UTF_16LE {
+        if bom {
+            data.extend_from_slice(b"\xFF\xFE");
+        }
+        data.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+    } else if encoding == UTF_16BE {
+        if bom {
+            data.extend_from_slice(b"\xFE\xFF");
+        }
+        data.extend(text.encode_utf16().flat_map(u16::to_be_bytes));
+    } else {
+        if bom {
+            data.extend_from_slice(b"\xEF\xBB\xBF");
+        }
+        data.extend_from_slice(&encoding.encode(text).0);
+    }
+    data
+}
+
+/// Shift the timings of a subtitle file by an offset in milliseconds.
+///
+/// Cues shifted before the start of the movie are moved to the start.
+fn shift_file(path: &Path, format: SubtitleFormat, frame_rate: f64, offset: i64) -> Result {
+    let data = fs::read(path)?;
+
+    // a byte order mark names the encoding and is written back
+    let (encoding, bom) = match Encoding::for_bom(&data) {
+        Some((encoding, _)) => (encoding, true),
+        None => {
+            let (charset, _, _) = chardet::detect(&data);
+            let encoding = Encoding::for_label(chardet::charset2encoding(&charset).as_bytes());
+            (encoding.unwrap_or(encoding_rs::UTF_8), false)
+        }
+    };
+
+    let mut sub = subparse::parse_bytes(format, &data, encoding, frame_rate)?;
+    let mut entries = sub.get_subtitle_entries()?;
+    for entry in entries.iter_mut() {
+        entry.timespan += TimeDelta::from_msecs(offset);
+        entry.timespan.start = entry.timespan.start.max(TimePoint::from_msecs(0));
+        entry.timespan.end = entry.timespan.end.max(TimePoint::from_msecs(0));
+    }
+    sub.update_subtitle_entries(&entries)?;
+
+    // write the subtitle back in its original encoding
+    let text = String::from_utf8_lossy(&sub.to_data()?).into_owned();
+    fs::write(path, encode_text(text.trim_start_matches('\u{FEFF}'), encoding, bom))?;
+
+    Ok(())
+}
+
+pub fn cmd_subs_shift(config: Config, library: &mut Library, movie: String, lang: String, offset: i64) -> Result {
+    let root_path = config.root_path();
+
+    let mut target = match find_target(&config, library, &movie)? {
+        Some(target) => target,
+        None => {
+            println!("No movie, version or episode in the library matches {}", movie);
+            return Ok(());
+        }
+    };
+    let lang = match parse_lang_tag(&lang) {
+        Some(lang) => lang,
+        None => {
+            println!("Unknown language {}", lang);
+            return Ok(());
+        }
+    };
+
+    let mut subtitles = target.take_subtitles();
+    subtitles.retain(|s| s.lang == lang);
+
+    if subtitles.is_empty() {
+        println!("No {} subtitle for {}", lang, target.file().path);
+        return Ok(());
+    }
+
+    for subtitle in subtitles.iter_mut() {
+        let path = root_path.join(&subtitle.file.path);
+
+        let format = match subtitle.format.as_str() {
+            "srt" => SubtitleFormat::SubRip,
+            "ssa" => SubtitleFormat::SubStationAlpha,
+            "microdvd" => SubtitleFormat::MicroDVD,
+            format => {
+                println!(
+                    "Unable to shift {}, {} subtitles are not supported",
+                    subtitle.file.path, format
+                );
+                continue;
+            }
+        };
+
+        // timings of frame-based subtitles are converted with the frame rate of the movie
+        let frame_rate = if format == SubtitleFormat::MicroDVD {
+            let info = match ffprobe::scan(root_path.join(&target.file().path)) {
+                Ok(info) => info,
+                Err(err) => {
+                    println!(
+                        "Unable to shift {}, the movie could not be probed: {}",
+                        subtitle.file.path, err
+                    );
+                    continue;
+                }
+            };
+            match info.video.iter().filter_map(|v| v.frame_rate()).next() {
+                Some(frame_rate) => frame_rate,
+                None => {
+                    println!(
+                        "Unable to shift {}, the frame rate of the movie is unknown",
+                        subtitle.file.path
+                    );
+                    continue;
+                }
+            }
+        } else {
+            0.0
+        };
+
+        println!("Shifting {} by {} ms", subtitle.file.path, offset);
+        shift_file(&path, format, frame_rate, offset)?;
+
+        // keep the fingerprint of the imported file so that it is not imported again
+        let fingerprint = fingerprint::file(&path)?;
+        if subtitle.source_fingerprint.is_none() {
+            subtitle.source_fingerprint = Some(subtitle.file.fingerprint.clone());
+        }
+        subtitle.file.fingerprint = fingerprint;
+        target.save_subtitle(library, subtitle)?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_offset() {
+    assert_eq!(parse_offset("1.5s"), Ok(1500));
+    assert_eq!(parse_offset("-500ms"), Ok(-500));
+    assert_eq!(parse_offset("2"), Ok(2000));
+    assert!(parse_offset("soon").is_err());
+}
+
+#[test]
+fn test_shift_file() -> Result {
+    let dir = std::env::temp_dir().join("mero-test-shift");
+    fs::create_dir_all(&dir)?;
+    let path = dir.join("movie.en.srt");
+    fs::write(
+        &path,
+        "1\n00:00:01,000 --> 00:00:02,000\nHello\n\n2\n00:00:10,000 --> 00:00:12,500\nWorld\n",
+    )?;
+
+    shift_file(&path, SubtitleFormat::SubRip, 0.0, -1500)?;
+
+    let sub = subparse::parse_str(SubtitleFormat::SubRip, &fs::read_to_string(&path)?, 0.0)?;
+    let timings: Vec<_> = sub
+        .get_subtitle_entries()?
+        .iter()
+        .map(|e| (e.timespan.start.msecs(), e.timespan.end.msecs()))
+        .collect();
+    assert_eq!(timings, vec![(0, 500), (8500, 11000)]);
+
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_shift_utf16_file() -> Result {
+    let dir = std::env::temp_dir().join("mero-test-shift-utf16");
+    fs::create_dir_all(&dir)?;
+    let path = dir.join("movie.fr.srt");
+    fs::write(
+        &path,
+        encode_text("1\n00:00:01,000 --> 00:00:02,000\nCafé\n", UTF_16LE, true),
+    )?;
+
+    shift_file(&path, SubtitleFormat::SubRip, 0.0, 1000)?;
+
+    let data = fs::read(&path)?;
+    assert!(data.starts_with(b"\xFF\xFE"));
+    let (text, _, _) = UTF_16LE.decode(&data);
+    assert!(text.contains("00:00:02,000 --> 00:00:03,000"), "{}", text);
+    assert!(text.contains("Café"), "{}", text);
+
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_shift_version_and_episode() -> Result {
+    use crate::io::Fingerprint;
+    use crate::library::Series;
+    use crate::scan::RelPath;
+
+    let file = |path: &str, fp: &str| File::new(RelPath::from_string(path.into()), Fingerprint::from_string(fp.into()));
+    let srt = |text: &str| format!("1\n00:00:01,000 --> 00:00:02,000\n{}\n", text);
+
+    let dir = std::env::temp_dir().join(format!("mero-test-shift-targets-{}", std::process::id()));
+    fs::create_dir_all(dir.join("Heat.(1995)"))?;
+    fs::create_dir_all(dir.join("Breaking.Bad.(2008)/Season 01"))?;
+    fs::write(dir.join("Heat.(1995)/Heat.(1995) - 2160p.en.srt"), srt("Heat"))?;
+    fs::write(
+        dir.join("Breaking.Bad.(2008)/Season 01/Breaking.Bad.S01E01.en.srt"),
+        srt("Breaking Bad"),
+    )?;
+
+    let mut library = Library::open(Path::new(":memory:"))?;
+    let mut movie = Movie::new(
+        file("Heat.(1995)/Heat.(1995).mkv", "a"),
+        TitleId::new(113_277),
+        "Heat",
+        "Heat",
+        1995,
+    );
+    let mut version = Version::new(file("Heat.(1995)/Heat.(1995) - 2160p.mkv", "b"), None);
+    version.subtitles.push(Subtitle::new(
+        file("Heat.(1995)/Heat.(1995) - 2160p.en.srt", "c"),
+        "eng",
+        "srt",
+    ));
+    movie.versions.push(version);
+    library.save_movie(&movie)?;
+
+    let mut series = Series::new(TitleId::new(903_747), "Breaking Bad", "Breaking Bad", 2008);
+    let mut episode = Episode::new(
+        file("Breaking.Bad.(2008)/Season 01/Breaking.Bad.S01E01.mkv", "d"),
+        TitleId::new(959_621),
+        1,
+        1,
+    );
+    episode.subtitles.push(Subtitle::new(
+        file("Breaking.Bad.(2008)/Season 01/Breaking.Bad.S01E01.en.srt", "e"),
+        "eng",
+        "srt",
+    ));
+    series.episodes.push(episode);
+    library.save_series(&series)?;
+
+    let config = Config::new(&dir);
+    cmd_subs_shift(
+        Config::new(&dir),
+        &mut library,
+        "Heat.(1995)/Heat.(1995) - 2160p.mkv".into(),
+        "en".into(),
+        1000,
+    )?;
+    cmd_subs_shift(Config::new(&dir), &mut library, "tt0959621".into(), "en".into(), 1000)?;
+
+    // both subtitles are shifted and keep the fingerprint of their imported file
+    for (target, fingerprint) in &[("Heat.(1995)/Heat.(1995) - 2160p.mkv", "c"), ("tt0959621", "e")] {
+        let mut target = find_target(&config, &library, target)?.expect("target should be found");
+        let subtitle = target.take_subtitles().remove(0);
+        assert!(fs::read_to_string(dir.join(&subtitle.file.path))?.contains("00:00:02,000 --> 00:00:03,000"));
+        assert_eq!(
+            subtitle.source_fingerprint,
+            Some(Fingerprint::from_string(fingerprint.to_string()))
+        );
+    }
+
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
@@ -5,15 +5,47 @@ use crate::library::Library;
 pub fn cmd_sync(config: Config, library: &mut Library) -> Result {
     let root_path = config.root_path();
 
-    for movie in library.all_movies()? {
+    for mut movie in library.all_movies()? {
         let exists = root_path.join(&movie.file.path).exists();
         if !exists {
-            println!("Removing movie {}", movie.file.path);
-            library.delete_movie(&movie)?;
+            library.load_parts(&mut movie)?;
+            library.load_extras(&mut movie)?;
+            library.load_versions(&mut movie)?;
+            for version in movie.versions.iter_mut() {
+                library.load_version_subtitles(version)?;
+            }
+
+            // a version left in the library takes the place of the movie
+            let promoted = movie
+                .versions
+                .iter()
+                .position(|version| root_path.join(&version.file.path).exists());
+            match promoted {
+                Some(index) => {
+                    println!(
+                        "Replacing movie {} by its version {}",
+                        movie.file.path, movie.versions[index].file.path
+                    );
+                    library.promote_version(&mut movie, index)?;
+                }
+                None => {
+                    println!("Removing movie {}", movie.file.path);
+                    library.delete_movie(&movie)?;
+                }
+            }
         }
     }
 
     for mut movie in library.all_movies()? {
+        library.load_parts(&mut movie)?;
+        for part in &movie.parts {
+            let exists = root_path.join(&part.file.path).exists();
+            if !exists {
+                println!("Removing part {}", part.file.path);
+                library.delete_part(&movie.id, part)?;
+            }
+        }
+
         library.load_subtitles(&mut movie)?;
         for subtitle in &movie.subtitles {
             let exists = root_path.join(&subtitle.file.path).exists();
@@ -22,7 +54,188 @@ pub fn cmd_sync(config: Config, library: &mut Library) -> Result {
                 library.delete_subtitle(&movie.id, subtitle)?;
             }
         }
+
+        library.load_extras(&mut movie)?;
+        for extra in &movie.extras {
+            let exists = root_path.join(&extra.file.path).exists();
+            if !exists {
+                println!("Removing extra {}", extra.file.path);
+                library.delete_extra(&movie.id, extra)?;
+            }
+        }
+
+        library.load_versions(&mut movie)?;
+        for mut version in movie.versions {
+            library.load_version_subtitles(&mut version)?;
+
+            let exists = root_path.join(&version.file.path).exists();
+            if !exists {
+                println!("Removing version {}", version.file.path);
+                library.delete_version(&version)?;
+                continue;
+            }
+
+            for subtitle in &version.subtitles {
+                let exists = root_path.join(&subtitle.file.path).exists();
+                if !exists {
+                    println!("Removing subtitle {}", subtitle.file.path);
+                    library.delete_version_subtitle(&version.id, subtitle)?;
+                }
+            }
+        }
+    }
+
+    for mut series in library.all_series()? {
+        library.load_episodes(&mut series)?;
+        for mut episode in series.episodes {
+            library.load_episode_subtitles(&mut episode)?;
+
+            let exists = root_path.join(&episode.file.path).exists();
+            if !exists {
+                println!("Removing episode {}", episode.file.path);
+                library.delete_episode(&episode)?;
+                continue;
+            }
+
+            for subtitle in &episode.subtitles {
+                let exists = root_path.join(&subtitle.file.path).exists();
+                if !exists {
+                    println!("Removing subtitle {}", subtitle.file.path);
+                    library.delete_episode_subtitle(&episode.id, subtitle)?;
+                }
+            }
+        }
     }
 
     Ok(())
 }
+
+#[test]
+fn test_sync_episodes() -> Result {
+    use std::fs;
+    use std::path::Path;
+
+    use crate::index::TitleId;
+    use crate::io::Fingerprint;
+    use crate::library::{Episode, File, Series};
+    use crate::scan::RelPath;
+
+    let dir = std::env::temp_dir().join("mero-test-sync-episodes");
+    fs::create_dir_all(dir.join("Breaking.Bad.(2008)/Season.01"))?;
+    fs::write(dir.join("Breaking.Bad.(2008)/Season.01/S01E01.mkv"), "")?;
+
+    let mut library = Library::open(Path::new(":memory:"))?;
+    let mut series = Series::new(TitleId::new(903_747), "Breaking Bad", "Breaking Bad", 2008);
+    for &(number, fp) in &[(1u16, "a"), (2, "b")] {
+        series.episodes.push(Episode::new(
+            File::new(
+                RelPath::from_string(format!("Breaking.Bad.(2008)/Season.01/S01E0{}.mkv", number)),
+                Fingerprint::from_string(fp.to_string()),
+            ),
+            TitleId::new(959_620 + u32::from(number)),
+            1,
+            number,
+        ));
+    }
+    library.save_series(&series)?;
+
+    cmd_sync(Config::new(&dir), &mut library)?;
+
+    let mut series = library.all_series()?.remove(0);
+    library.load_episodes(&mut series)?;
+    let numbers: Vec<_> = series.episodes.iter().map(|e| e.number).collect();
+    assert_eq!(numbers, vec![1]);
+
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_sync_missing_movie() -> Result {
+    use std::path::Path;
+
+    use crate::index::TitleId;
+    use crate::io::Fingerprint;
+    use crate::library::{File, Movie, Part};
+    use crate::scan::RelPath;
+
+    let file = |path: &str, fp: &str| File::new(RelPath::from_string(path.into()), Fingerprint::from_string(fp.into()));
+
+    let mut library = Library::open(Path::new(":memory:"))?;
+    let mut movie = Movie::new(
+        file("Heat.(1995)/Heat (1995) - part1.avi", "a"),
+        TitleId::new(113_277),
+        "Heat",
+        "Heat",
+        1995,
+    );
+    movie
+        .parts
+        .push(Part::new(file("Heat.(1995)/Heat (1995) - part2.avi", "b"), 2));
+    library.save_movie(&movie)?;
+
+    cmd_sync(
+        Config::new(std::env::temp_dir().join("mero-test-sync-missing")),
+        &mut library,
+    )?;
+
+    // the parts are deleted with the movie, so that the same release can be imported again
+    assert!(library.all_movies()?.is_empty());
+    assert!(!library.has_fingerprint(&Fingerprint::from_string("b".into()))?);
+    Ok(())
+}
+
+#[test]
+fn test_sync_promote_version() -> Result {
+    use std::fs;
+    use std::path::Path;
+
+    use crate::index::TitleId;
+    use crate::io::Fingerprint;
+    use crate::library::{File, Movie, Subtitle, Version};
+    use crate::scan::RelPath;
+
+    let file = |path: &str, fp: &str| File::new(RelPath::from_string(path.into()), Fingerprint::from_string(fp.into()));
+
+    let dir = std::env::temp_dir().join("mero-test-sync-promote");
+    fs::create_dir_all(dir.join("Heat.(1995)"))?;
+    fs::write(dir.join("Heat.(1995)/Heat.(1995) - 2160p.mkv"), "")?;
+    fs::write(dir.join("Heat.(1995)/Heat.(1995) - 2160p.en.srt"), "")?;
+
+    let mut library = Library::open(Path::new(":memory:"))?;
+    let mut movie = Movie::new(
+        file("Heat.(1995)/Heat.(1995).mkv", "a"),
+        TitleId::new(113_277),
+        "Heat",
+        "Heat",
+        1995,
+    );
+    movie.resolution = Some(1080);
+    let mut version = Version::new(file("Heat.(1995)/Heat.(1995) - 2160p.mkv", "b"), None);
+    version.resolution = Some(2160);
+    version.subtitles.push(Subtitle::new(
+        file("Heat.(1995)/Heat.(1995) - 2160p.en.srt", "c"),
+        "eng",
+        "srt",
+    ));
+    movie.versions.push(version);
+    library.save_movie(&movie)?;
+
+    cmd_sync(Config::new(&dir), &mut library)?;
+
+    // the movie keeps its id, with the file and subtitles of its version
+    let mut movies = library.all_movies()?;
+    assert_eq!(movies.len(), 1);
+    let mut promoted = movies.remove(0);
+    library.load_versions(&mut promoted)?;
+    library.load_subtitles(&mut promoted)?;
+    assert_eq!(promoted.id, movie.id);
+    assert_eq!(promoted.file.path.to_string(), "Heat.(1995)/Heat.(1995) - 2160p.mkv");
+    assert_eq!(promoted.resolution, Some(2160));
+    assert!(promoted.versions.is_empty());
+    assert_eq!(promoted.subtitles.len(), 1);
+    assert!(!library.has_fingerprint(&Fingerprint::from_string("a".into()))?);
+
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
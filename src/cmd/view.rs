@@ -11,39 +11,70 @@ use crate::cmd::scan::Report;
 use crate::error::Result;
 use crate::index::{Title, TitleId};
 use crate::library::Library;
-use crate::scan::{MovieFile, PathSize};
+use crate::scan::{alpha2, rank_subtitles, ExtraFile, LinkFile, MovieFile, MovieIdentity, PathSize, SubtitleFile};
 use crate::utils::NonNan;
 
-#[derive(Serialize)]
 pub struct Classified {
+    /// Files still being downloaded, they are classified by a later scan.
+    pub pending: Vec<MovieFile>,
+    /// Files which ffprobe found to be corrupt or truncated.
+    pub broken: Vec<MovieFile>,
     pub ignored: Vec<MovieFile>,
+    pub unparsed: Vec<MovieFile>,
     pub unmatched: Vec<MovieFile>,
     pub duplicates: Vec<MovieFile>,
     pub matches: Vec<MovieFile>,
-    pub conflicts: HashMap<TitleId, Vec<MovieFile>>,
+    /// Copies of the same title, edition and resolution.
+    pub conflicts: HashMap<(TitleId, Option<String>, Option<u32>), Vec<MovieFile>>,
 }
 
 impl Classified {
-    pub fn classify(library: &Library, movies: Vec<MovieFile>) -> Result<Classified> {
+    /// Classify the movies of a scan report, when `upgrade` is set the copies of the edition of a library movie are
+    /// duplicates of it whatever their resolution, so that they can replace it.
+    pub fn classify(library: &Library, movies: Vec<MovieFile>, upgrade: bool) -> Result<Classified> {
+        let mut pending = vec![];
+        let mut broken = vec![];
         let mut ignored = vec![];
+        let mut unparsed = vec![];
         let mut unmatched = vec![];
         let mut duplicates = vec![];
         let mut movies_by_title = HashMap::new();
 
         for movie in movies {
-            if let Some(identity) = movie.identity.as_ref() {
+            if movie.pending.is_some() {
+                pending.push(movie);
+            } else if movie.broken.is_some() {
+                broken.push(movie);
+            } else if let Some(identity) = movie.identity.as_ref() {
                 if library.has_fingerprint(&movie.fingerprint)? {
                     ignored.push(movie);
                 } else {
-                    if library.has_title(&identity.value.title)? {
+                    // other editions or resolutions of a movie in the library are imported as new versions
+                    let edition = movie
+                        .release
+                        .edition
+                        .clone()
+                        .filter(|_| identity.value.episode.is_none());
+                    let title = &identity.value.title;
+                    let in_library = match &identity.value.episode {
+                        Some(episode) => library.has_episode(episode)?,
+                        None => {
+                            library.has_version(title, edition.as_deref(), movie.resolution())?
+                                || (upgrade && library.has_movie_edition(title, edition.as_deref())?)
+                        }
+                    };
+
+                    if in_library {
                         duplicates.push(movie);
                     } else {
                         movies_by_title
-                            .entry(identity.value.title.title_id)
+                            .entry((identity.value.title_id(), edition))
                             .or_insert(Vec::new())
                             .push(movie);
                     }
                 }
+            } else if !movie.parsed {
+                unparsed.push(movie);
             } else {
                 unmatched.push(movie);
             }
@@ -52,11 +83,27 @@ impl Classified {
         let mut matches = vec![];
         let mut conflicts = HashMap::new();
 
-        for (title_id, titles) in movies_by_title.drain() {
-            if titles.len() <= 1 {
-                matches.extend(titles);
-            } else {
-                conflicts.insert(title_id, titles);
+        for ((title_id, edition), titles) in movies_by_title.drain() {
+            // copies in different resolutions are versions of each other, unless one of them has an unknown
+            // resolution; episodes have no versions
+            let versions = titles
+                .iter()
+                .all(|m| m.resolution().is_some() && m.identity().and_then(|i| i.episode.as_ref()).is_none());
+
+            let mut by_resolution = HashMap::new();
+            for movie in titles {
+                by_resolution
+                    .entry(movie.resolution().filter(|_| versions))
+                    .or_insert(Vec::new())
+                    .push(movie);
+            }
+
+            for (resolution, titles) in by_resolution {
+                if titles.len() <= 1 {
+                    matches.extend(titles);
+                } else {
+                    conflicts.insert((title_id, edition.clone(), resolution), titles);
+                }
             }
         }
 
@@ -64,7 +111,10 @@ impl Classified {
         duplicates.sort_by_key(|m| m.identity.as_ref().expect("identity should not be None in sort").score);
 
         Ok(Classified {
+            pending,
+            broken,
             ignored,
+            unparsed,
             unmatched,
             duplicates,
             matches,
@@ -100,12 +150,44 @@ fn fmt_size(size: u64) -> String {
     format!("{} bytes", size)
 }
 
+/// Describe a subtitle and what import does with it, given its rank among its language.
+fn fmt_subtitle(subtitle: &SubtitleFile, rank: usize) -> String {
+    let mut desc = alpha2(&subtitle.lang).unwrap_or(&subtitle.lang).to_owned();
+    if subtitle.forced {
+        desc.push_str(" forced");
+    }
+    if subtitle.sdh {
+        desc.push_str(" sdh");
+    }
+    desc.push_str(&format!(", {}, {} entries, ", subtitle.format, subtitle.entries));
+    if rank == 0 {
+        desc.push_str("best");
+    } else {
+        desc.push_str(&format!("duplicate {}", rank + 1));
+    }
+    if let Some(sync) = subtitle.sync {
+        desc.push_str(&format!(", out of sync: {}", sync));
+    }
+    desc
+}
+
+/// Subtitles of a movie along with their rank.
+fn ranked_subtitles(file: &MovieFile) -> impl Iterator<Item = (&SubtitleFile, usize)> {
+    let runtime = file.identity().map(|identity| identity.title.runtime);
+    file.subtitles.iter().zip(rank_subtitles(&file.subtitles, runtime))
+}
+
+fn fmt_episode(season: u16, episode: u16) -> String {
+    format!("S{:02}E{:02}", season, episode)
+}
+
 #[derive(Serialize)]
 struct TitleDto {
     primary_title: String,
     original_title: Option<String>,
     year: u16,
     url: String,
+    episode: Option<String>,
 }
 
 impl From<&Title> for TitleDto {
@@ -115,10 +197,22 @@ impl From<&Title> for TitleDto {
             original_title: title.original_title.clone(),
             year: title.year,
             url: format!("https://imdb.com/title/{}/", title.title_id.full()),
+            episode: None,
         }
     }
 }
 
+impl From<&MovieIdentity> for TitleDto {
+    fn from(identity: &MovieIdentity) -> TitleDto {
+        let mut dto = TitleDto::from(&identity.title);
+        if let Some(episode) = &identity.episode {
+            dto.url = format!("https://imdb.com/title/{}/", episode.title_id.full());
+            dto.episode = Some(fmt_episode(episode.season, episode.episode));
+        }
+        dto
+    }
+}
+
 #[derive(Serialize)]
 struct PathDto {
     filename: String,
@@ -136,10 +230,69 @@ impl From<&PathSize> for PathDto {
     }
 }
 
+/// File which is not imported, with the reason why.
+#[derive(Serialize)]
+struct FlaggedDto {
+    path: PathDto,
+    reason: String,
+}
+
+impl FlaggedDto {
+    fn new(movie: &MovieFile, reason: Option<String>) -> FlaggedDto {
+        FlaggedDto {
+            path: movie.pathsize().into(),
+            reason: reason.unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct LinkDto {
+    path: String,
+    target: String,
+}
+
+impl From<&LinkFile> for LinkDto {
+    fn from(link: &LinkFile) -> LinkDto {
+        LinkDto {
+            path: link.path.display().to_string(),
+            target: link.target.display().to_string(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ExtraDto {
+    path: PathDto,
+    kind: String,
+}
+
+impl From<&ExtraFile> for ExtraDto {
+    fn from(extra: &ExtraFile) -> ExtraDto {
+        ExtraDto {
+            path: From::from(extra.pathsize()),
+            kind: extra.kind.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct SubtitleDto {
+    path: PathDto,
+    description: String,
+}
+
 #[derive(Serialize)]
 struct MatchInfoDto {
     path: PathDto,
     score: String,
+    release: String,
+    source: String,
+    video: Vec<String>,
+    audio: Vec<String>,
+    parts: Vec<PathDto>,
+    subtitles: Vec<SubtitleDto>,
+    extras: Vec<ExtraDto>,
 }
 
 impl From<&MovieFile> for MatchInfoDto {
@@ -149,6 +302,18 @@ impl From<&MovieFile> for MatchInfoDto {
         MatchInfoDto {
             path: From::from(file.pathsize()),
             score: fmt_score(scored.score),
+            release: file.release.to_string(),
+            source: scored.value.source.to_string(),
+            video: file.video.iter().map(|v| v.to_string()).collect(),
+            audio: file.audio.iter().map(|a| a.to_string()).collect(),
+            parts: file.parts.iter().map(|p| From::from(p.pathsize())).collect(),
+            subtitles: ranked_subtitles(file)
+                .map(|(subtitle, rank)| SubtitleDto {
+                    path: From::from(&subtitle.path),
+                    description: fmt_subtitle(subtitle, rank),
+                })
+                .collect(),
+            extras: file.extras.iter().map(From::from).collect(),
         }
     }
 }
@@ -164,7 +329,7 @@ impl From<&MovieFile> for MatchDto {
         let scored = file.identity.as_ref().expect("identity is none");
 
         MatchDto {
-            title: From::from(&scored.value.title),
+            title: From::from(&scored.value),
             info: file.into(),
         }
     }
@@ -173,41 +338,116 @@ impl From<&MovieFile> for MatchDto {
 #[derive(Serialize)]
 struct ConflictDto {
     title: TitleDto,
+    edition: Option<String>,
+    resolution: Option<u32>,
     paths: Vec<MatchInfoDto>,
 }
 
-impl From<&[MovieFile]> for ConflictDto {
-    fn from(conflicts: &[MovieFile]) -> ConflictDto {
+impl ConflictDto {
+    fn new(edition: &Option<String>, resolution: Option<u32>, conflicts: &[MovieFile]) -> ConflictDto {
         let identity = conflicts[0].identity.as_ref().expect("identity is none");
         ConflictDto {
-            title: From::from(&identity.value.title),
+            title: From::from(&identity.value),
+            edition: edition.clone(),
+            resolution,
             paths: conflicts.iter().map(From::from).collect(),
         }
     }
 }
 
+#[derive(Serialize)]
+struct ShowDto {
+    title: TitleDto,
+    episodes: Vec<MatchDto>,
+}
+
+/// Group the episodes among the given files by show, sorted by show title then episode number.
+fn group_by_show(files: &[MovieFile]) -> Vec<ShowDto> {
+    let mut episodes: Vec<&MovieFile> = files.iter().filter(|m| is_episode(m)).collect();
+    episodes.sort_by_key(|m| {
+        let identity = m.identity().expect("identity is none");
+        let episode = identity.episode.as_ref().expect("episode is none");
+        (
+            &identity.title.primary_title,
+            identity.title.title_id.0,
+            episode.season,
+            episode.episode,
+        )
+    });
+
+    let mut shows: Vec<ShowDto> = vec![];
+    let mut current = None;
+
+    for file in episodes {
+        let title = &file.identity().expect("identity is none").title;
+        if current != Some(title.title_id) {
+            current = Some(title.title_id);
+            shows.push(ShowDto {
+                title: From::from(title),
+                episodes: vec![],
+            });
+        }
+        shows.last_mut().unwrap().episodes.push(From::from(file));
+    }
+
+    shows
+}
+
+fn is_episode(file: &MovieFile) -> bool {
+    file.identity().map(|i| i.episode.is_some()).unwrap_or(false)
+}
+
 #[derive(Serialize)]
 struct DisplayDto {
     matches: Vec<MatchDto>,
+    shows: Vec<ShowDto>,
     conflicts: Vec<ConflictDto>,
     duplicates: Vec<MatchDto>,
+    unparsed: Vec<PathDto>,
     unmatched: Vec<PathDto>,
     ignored: Vec<PathDto>,
+    pending: Vec<FlaggedDto>,
+    broken: Vec<FlaggedDto>,
+    unassigned_subtitles: Vec<PathDto>,
+    links: Vec<LinkDto>,
 }
 
 impl From<&Classified> for DisplayDto {
     fn from(classified: &Classified) -> DisplayDto {
         DisplayDto {
-            matches: classified.matches.iter().map(From::from).collect(),
-            conflicts: classified.conflicts.values().map(|cs| From::from(&cs[..])).collect(),
+            matches: classified
+                .matches
+                .iter()
+                .filter(|m| !is_episode(m))
+                .map(From::from)
+                .collect(),
+            shows: group_by_show(&classified.matches),
+            conflicts: classified
+                .conflicts
+                .iter()
+                .map(|((_, edition, resolution), cs)| ConflictDto::new(edition, *resolution, cs))
+                .collect(),
             duplicates: classified.duplicates.iter().map(From::from).collect(),
+            unparsed: classified.unparsed.iter().map(|file| file.pathsize().into()).collect(),
             unmatched: classified.unmatched.iter().map(|file| file.pathsize().into()).collect(),
             ignored: classified.ignored.iter().map(|file| file.pathsize().into()).collect(),
+            pending: classified
+                .pending
+                .iter()
+                .map(|movie| FlaggedDto::new(movie, movie.pending.map(|p| p.to_string())))
+                .collect(),
+            broken: classified
+                .broken
+                .iter()
+                .map(|movie| FlaggedDto::new(movie, movie.broken.as_ref().map(|b| b.to_string())))
+                .collect(),
+            unassigned_subtitles: vec![],
+            links: vec![],
         }
     }
 }
 
-fn print_text_report(classified: &Classified) {
+fn print_text_report(classified: &Classified, unassigned_subtitles: &[PathSize], links: &[LinkFile]) {
     println!("Ignored (files that were already imported)");
     println!("=======");
     for movie in &classified.ignored {
@@ -215,6 +455,13 @@ fn print_text_report(classified: &Classified) {
     }
     println!();
 
+    println!("Unparsed (files without a year in their name that could not be matched)");
+    println!("========");
+    for movie in &classified.unparsed {
+        println!("{}", movie.path().display());
+    }
+    println!();
+
     println!("Unmatched (files that could not be matched with a movie)");
     println!("=========");
     for movie in &classified.unmatched {
@@ -222,6 +469,38 @@ fn print_text_report(classified: &Classified) {
     }
     println!();
 
+    println!("Pending (files still being downloaded, they will be classified by the next scan)");
+    println!("=======");
+    for movie in &classified.pending {
+        println!("{} ({})", movie.path().display(), movie.pending.expect("pending movie"));
+    }
+    println!();
+
+    println!("Broken (files that ffprobe found to be corrupt or truncated)");
+    println!("======");
+    for movie in &classified.broken {
+        println!(
+            "{} ({})",
+            movie.path().display(),
+            movie.broken.as_ref().expect("broken movie")
+        );
+    }
+    println!();
+
+    println!("Unassigned subtitles (subtitles that could not be assigned to a movie)");
+    println!("====================");
+    for subtitle in unassigned_subtitles {
+        println!("{}", subtitle.path.display());
+    }
+    println!();
+
+    println!("Links (symbolic links that were not followed)");
+    println!("=====");
+    for link in links {
+        println!("{} -> {}", link.path.display(), link.target.display());
+    }
+    println!();
+
     println!("Duplicates (different copy of a movie already in the library)");
     println!("==========");
     for movie in &classified.duplicates {
@@ -231,7 +510,7 @@ fn print_text_report(classified: &Classified) {
 
     println!("Conflicts (different copies of the same movie, not yet in the library)");
     println!("=========");
-    for (_, movies) in classified.conflicts.iter() {
+    for ((_, edition, resolution), movies) in classified.conflicts.iter() {
         let title = &movies
             .first()
             .and_then(|m| m.identity.as_ref())
@@ -241,6 +520,19 @@ fn print_text_report(classified: &Classified) {
         println!("Title: {}", title.primary_title);
         println!("Year: {}", title.year);
         println!("URL: https://imdb.com/title/{}/", title.title_id.full());
+        if let Some(episode) = movies
+            .first()
+            .and_then(|m| m.identity())
+            .and_then(|i| i.episode.as_ref())
+        {
+            println!("Episode: {}", fmt_episode(episode.season, episode.episode));
+        }
+        if let Some(edition) = edition {
+            println!("Edition: {}", edition);
+        }
+        if let Some(resolution) = resolution {
+            println!("Resolution: {}p", resolution);
+        }
         for movie in movies {
             println!("Path: {}", movie.path().display());
         }
@@ -249,15 +541,53 @@ fn print_text_report(classified: &Classified) {
 
     println!("Matches (movies to be imported)");
     println!("=======");
-    for movie in &classified.matches {
+    for movie in classified.matches.iter().filter(|m| !is_episode(m)) {
         let identity = movie.identity.as_ref().expect("identity should not be None in print");
         let title = &identity.value.title;
         println!("Path: {}", movie.path().display());
-        println!("Name: {}", movie.path().file_name().and_then(|s| s.to_str()).unwrap());
+        println!("Name: {}", fmt_filename(movie.path()));
         println!("Title: {}", title.primary_title);
         println!("Year: {}", title.year);
         println!("URL: https://imdb.com/title/{}/", title.title_id.full());
         println!("Score: {:0.3}", identity.score);
+        println!("Matched by: {}", identity.value.source);
+        println!("Release: {}", movie.release);
+        for video in &movie.video {
+            println!("Video: {}", video);
+        }
+        for audio in &movie.audio {
+            println!("Audio: {}", audio);
+        }
+        for (index, part) in movie.parts.iter().enumerate() {
+            println!("Part {}: {}", index + 2, part.path().display());
+        }
+        for (subtitle, rank) in ranked_subtitles(movie) {
+            println!(
+                "Subtitle: {} ({})",
+                subtitle.path().display(),
+                fmt_subtitle(subtitle, rank)
+            );
+        }
+        for extra in &movie.extras {
+            println!("Extra: {} ({})", extra.path().display(), extra.kind);
+        }
+        println!();
+    }
+
+    println!("Episodes (episodes to be imported, grouped by show)");
+    println!("========");
+    for show in group_by_show(&classified.matches) {
+        println!("Title: {}", show.title.primary_title);
+        println!("Year: {}", show.title.year);
+        println!("URL: {}", show.title.url);
+        for episode in &show.episodes {
+            println!(
+                "{} [{}] {}",
+                episode.title.episode.as_ref().expect("episode is none"),
+                episode.info.score,
+                episode.info.path.path
+            );
+        }
         println!();
     }
 }
@@ -266,8 +596,10 @@ pub fn cmd_view(path: impl AsRef<Path>, library: &Library, no_html: bool) -> Res
     let path = path.as_ref();
 
     let report = Report::load(path)?;
-    let classified = Classified::classify(library, report.movies)?;
-    let display = DisplayDto::from(&classified);
+    let classified = Classified::classify(library, report.movies, false)?;
+    let mut display = DisplayDto::from(&classified);
+    display.unassigned_subtitles = report.unassigned_subtitles.iter().map(From::from).collect();
+    display.links = report.links.iter().map(From::from).collect();
 
     if !no_html {
         let mut tera = Tera::default();
@@ -285,10 +617,10 @@ pub fn cmd_view(path: impl AsRef<Path>, library: &Library, no_html: bool) -> Res
         )?;
         file.flush()?;
         if open::that(&html_path).is_err() {
-            print_text_report(&classified);
+            print_text_report(&classified, &report.unassigned_subtitles, &report.links);
         }
     } else {
-        print_text_report(&classified);
+        print_text_report(&classified, &report.unassigned_subtitles, &report.links);
     }
 
     Ok(())
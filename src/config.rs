@@ -44,6 +44,15 @@ impl Config {
         CONFIG_DIR.join("local_storage.bin.gz")
     }
 
+    /// Ignore rules applied to every scan, in the format of `.meroignore` files.
+    pub fn ignore_file_path(&self) -> PathBuf {
+        CONFIG_DIR.join("ignore")
+    }
+
+    pub fn scan_cache_path(&self) -> PathBuf {
+        CONFIG_DIR.join("scan_cache.bin")
+    }
+
     pub fn root_path(&self) -> &Path {
         &self.root_path
     }
@@ -63,4 +72,9 @@ impl Config {
     pub fn tmdb_cache_path(&self) -> PathBuf {
         self.root_path.join(".mero/tmdb-cache.bin.gz")
     }
+
+    /// Directory where the files replaced by an upgrade are moved.
+    pub fn trash_path(&self) -> PathBuf {
+        self.root_path.join(".mero/trash")
+    }
 }
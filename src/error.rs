@@ -15,8 +15,11 @@ pub enum Error {
     Json(serde_json::Error),
     ParseIntError(ParseIntError),
     Http(attohttpc::Error),
+    /// The index file was written in the format of an older version.
+    OutdatedIndex,
     Sqlite(rusqlite::Error),
     SpawnError(String),
+    Subtitle(subparse::errors::Error),
     Transfer {
         src: Option<io::Error>,
         dst: Option<io::Error>,
@@ -36,8 +39,10 @@ impl fmt::Display for Error {
             Json(e) => write!(w, "Error({})", e),
             ParseIntError(e) => write!(w, "Error({})", e),
             Http(e) => write!(w, "Error({})", e),
+            OutdatedIndex => write!(w, "Error(OutdatedIndex)"),
             Sqlite(e) => write!(w, "Error({})", e),
             SpawnError(e) => write!(w, "Error(SpawnError({}))", e),
+            Subtitle(e) => write!(w, "Error(Subtitle({}))", e),
             Transfer { src, dst } => match (src, dst) {
                 (Some(e1), Some(e2)) => write!(w, "Error(Transfer(Both({}, {})))", e1, e2),
                 (Some(e), _) => write!(w, "Error(Transfer(Source({})))", e),
@@ -59,8 +64,10 @@ impl error::Error for Error {
             Json(e) => e.description(),
             ParseIntError(e) => e.description(),
             Http(e) => e.description(),
+            OutdatedIndex => "index built by an older version",
             Sqlite(e) => e.description(),
             SpawnError(_) => "error spawning process",
+            Subtitle(_) => "error reading subtitle",
             Transfer { src, dst } => match (src, dst) {
                 (Some(_), Some(_)) => "transfer error both source and destination",
                 (Some(_), _) => "transfer error source",
@@ -80,8 +87,10 @@ impl error::Error for Error {
             Json(e) => e.source(),
             ParseIntError(e) => e.source(),
             Http(e) => e.source(),
+            OutdatedIndex => None,
             Sqlite(e) => e.source(),
             SpawnError(_) => None,
+            Subtitle(e) => e.source(),
             Transfer { src, dst } => match (src, dst) {
                 (Some(_), Some(_)) => None,
                 (Some(e), _) => e.source(),
@@ -142,3 +151,9 @@ impl From<rusqlite::Error> for Error {
         Error::Sqlite(err)
     }
 }
+
+impl From<subparse::errors::Error> for Error {
+    fn from(err: subparse::errors::Error) -> Error {
+        Error::Subtitle(err)
+    }
+}
@@ -1,5 +1,6 @@
 use std::collections::{HashMap, HashSet};
 use std::fs::File;
+use std::io::Read;
 use std::path::Path;
 use std::str::FromStr;
 
@@ -9,11 +10,17 @@ use serde::{Deserialize, Serialize};
 
 use super::counter::Counter;
 use super::fixed_string::FixedString;
-use crate::error::Result;
+use crate::error::{Error, Result};
 use crate::utils::{self, NonNan};
 
 const MIN_VOTES: u32 = 25;
 
+/// Start of index files, the files written before it start with the number of titles instead.
+const INDEX_MAGIC: [u8; 4] = *b"mero";
+/// Version of the index format, bumped when the index or the parsing of the dataset changes so that older files are
+/// rebuilt.
+const INDEX_VERSION: u32 = 3;
+
 #[derive(Debug, Deserialize, Serialize, Copy, Clone, Eq, PartialEq, Hash)]
 pub struct TitleId(pub u32);
 
@@ -27,9 +34,16 @@ impl TitleId {
     }
 }
 
+#[derive(Debug, Deserialize, Serialize, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum TitleKind {
+    Movie,
+    Series,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Title {
     pub title_id: TitleId,
+    pub kind: TitleKind,
     pub primary_title: String,
     pub original_title: Option<String>,
     pub year: u16,
@@ -37,6 +51,14 @@ pub struct Title {
     pub vote_count: u32,
 }
 
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Episode {
+    pub title_id: TitleId,
+    pub series_id: TitleId,
+    pub season: u16,
+    pub episode: u16,
+}
+
 fn open_csv(path: &Path) -> Result<csv::Reader<Decoder<File>>> {
     let file = File::open(path)?;
     Ok(csv::ReaderBuilder::new()
@@ -57,10 +79,11 @@ fn parse_id(record: &str) -> Result<TitleId> {
     Ok(TitleId(record[2..].parse()?))
 }
 
-fn is_valid_type(title_type: &str) -> bool {
+fn title_kind(title_type: &str) -> Option<TitleKind> {
     match title_type {
-        "movie" | "tvMovie" | "video" | "short" => true,
-        _ => false,
+        "movie" | "tvMovie" | "video" | "short" => Some(TitleKind::Movie),
+        "tvSeries" | "tvMiniSeries" => Some(TitleKind::Series),
+        _ => None,
     }
 }
 
@@ -89,13 +112,14 @@ fn build_titles_table(data_dir_path: &Path) -> Result<HashMap<TitleId, Title>> {
         let original_title = &record[3];
         let adult = &record[4];
         let start_year = parse_none(&record[5]);
-        let runtime = parse_none(&record[5]);
+        let runtime = parse_none(&record[7]);
         let vote_count = votes_table.get(&title_id);
 
-        match (is_valid_type(title_type), adult, start_year, runtime, vote_count) {
-            (true, "0", Some(start_year), Some(runtime), Some(vote_count)) => {
+        match (title_kind(title_type), adult, start_year, runtime, vote_count) {
+            (Some(kind), "0", Some(start_year), Some(runtime), Some(vote_count)) => {
                 let title = Title {
                     title_id: title_id,
+                    kind,
                     primary_title: primary_title.into(),
                     original_title: if primary_title != original_title {
                         Some(original_title.into())
@@ -116,6 +140,38 @@ fn build_titles_table(data_dir_path: &Path) -> Result<HashMap<TitleId, Title>> {
     Ok(titles_table)
 }
 
+fn build_episodes_table(
+    data_dir_path: &Path,
+    titles: &HashMap<TitleId, Title>,
+) -> Result<HashMap<TitleId, Vec<Episode>>> {
+    let mut episodes_reader = open_csv(&data_dir_path.join("title.episode.tsv.gz"))?;
+
+    let mut episodes_table: HashMap<TitleId, Vec<Episode>> = HashMap::new();
+
+    for record in episodes_reader.records() {
+        let record = record?;
+        let title_id = parse_id(&record[0])?;
+        let series_id = parse_id(&record[1])?;
+        let season = parse_none(&record[2]);
+        let episode = parse_none(&record[3]);
+
+        // Only keep the episodes of series that made it into the titles table.
+        let is_series = titles.get(&series_id).map(|t| t.kind == TitleKind::Series);
+
+        if let (Some(true), Some(season), Some(episode)) = (is_series, season, episode) {
+            episodes_table.entry(series_id).or_default().push(Episode {
+                title_id,
+                series_id,
+                season,
+                episode,
+            });
+        }
+    }
+
+    episodes_table.shrink_to_fit();
+    Ok(episodes_table)
+}
+
 // Token splitter must be a superset of the filter_path function
 fn token_splitter(c: char) -> bool {
     match c {
@@ -205,35 +261,97 @@ impl<T> Scored<T> {
 pub struct Index {
     titles: HashMap<TitleId, Title>,
     reverse: HashMap<FixedString, HashSet<TitleId>>,
+    episodes: HashMap<TitleId, Vec<Episode>>,
 }
 
 impl Index {
     pub fn create_index(data_dir: &Path) -> Result<Index> {
         let titles = build_titles_table(data_dir)?;
         let reverse = build_reverse_lookup_table(&titles);
+        let episodes = build_episodes_table(data_dir, &titles)?;
 
-        Ok(Index { titles, reverse })
+        Ok(Index {
+            titles,
+            reverse,
+            episodes,
+        })
+    }
+
+    /// Create a small index of movies for tests, from (id, title, year) tuples.
+    #[cfg(test)]
+    pub fn from_titles(titles: &[(u32, &str, u16)]) -> Index {
+        let titles: HashMap<TitleId, Title> = titles
+            .iter()
+            .map(|&(id, primary_title, year)| {
+                let title = Title {
+                    title_id: TitleId(id),
+                    kind: TitleKind::Movie,
+                    primary_title: primary_title.into(),
+                    original_title: None,
+                    year,
+                    runtime: 100,
+                    vote_count: 1000,
+                };
+                (title.title_id, title)
+            })
+            .collect();
+        let reverse = build_reverse_lookup_table(&titles);
+
+        Index {
+            titles,
+            reverse,
+            episodes: HashMap::new(),
+        }
+    }
+
+    /// Read an index, fails with `Error::OutdatedIndex` if it was written in an older format.
+    fn read(mut reader: impl Read) -> Result<Index> {
+        let header: ([u8; 4], u32) = bincode::deserialize_from(&mut reader)?;
+        if header != (INDEX_MAGIC, INDEX_VERSION) {
+            return Err(Error::OutdatedIndex);
+        }
+        Ok(bincode::deserialize_from(reader)?)
     }
 
     pub fn load_index(path: impl AsRef<Path>) -> Result<Index> {
-        let mut index: Index = utils::deserialize_bin_gz(path)?;
+        let mut index = Index::read(Decoder::new(File::open(path.as_ref())?)?)?;
 
         index.titles.shrink_to_fit();
         index.reverse.shrink_to_fit();
         index.reverse.values_mut().for_each(|bucket| bucket.shrink_to_fit());
+        index.episodes.shrink_to_fit();
 
         Ok(index)
     }
 
     pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
-        utils::serialize_bin_gz(path, self)
+        utils::serialize_bin_gz(path, &(INDEX_MAGIC, INDEX_VERSION, self))
     }
 
-    pub fn get_title(&self, title_id: TitleId) -> &Title {
-        &self.titles[&title_id]
+    pub fn get_title(&self, title_id: TitleId) -> Option<&Title> {
+        self.titles.get(&title_id)
+    }
+
+    pub fn get_episode(&self, series_id: TitleId, season: u16, episode: u16) -> Option<&Episode> {
+        self.episodes
+            .get(&series_id)?
+            .iter()
+            .find(|e| e.season == season && e.episode == episode)
     }
 
     pub fn find_all(&self, text: &str, year: Option<i32>) -> Vec<Scored<&Title>> {
+        self.find_all_kind(text, year, TitleKind::Movie)
+    }
+
+    pub fn find(&self, text: &str, year: Option<i32>) -> Option<Scored<&Title>> {
+        self.find_all(text, year).into_iter().next()
+    }
+
+    pub fn find_series(&self, text: &str, year: Option<i32>) -> Option<Scored<&Title>> {
+        self.find_all_kind(text, year, TitleKind::Series).into_iter().next()
+    }
+
+    fn find_all_kind(&self, text: &str, year: Option<i32>, kind: TitleKind) -> Vec<Scored<&Title>> {
         let text = text.to_lowercase();
         let mut tokens = vec![];
 
@@ -243,7 +361,12 @@ impl Index {
         for token in tokens.drain(..) {
             if !is_ignored_token(&token) {
                 if let Some(title_ids) = self.reverse.get(&token) {
-                    matches.extend(title_ids.iter().cloned());
+                    matches.extend(
+                        title_ids
+                            .iter()
+                            .filter(|title_id| self.titles[title_id].kind == kind)
+                            .cloned(),
+                    );
                 }
             }
         }
@@ -286,8 +409,63 @@ impl Index {
             vec![]
         }
     }
+}
 
-    pub fn find(&self, text: &str, year: Option<i32>) -> Option<Scored<&Title>> {
-        self.find_all(text, year).into_iter().next()
-    }
+#[test]
+fn test_outdated_index() -> Result<()> {
+    let index = Index::from_titles(&[(113_277, "Heat", 1995)]);
+
+    // indexes written before the header start with the number of titles
+    let old = bincode::serialize(&index)?;
+    assert!(match Index::read(&old[..]) {
+        Err(Error::OutdatedIndex) => true,
+        _ => false,
+    });
+
+    let current = bincode::serialize(&(INDEX_MAGIC, INDEX_VERSION, &index))?;
+    let loaded = Index::read(&current[..])?;
+    assert_eq!(loaded.get_title(TitleId(113_277)).map(|t| t.year), Some(1995));
+    Ok(())
+}
+
+#[test]
+fn test_build_titles_table() -> Result<()> {
+    use std::fs;
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    let dir = std::env::temp_dir().join(format!("mero-test-titles-{}", std::process::id()));
+    fs::create_dir_all(&dir)?;
+    let write_gz = |name: &str, text: &str| -> Result<()> {
+        let mut encoder = GzEncoder::new(File::create(dir.join(name))?, Compression::default());
+        encoder.write_all(text.as_bytes())?;
+        encoder.finish()?;
+        Ok(())
+    };
+    write_gz(
+        "title.basics.tsv.gz",
+        "tconst\ttitleType\tprimaryTitle\toriginalTitle\tisAdult\tstartYear\tendYear\truntimeMinutes\tgenres\n\
+         tt0113277\tmovie\tHeat\tHeat\t0\t1995\t\\N\t170\tAction,Crime,Drama\n\
+         tt0903747\ttvSeries\tBreaking Bad\tBreaking Bad\t0\t2008\t2013\t49\tCrime,Drama,Thriller\n",
+    )?;
+    write_gz(
+        "title.ratings.tsv.gz",
+        "tconst\taverageRating\tnumVotes\ntt0113277\t8.3\t700000\ntt0903747\t9.5\t2000000\n",
+    )?;
+
+    let titles = build_titles_table(&dir);
+    fs::remove_dir_all(&dir)?;
+    let titles = titles?;
+
+    // the runtime is read from its own column, not from the start or end year
+    let heat = &titles[&TitleId(113_277)];
+    assert_eq!((heat.year, heat.runtime), (1995, 170));
+    let series = &titles[&TitleId(903_747)];
+    assert_eq!(
+        (series.kind, series.year, series.runtime),
+        (TitleKind::Series, 2008, 49)
+    );
+    Ok(())
 }
@@ -3,4 +3,4 @@ mod fixed_string;
 mod index;
 
 pub use fixed_string::FixedString;
-pub use index::{Index, Scored, Title, TitleId};
+pub use index::{Episode, Index, Scored, Title, TitleId, TitleKind};
@@ -0,0 +1,427 @@
+//! Zip and tar archives, read as directories.
+//!
+//! A member of an archive is designated by the path of the archive joined with the name of the
+//! member, as in `/downloads/subs.zip/Movie.en.srt`. Such paths can be opened with `open`, the
+//! same way as regular files.
+
+use std::cmp;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use flate2::read::DeflateDecoder;
+use lazy_static::lazy_static;
+use tar::EntryType;
+use zip::{CompressionMethod, ZipArchive};
+
+/// Largest member compressed with another method than deflate which is decompressed in memory.
+const MAX_MEMORY_MEMBER: u64 = 64 * 1024 * 1024;
+
+/// Offsets of the members of a tar archive, found when it is listed.
+struct TarOffsets {
+    len: u64,
+    mtime: SystemTime,
+    /// Start and length of the data of each member.
+    members: HashMap<PathBuf, (u64, u64)>,
+}
+
+lazy_static! {
+    // opening a member would otherwise read the headers of the archive up to it
+    static ref TAR_OFFSETS: Mutex<HashMap<PathBuf, TarOffsets>> = Mutex::new(HashMap::new());
+}
+
+/// Member of an archive.
+#[derive(Debug)]
+pub struct Entry {
+    /// Path of the member inside the archive.
+    pub path: PathBuf,
+    pub len: u64,
+    pub mtime: SystemTime,
+    pub is_dir: bool,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Kind {
+    Zip,
+    Tar,
+}
+
+fn kind(path: &Path) -> Option<Kind> {
+    match path.extension()?.to_str()?.to_lowercase().as_str() {
+        "zip" => Some(Kind::Zip),
+        "tar" => Some(Kind::Tar),
+        _ => None,
+    }
+}
+
+/// Check if a file is an archive which can be read as a directory, from its extension.
+pub fn is_archive(path: impl AsRef<Path>) -> bool {
+    kind(path.as_ref()).is_some()
+}
+
+/// Only keep the normal components of a member's path, so that it cannot escape the archive.
+fn clean_member_path(path: &Path) -> Option<PathBuf> {
+    let path: PathBuf = path
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(name) => Some(name),
+            _ => None,
+        })
+        .collect();
+    if path.as_os_str().is_empty() {
+        None
+    } else {
+        Some(path)
+    }
+}
+
+/// List the members of an archive.
+pub fn list(path: impl AsRef<Path>) -> io::Result<Vec<Entry>> {
+    let path = path.as_ref();
+    let file = fs::File::open(path)?;
+    let archive_mtime = file.metadata()?.modified()?;
+
+    let mut entries = vec![];
+
+    match kind(path) {
+        Some(Kind::Zip) => {
+            let mut zip = ZipArchive::new(file).map_err(zip_error)?;
+            for index in 0..zip.len() {
+                let member = zip.by_index(index).map_err(zip_error)?;
+                if let Some(member_path) = member.enclosed_name().and_then(clean_member_path) {
+                    entries.push(Entry {
+                        path: member_path,
+                        len: member.size(),
+                        // the time of zip members has no time zone, the archive's is used instead
+                        mtime: archive_mtime,
+                        is_dir: member.is_dir(),
+                    });
+                }
+            }
+        }
+        Some(Kind::Tar) => {
+            let metadata = file.metadata()?;
+            let mut offsets = TarOffsets {
+                len: metadata.len(),
+                mtime: archive_mtime,
+                members: HashMap::new(),
+            };
+
+            let mut tar = tar::Archive::new(file);
+            for member in tar.entries()? {
+                let member = member?;
+                let is_dir = match member.header().entry_type() {
+                    EntryType::Regular | EntryType::Continuous => false,
+                    EntryType::Directory => true,
+                    _ => continue,
+                };
+                if let Some(member_path) = clean_member_path(&member.path()?) {
+                    if !is_dir {
+                        offsets
+                            .members
+                            .insert(member_path.clone(), (member.raw_file_position(), member.size()));
+                    }
+                    entries.push(Entry {
+                        path: member_path,
+                        len: member.size(),
+                        mtime: UNIX_EPOCH + Duration::from_secs(member.header().mtime()?),
+                        is_dir,
+                    });
+                }
+            }
+
+            if let Ok(mut cache) = TAR_OFFSETS.lock() {
+                cache.insert(path.to_owned(), offsets);
+            }
+        }
+        None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "not an archive")),
+    }
+
+    Ok(entries)
+}
+
+/// Split the path of an archive member into the path of the archive and the path of the member.
+///
+/// Returns `None` if no parent of the path is an archive.
+pub fn split_member(path: &Path) -> Option<(&Path, &Path)> {
+    path.ancestors()
+        .skip(1)
+        .find(|ancestor| is_archive(ancestor) && ancestor.is_file())
+        .and_then(|archive| Some((archive, path.strip_prefix(archive).ok()?)))
+}
+
+/// Check if a path designates a member of an archive rather than a regular file.
+pub fn is_member(path: impl AsRef<Path>) -> bool {
+    let path = path.as_ref();
+    !path.exists() && split_member(path).is_some()
+}
+
+/// Find the start and length of a member of a tar archive, listing the archive if it changed since it was listed.
+fn tar_offsets(archive: &Path, member_path: &Path) -> io::Result<Option<(u64, u64)>> {
+    let metadata = fs::metadata(archive)?;
+    let find = || -> Option<Option<(u64, u64)>> {
+        let cache = TAR_OFFSETS.lock().ok()?;
+        let offsets = cache.get(archive)?;
+        if offsets.len != metadata.len() || Some(offsets.mtime) != metadata.modified().ok() {
+            return None;
+        }
+        Some(offsets.members.get(member_path).copied())
+    };
+
+    match find() {
+        Some(offsets) => Ok(offsets),
+        None => {
+            list(archive)?;
+            Ok(find().flatten())
+        }
+    }
+}
+
+/// Part of a file, such as the data of an archive member.
+struct Window {
+    file: fs::File,
+    start: u64,
+    len: u64,
+    pos: u64,
+}
+
+impl Window {
+    fn new(mut file: fs::File, start: u64, len: u64) -> io::Result<Window> {
+        file.seek(SeekFrom::Start(start))?;
+        Ok(Window {
+            file,
+            start,
+            len,
+            pos: 0,
+        })
+    }
+}
+
+impl Read for Window {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let max = cmp::min(buf.len() as u64, self.len.saturating_sub(self.pos)) as usize;
+        let n = self.file.read(&mut buf[..max])?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for Window {
+    fn seek(&mut self, from: SeekFrom) -> io::Result<u64> {
+        let target = match from {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        };
+        let target = target.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid seek position"))?;
+        self.file.seek(SeekFrom::Start(self.start + target))?;
+        self.pos = target;
+        Ok(target)
+    }
+}
+
+/// Reader over a regular file or a member of an archive.
+///
+/// Members which are stored as-is are read straight from the archive, deflated members are
+/// decompressed as they are read and other compressed members are decompressed in memory.
+pub struct Member {
+    inner: Inner,
+    len: u64,
+}
+
+enum Inner {
+    Window(Window),
+    /// Decompressed position in the member, seeking backwards starts over from the start.
+    Deflate {
+        decoder: DeflateDecoder<Window>,
+        pos: u64,
+    },
+    Memory(Cursor<Vec<u8>>),
+}
+
+impl fmt::Debug for Member {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Member[{}]", self.len)
+    }
+}
+
+impl Member {
+    fn window(file: fs::File, start: u64, len: u64) -> io::Result<Member> {
+        Ok(Member {
+            inner: Inner::Window(Window::new(file, start, len)?),
+            len,
+        })
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+}
+
+impl Read for Member {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.inner {
+            Inner::Window(window) => window.read(buf),
+            Inner::Deflate { decoder, pos } => {
+                let n = decoder.read(buf)?;
+                *pos += n as u64;
+                Ok(n)
+            }
+            Inner::Memory(cursor) => cursor.read(buf),
+        }
+    }
+}
+
+impl Seek for Member {
+    fn seek(&mut self, from: SeekFrom) -> io::Result<u64> {
+        match &mut self.inner {
+            Inner::Window(window) => window.seek(from),
+            Inner::Deflate { decoder, pos } => {
+                let target = match from {
+                    SeekFrom::Start(offset) => Some(offset),
+                    SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+                    SeekFrom::Current(offset) => pos.checked_add_signed(offset),
+                };
+                let target =
+                    target.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid seek position"))?;
+
+                if target < *pos {
+                    let compressed = decoder.get_ref();
+                    let window = Window::new(compressed.file.try_clone()?, compressed.start, compressed.len)?;
+                    *decoder = DeflateDecoder::new(window);
+                    *pos = 0;
+                }
+                *pos += io::copy(&mut decoder.take(target - *pos), &mut io::sink())?;
+                Ok(*pos)
+            }
+            Inner::Memory(cursor) => cursor.seek(from),
+        }
+    }
+}
+
+/// Open a regular file or a member of an archive.
+pub fn open(path: impl AsRef<Path>) -> io::Result<Member> {
+    let path = path.as_ref();
+
+    if path.exists() {
+        let file = fs::File::open(path)?;
+        let len = file.metadata()?.len();
+        return Member::window(file, 0, len);
+    }
+
+    let not_found = || io::Error::new(io::ErrorKind::NotFound, format!("{} not found", path.display()));
+    let (archive, member_path) = split_member(path).ok_or_else(not_found)?;
+    let file = fs::File::open(archive)?;
+
+    match kind(archive) {
+        Some(Kind::Zip) => {
+            let mut zip = ZipArchive::new(file).map_err(zip_error)?;
+            let index = (0..zip.len())
+                .find(|&index| {
+                    zip.by_index(index)
+                        .ok()
+                        .and_then(|member| member.enclosed_name().and_then(clean_member_path))
+                        .as_deref()
+                        == Some(member_path)
+                })
+                .ok_or_else(not_found)?;
+
+            let mut member = zip.by_index(index).map_err(zip_error)?;
+            let (start, compressed_len, len) = (member.data_start(), member.compressed_size(), member.size());
+            match member.compression() {
+                CompressionMethod::Stored => {
+                    drop(member);
+                    return Member::window(zip.into_inner(), start, len);
+                }
+                CompressionMethod::Deflated => {
+                    drop(member);
+                    let compressed = Window::new(zip.into_inner(), start, compressed_len)?;
+                    return Ok(Member {
+                        inner: Inner::Deflate {
+                            decoder: DeflateDecoder::new(compressed),
+                            pos: 0,
+                        },
+                        len,
+                    });
+                }
+                _ if len > MAX_MEMORY_MEMBER => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{} is too large to be decompressed in memory", path.display()),
+                    ));
+                }
+                _ => {}
+            }
+
+            let mut data = Vec::with_capacity(member.size() as usize);
+            member.read_to_end(&mut data)?;
+            Ok(Member {
+                len: data.len() as u64,
+                inner: Inner::Memory(Cursor::new(data)),
+            })
+        }
+        Some(Kind::Tar) => {
+            let (start, len) = tar_offsets(archive, member_path)?.ok_or_else(not_found)?;
+            Member::window(file, start, len)
+        }
+        None => Err(not_found()),
+    }
+}
+
+/// Read a regular file or a member of an archive to the end.
+pub fn read(path: impl AsRef<Path>) -> io::Result<Vec<u8>> {
+    let mut member = open(path)?;
+    let mut data = Vec::with_capacity(member.len() as usize);
+    member.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+fn zip_error(err: zip::result::ZipError) -> io::Error {
+    match err {
+        zip::result::ZipError::Io(err) => err,
+        err => io::Error::new(io::ErrorKind::InvalidData, err.to_string()),
+    }
+}
+
+#[test]
+fn test_read_members() {
+    let zip = Path::new("testdata/archive/subs.zip");
+    let mut names: Vec<_> = list(zip).unwrap().into_iter().map(|e| e.path).collect();
+    names.sort();
+    assert_eq!(
+        names,
+        vec![
+            PathBuf::from("Heat.1995.en.srt"),
+            PathBuf::from("Subs/Heat.1995.fr.srt")
+        ]
+    );
+    assert_eq!(
+        read(zip.join("Heat.1995.en.srt")).unwrap(),
+        b"1\n00:00:01,000 --> 00:00:02,000\nHello\n"
+    );
+
+    let tar = Path::new("testdata/archive/movie.tar");
+    let entries = list(tar).unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].path, PathBuf::from("Heat (1995).mkv"));
+    assert_eq!(read(tar.join("Heat (1995).mkv")).unwrap(), b"heat");
+
+    // deflated members are decompressed as they are read, seeking backwards starts over
+    let fr = read(zip.join("Subs/Heat.1995.fr.srt")).unwrap();
+    let mut member = open(zip.join("Subs/Heat.1995.fr.srt")).unwrap();
+    let mut buf = [0; 16];
+    member.seek(SeekFrom::Start(400)).unwrap();
+    member.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf[..], &fr[400..416]);
+    member.seek(SeekFrom::Start(10)).unwrap();
+    member.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf[..], &fr[10..26]);
+
+    assert!(is_member(tar.join("Heat (1995).mkv")));
+    assert!(!is_member(tar));
+    assert!(open(tar.join("missing.mkv")).is_err());
+}
@@ -1,12 +1,12 @@
 use std::cmp;
 use std::fmt::Write;
-use std::fs::File;
 use std::io::{self, Seek, SeekFrom};
 use std::path::Path;
 
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 
+use super::archive;
 use crate::utils::SafeBuffer;
 
 const BLOCK_SIZE: u64 = 64 * 1024; // 64 KiB
@@ -74,8 +74,9 @@ pub fn file<A>(path: A) -> io::Result<Fingerprint>
 where
     A: AsRef<Path>,
 {
-    let mut file = File::open(path)?;
-    let len = file.metadata()?.len();
+    // members of archives are fingerprinted like regular files
+    let mut file = archive::open(path)?;
+    let len = file.len();
 
     let (seek_pos, read_max) = calc(len);
 
@@ -91,7 +92,7 @@ fn test_small() {
     use std::io::Read;
 
     let path = "testdata/fingerprint/small.bin";
-    let mut f = File::open(path).unwrap();
+    let mut f = std::fs::File::open(path).unwrap();
     let mut buf = Vec::new();
     f.read_to_end(&mut buf).unwrap();
 
@@ -103,7 +104,7 @@ fn test_large() {
     use std::io::Read;
 
     let path = "testdata/fingerprint/large.bin";
-    let mut f = File::open(path).unwrap();
+    let mut f = std::fs::File::open(path).unwrap();
     let mut buf = Vec::new();
     f.read_to_end(&mut buf).unwrap();
 
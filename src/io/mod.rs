@@ -1,3 +1,4 @@
+pub mod archive;
 pub mod fingerprint;
 pub mod transfer;
 
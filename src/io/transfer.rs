@@ -3,6 +3,10 @@ use std::fs::{self, DirBuilder};
 use std::io::Write;
 use std::path::PathBuf;
 
+use encoding_rs::{Encoding, UTF_8};
+use subparse::{MdvdFile, SrtFile, SubtitleFile};
+
+use super::archive::{self, Member};
 use crate::utils::SafeBuffer;
 use crate::Result;
 
@@ -14,10 +18,13 @@ pub enum Status {
     Copying {
         copied: u64,
         len: u64,
-        src: fs::File,
+        src: Member,
         dst: fs::File,
     },
     Hardlinked,
+    /// Member of an archive extracted to the destination.
+    Extracted,
+    Converted,
     Err,
 }
 
@@ -35,15 +42,57 @@ impl fmt::Display for Status {
                 write!(f, "Copying({:.2}/{:.2} MiB)", *copied as f64 / MIB, *len as f64 / MIB)
             }
             Hardlinked => write!(f, "Hardlinked"),
+            Extracted => write!(f, "Extracted"),
+            Converted => write!(f, "Converted"),
             Err => write!(f, "Err"),
         }
     }
 }
 
+/// Re-encoding of a text subtitle to UTF-8 during its transfer.
+#[derive(Debug, Clone, Copy)]
+pub struct Conversion {
+    pub encoding: &'static Encoding,
+    /// Write a byte order mark at the start of the file.
+    pub bom: bool,
+    /// Convert a MicroDVD subtitle to SRT, reading its frames at this frame rate.
+    pub srt_frame_rate: Option<f64>,
+}
+
+impl Conversion {
+    fn convert(&self, src: &[u8]) -> Result<Vec<u8>> {
+        // a byte order mark in the source takes precedence over the detected encoding
+        let (text, _, _) = self.encoding.decode(src);
+
+        let text = match self.srt_frame_rate {
+            Some(frame_rate) => {
+                let mdvd = MdvdFile::parse(&text, frame_rate)?;
+                let entries = mdvd
+                    .get_subtitle_entries()?
+                    .into_iter()
+                    .map(|entry| (entry.timespan, entry.line.unwrap_or_default()))
+                    .collect();
+                String::from_utf8_lossy(&SrtFile::create(entries)?.to_data()?)
+                    .into_owned()
+                    .into()
+            }
+            None => text,
+        };
+
+        let mut dst = Vec::with_capacity(text.len() + 3);
+        if self.bom && !text.starts_with('\u{FEFF}') {
+            dst.extend_from_slice(b"\xEF\xBB\xBF");
+        }
+        dst.extend_from_slice(UTF_8.encode(&text).0.as_ref());
+        Ok(dst)
+    }
+}
+
 #[derive(Debug)]
 pub struct Transfer {
     pub src: PathBuf,
     pub dst: PathBuf,
+    pub conversion: Option<Conversion>,
     pub status: Option<Status>,
 }
 
@@ -75,30 +124,46 @@ impl Transfer {
                     DirBuilder::new().recursive(true).create(parent)?;
                 }
 
-                let src_metadata = self.src.metadata()?;
+                // Converted files are small, they are written at once.
+                if let Some(conversion) = &self.conversion {
+                    fs::write(&self.dst, conversion.convert(&archive::read(&self.src)?)?)?;
+                    return Ok(Status::Converted);
+                }
+
+                let src = archive::open(&self.src)?;
 
                 // If the destination exists but doesn't have the same length,
                 // as the source we might have an incomplete file. Delete it.
                 if let Ok(dst_metadata) = self.dst.metadata() {
-                    if dst_metadata.len() != src_metadata.len() {
+                    if dst_metadata.len() != src.len() {
                         fs::remove_file(&self.dst)?;
                     } else {
                         return Ok(Status::Copied);
                     }
                 }
 
+                // Members of archives cannot be hard-linked, they are extracted by copying them.
+                if archive::is_member(&self.src) {
+                    return Ok(Status::Copying {
+                        len: src.len(),
+                        src,
+                        dst: fs::File::create(&self.dst)?,
+                        copied: 0,
+                    });
+                }
+
                 // Try to hard-link the file. If it cannot be hard-linked, copy it.
-                Ok(match fs::hard_link(&self.src, &self.dst) {
+                // Links are resolved first, hard-linking a symbolic link would link the link itself.
+                Ok(match fs::hard_link(self.src.canonicalize()?, &self.dst) {
                     Ok(_) => Status::Hardlinked,
                     // TODO: check what stupid thing Windows does with hard-linking across devices
                     Err(ref err) if err.raw_os_error() == Some(EXDEV) => {
-                        let src = fs::File::open(&self.src)?;
                         let dst = fs::File::create(&self.dst)?;
 
                         Status::Copying {
+                            len: src.len(),
                             src,
                             dst,
-                            len: src_metadata.len(),
                             copied: 0,
                         }
                     }
@@ -113,7 +178,11 @@ impl Transfer {
             } => Ok(match buff.clear_read(&mut src, 8192)? {
                 0 => {
                     dst.sync_all()?;
-                    Status::Copied
+                    if archive::is_member(&self.src) {
+                        Status::Extracted
+                    } else {
+                        Status::Copied
+                    }
                 }
                 n => {
                     dst.write_all(&buff)?;
@@ -179,6 +248,17 @@ impl Manager {
         self.transfers.push(Transfer {
             src: src.into(),
             dst: dst.into(),
+            conversion: None,
+            status: Some(Status::Waiting),
+        })
+    }
+
+    /// Add a transfer which re-encodes a text file to UTF-8 instead of copying its bytes.
+    pub fn add_conversion(&mut self, src: impl Into<PathBuf>, dst: impl Into<PathBuf>, conversion: Conversion) {
+        self.transfers.push(Transfer {
+            src: src.into(),
+            dst: dst.into(),
+            conversion: Some(conversion),
             status: Some(Status::Waiting),
         })
     }
@@ -206,3 +286,55 @@ impl Manager {
         }
     }
 }
+
+#[test]
+fn test_conversion() {
+    let cp1252 = Encoding::for_label(b"windows-1252").unwrap();
+    let src = b"Caf\xE9";
+
+    let mut conversion = Conversion {
+        encoding: cp1252,
+        bom: false,
+        srt_frame_rate: None,
+    };
+    assert_eq!(conversion.convert(src).unwrap(), "Café".as_bytes());
+
+    conversion.bom = true;
+    assert_eq!(conversion.convert(src).unwrap(), "\u{FEFF}Café".as_bytes());
+
+    // the byte order mark of the source is dropped with its encoding, only the UTF-8 one is written
+    let utf16 = b"\xFF\xFEC\0a\0f\0\xE9\0";
+    assert_eq!(conversion.convert(utf16).unwrap(), "\u{FEFF}Café".as_bytes());
+    conversion.bom = false;
+    assert_eq!(conversion.convert(utf16).unwrap(), "Café".as_bytes());
+}
+
+#[test]
+fn test_conversion_to_srt() {
+    let conversion = Conversion {
+        encoding: UTF_8,
+        bom: false,
+        srt_frame_rate: Some(25.0),
+    };
+    let srt = conversion.convert(b"{25}{50}Hello|world\n").unwrap();
+    let srt = String::from_utf8(srt).unwrap();
+    assert!(srt.contains("00:00:01,000 --> 00:00:02,000"), "{}", srt);
+    assert!(srt.contains("Hello"), "{}", srt);
+}
+
+#[test]
+fn test_extract_member() {
+    let dst = std::env::temp_dir().join("mero-test-extract/Heat (1995).mkv");
+    let _ = fs::remove_file(&dst);
+
+    let mut manager = Manager::new();
+    manager.add_transfer("testdata/archive/movie.tar/Heat (1995).mkv", &dst);
+    while manager.step().unwrap().is_some() {}
+
+    match manager.transfers()[0].status() {
+        Status::Extracted => {}
+        status => panic!("unexpected status {}", status),
+    }
+    assert_eq!(fs::read(&dst).unwrap(), b"heat");
+    fs::remove_dir_all(dst.parent().unwrap()).unwrap();
+}
@@ -1,16 +1,18 @@
 mod sql_builder;
 
+use std::mem;
 use std::path::Path;
 
 use log::debug;
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
 use rusqlite::{named_params, params, Connection};
 use uuid::Uuid;
 
 use self::sql_builder::insert_into;
 use crate::error::Result;
-use crate::index::{Title, TitleId};
+use crate::index::{self, Title, TitleId};
 use crate::io::Fingerprint;
-use crate::scan::RelPath;
+use crate::scan::{AudioInfo, RelPath, VideoInfo};
 
 pub struct File {
     pub id: Uuid,
@@ -28,16 +30,75 @@ impl File {
     }
 }
 
+/// Paths are stored as text, or as the raw bytes of the path if it is not UTF-8.
+impl ToSql for RelPath {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        if let Some(path) = self.to_str() {
+            return Ok(ToSqlOutput::Borrowed(ValueRef::Text(path)));
+        }
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+            Ok(ToSqlOutput::Borrowed(ValueRef::Blob(self.as_os_str().as_bytes())))
+        }
+        #[cfg(not(unix))]
+        {
+            Ok(ToSqlOutput::Owned(rusqlite::types::Value::Text(
+                self.to_string_lossy().into_owned(),
+            )))
+        }
+    }
+}
+
+impl FromSql for RelPath {
+    fn column_result(value: ValueRef) -> FromSqlResult<RelPath> {
+        match value {
+            ValueRef::Text(path) => Ok(RelPath::new(path)),
+            #[cfg(unix)]
+            ValueRef::Blob(path) => {
+                use std::os::unix::ffi::OsStrExt;
+                Ok(RelPath::new(std::ffi::OsStr::from_bytes(path)))
+            }
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+/// Additional file of a movie split in several parts, the movie's file being the first part.
+pub struct Part {
+    pub file: File,
+    pub number: u16,
+}
+
+impl Part {
+    pub fn new(file: File, number: u16) -> Part {
+        Part { file, number }
+    }
+}
+
 pub struct Subtitle {
     pub file: File,
     pub lang: String,
+    pub format: String,
+    pub forced: bool,
+    pub sdh: bool,
+    /// Confidence in the language, between 0 and 1.
+    pub confidence: f64,
+    /// Fingerprint of the original file when the subtitle was converted during the import.
+    pub source_fingerprint: Option<Fingerprint>,
 }
 
 impl Subtitle {
-    pub fn new(file: File, lang: impl Into<String>) -> Subtitle {
+    pub fn new(file: File, lang: impl Into<String>, format: impl Into<String>) -> Subtitle {
         Subtitle {
             file,
             lang: lang.into(),
+            format: format.into(),
+            forced: false,
+            sdh: false,
+            confidence: 1.0,
+            source_fingerprint: None,
         }
     }
 }
@@ -56,6 +117,46 @@ impl Image {
     }
 }
 
+pub struct Extra {
+    pub file: File,
+    pub kind: String,
+}
+
+impl Extra {
+    pub fn new(file: File, kind: impl Into<String>) -> Extra {
+        Extra {
+            file,
+            kind: kind.into(),
+        }
+    }
+}
+
+/// Another copy of a movie, such as another edition or resolution, with its own subtitles.
+pub struct Version {
+    pub id: Uuid,
+    pub file: File,
+    pub edition: Option<String>,
+    /// Resolution of the version's file, such as 2160 or 1080, if known.
+    pub resolution: Option<u32>,
+    pub subtitles: Vec<Subtitle>,
+    pub video: Vec<VideoInfo>,
+    pub audio: Vec<AudioInfo>,
+}
+
+impl Version {
+    pub fn new(file: File, edition: Option<String>) -> Version {
+        Version {
+            id: Uuid::new_v4(),
+            file,
+            edition,
+            resolution: None,
+            subtitles: vec![],
+            video: vec![],
+            audio: vec![],
+        }
+    }
+}
+
 pub struct Movie {
     pub id: Uuid,
     pub file: File,
@@ -63,8 +164,17 @@ pub struct Movie {
     pub primary_title: String,
     pub original_title: String,
     pub year: u16,
+    /// Edition of the movie's file, such as `Director's Cut`.
+    pub edition: Option<String>,
+    /// Resolution of the movie's file, such as 2160 or 1080, if known.
+    pub resolution: Option<u32>,
+    pub parts: Vec<Part>,
     pub subtitles: Vec<Subtitle>,
     pub images: Vec<Image>,
+    pub extras: Vec<Extra>,
+    pub versions: Vec<Version>,
+    pub video: Vec<VideoInfo>,
+    pub audio: Vec<AudioInfo>,
 }
 
 impl Movie {
@@ -82,12 +192,110 @@ impl Movie {
             primary_title: primary_title.into(),
             original_title: original_title.into(),
             year,
+            edition: None,
+            resolution: None,
+            parts: vec![],
             subtitles: vec![],
             images: vec![],
+            extras: vec![],
+            versions: vec![],
+            video: vec![],
+            audio: vec![],
         }
     }
 }
 
+pub struct Episode {
+    pub id: Uuid,
+    pub file: File,
+    pub imdb_id: TitleId,
+    pub season: u16,
+    pub number: u16,
+    pub subtitles: Vec<Subtitle>,
+    pub video: Vec<VideoInfo>,
+    pub audio: Vec<AudioInfo>,
+}
+
+impl Episode {
+    pub fn new(file: File, imdb_id: TitleId, season: u16, number: u16) -> Episode {
+        Episode {
+            id: Uuid::new_v4(),
+            file,
+            imdb_id,
+            season,
+            number,
+            subtitles: vec![],
+            video: vec![],
+            audio: vec![],
+        }
+    }
+}
+
+pub struct Series {
+    pub id: Uuid,
+    pub imdb_id: TitleId,
+    pub primary_title: String,
+    pub original_title: String,
+    pub year: u16,
+    pub episodes: Vec<Episode>,
+}
+
+impl Series {
+    pub fn new(
+        imdb_id: TitleId,
+        primary_title: impl Into<String>,
+        original_title: impl Into<String>,
+        year: u16,
+    ) -> Series {
+        Series {
+            id: Uuid::new_v4(),
+            imdb_id,
+            primary_title: primary_title.into(),
+            original_title: original_title.into(),
+            year,
+            episodes: vec![],
+        }
+    }
+}
+
+fn has_column(con: &Connection, table: &str, column: &str) -> Result<bool> {
+    let mut stmt = con.prepare(&format!("PRAGMA table_info({})", table))?;
+    let mut rows = stmt.query(params![])?;
+
+    while let Some(row) = rows.next()? {
+        let name: String = row.get(1)?;
+        if name == column {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+fn add_column(con: &Connection, table: &str, column: &str, definition: &str) -> Result<()> {
+    if !has_column(con, table, column)? {
+        debug!("adding column {}.{}", table, column);
+        con.execute_batch(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition))?;
+    }
+    Ok(())
+}
+
+/// Add the columns created after the first version of the tables to existing libraries.
+fn migrate(con: &Connection) -> Result<()> {
+    add_column(con, "movie", "edition", "TEXT")?;
+    add_column(con, "movie", "resolution", "INTEGER")?;
+    add_column(con, "version", "resolution", "INTEGER")?;
+    add_column(con, "subtitle", "format", "TEXT NOT NULL DEFAULT 'srt'")?;
+    add_column(con, "episode_subtitle", "format", "TEXT NOT NULL DEFAULT 'srt'")?;
+    for table in &["subtitle", "episode_subtitle"] {
+        add_column(con, table, "forced", "INTEGER NOT NULL DEFAULT 0")?;
+        add_column(con, table, "sdh", "INTEGER NOT NULL DEFAULT 0")?;
+        add_column(con, table, "confidence", "REAL NOT NULL DEFAULT 1.0")?;
+        add_column(con, table, "source_fingerprint", "TEXT")?;
+    }
+    Ok(())
+}
+
 pub struct Library {
     con: Connection,
 }
@@ -96,24 +304,56 @@ impl Library {
     pub fn open(path: &Path) -> Result<Library> {
         let con = Connection::open(path)?;
         con.execute_batch(include_str!("tables.sql"))?;
+        migrate(&con)?;
         Ok(Library { con })
     }
 
+    /// Check if a file is in the library, including the original of converted subtitles.
     pub fn has_fingerprint(&self, fp: &Fingerprint) -> Result<bool> {
-        let mut stmt = self.con.prepare("SELECT id FROM file WHERE fingerprint = ?")?;
+        let mut stmt = self.con.prepare(
+            "SELECT id FROM file WHERE fingerprint = ?1
+             UNION SELECT file_id FROM subtitle WHERE source_fingerprint = ?1
+             UNION SELECT file_id FROM episode_subtitle WHERE source_fingerprint = ?1
+             UNION SELECT file_id FROM version_subtitle WHERE source_fingerprint = ?1",
+        )?;
         let exists = stmt.exists(params![fp.as_str()])?;
         Ok(exists)
     }
 
-    pub fn has_title(&self, title: &Title) -> Result<bool> {
-        let mut stmt = self.con.prepare("SELECT id FROM movie WHERE imdb_id = ?")?;
-        let exists = stmt.exists(params![title.title_id.0])?;
+    /// Check if the library has a copy of a movie in an edition and resolution, `None` being the copy without edition.
+    ///
+    /// Copies whose resolution is unknown are the same version as copies of any resolution.
+    pub fn has_version(&self, title: &Title, edition: Option<&str>, resolution: Option<u32>) -> Result<bool> {
+        let mut stmt = self.con.prepare(
+            "SELECT id FROM movie WHERE imdb_id = ?1 AND edition IS ?2
+             AND (?3 IS NULL OR resolution IS NULL OR resolution = ?3)
+             UNION SELECT v.id FROM version v
+             INNER JOIN movie m on m.id = v.movie_id
+             WHERE m.imdb_id = ?1 AND v.edition IS ?2
+             AND (?3 IS NULL OR v.resolution IS NULL OR v.resolution = ?3)",
+        )?;
+        let exists = stmt.exists(params![title.title_id.0, edition, resolution])?;
+        Ok(exists)
+    }
+
+    /// Check if the main copy of a movie in the library is of an edition, versions are not checked.
+    pub fn has_movie_edition(&self, title: &Title, edition: Option<&str>) -> Result<bool> {
+        let mut stmt = self
+            .con
+            .prepare("SELECT id FROM movie WHERE imdb_id = ? AND edition IS ?")?;
+        let exists = stmt.exists(params![title.title_id.0, edition])?;
+        Ok(exists)
+    }
+
+    pub fn has_episode(&self, episode: &index::Episode) -> Result<bool> {
+        let mut stmt = self.con.prepare("SELECT id FROM episode WHERE imdb_id = ?")?;
+        let exists = stmt.exists(params![episode.title_id.0])?;
         Ok(exists)
     }
 
     pub fn all_movies(&self) -> Result<Vec<Movie>> {
         let mut stmt = self.con.prepare(
-            "SELECT m.id, m.imdb_id, m.primary_title, m.original_title, m.year, f.id, f.path, f.fingerprint
+            "SELECT m.id, m.imdb_id, m.primary_title, m.original_title, m.year, f.id, f.path, f.fingerprint, m.edition, m.resolution
              FROM movie m
              INNER JOIN file f on f.id = m.file_id",
         )?;
@@ -130,20 +370,86 @@ impl Library {
                 year: row.get(4)?,
                 file: File {
                     id: row.get(5)?,
-                    path: RelPath::from_string(row.get(6)?),
+                    path: row.get(6)?,
                     fingerprint: Fingerprint::from_string(row.get(7)?),
                 },
+                edition: row.get(8)?,
+                resolution: row.get(9)?,
+                parts: vec![],
                 subtitles: vec![],
                 images: vec![],
+                extras: vec![],
+                versions: vec![],
+                video: vec![],
+                audio: vec![],
             })
         }
 
         Ok(movies)
     }
 
+    pub fn find_movie(&self, imdb_id: TitleId) -> Result<Option<Movie>> {
+        let mut stmt = self.con.prepare(
+            "SELECT m.id, m.primary_title, m.original_title, m.year, f.id, f.path, f.fingerprint, m.edition, m.resolution
+             FROM movie m
+             INNER JOIN file f on f.id = m.file_id
+             WHERE m.imdb_id = ?",
+        )?;
+        let mut rows = stmt.query(params![imdb_id.0])?;
+
+        match rows.next()? {
+            Some(row) => Ok(Some(Movie {
+                id: row.get(0)?,
+                imdb_id,
+                primary_title: row.get(1)?,
+                original_title: row.get(2)?,
+                year: row.get(3)?,
+                file: File {
+                    id: row.get(4)?,
+                    path: row.get(5)?,
+                    fingerprint: Fingerprint::from_string(row.get(6)?),
+                },
+                edition: row.get(7)?,
+                resolution: row.get(8)?,
+                parts: vec![],
+                subtitles: vec![],
+                images: vec![],
+                extras: vec![],
+                versions: vec![],
+                video: vec![],
+                audio: vec![],
+            })),
+            None => Ok(None),
+        }
+    }
+
+    pub fn load_parts(&self, movie: &mut Movie) -> Result<()> {
+        let mut stmt = self.con.prepare(
+            "SELECT p.number, f.id, f.path, f.fingerprint
+             FROM part p
+             INNER JOIN file f on f.id = p.file_id
+             WHERE p.movie_id = ?
+             ORDER BY p.number",
+        )?;
+        let mut rows = stmt.query(params![&movie.id.as_bytes()[..]])?;
+
+        while let Some(row) = rows.next()? {
+            movie.parts.push(Part {
+                number: row.get(0)?,
+                file: File {
+                    id: row.get(1)?,
+                    path: row.get(2)?,
+                    fingerprint: Fingerprint::from_string(row.get(3)?),
+                },
+            });
+        }
+
+        Ok(())
+    }
+
     pub fn load_subtitles(&self, movie: &mut Movie) -> Result<()> {
         let mut stmt = self.con.prepare(
-            "SELECT s.lang, f.id, f.path, f.fingerprint
+            "SELECT s.lang, s.format, s.forced, s.sdh, s.confidence, s.source_fingerprint, f.id, f.path, f.fingerprint
              FROM subtitle s
              INNER JOIN file f on f.id = s.file_id
              WHERE s.movie_id = ?",
@@ -153,10 +459,15 @@ impl Library {
         while let Some(row) = rows.next()? {
             movie.subtitles.push(Subtitle {
                 lang: row.get(0)?,
+                format: row.get(1)?,
+                forced: row.get(2)?,
+                sdh: row.get(3)?,
+                confidence: row.get(4)?,
+                source_fingerprint: row.get::<_, Option<String>>(5)?.map(Fingerprint::from_string),
                 file: File {
-                    id: row.get(1)?,
-                    path: RelPath::from_string(row.get(2)?),
-                    fingerprint: Fingerprint::from_string(row.get(3)?),
+                    id: row.get(6)?,
+                    path: row.get(7)?,
+                    fingerprint: Fingerprint::from_string(row.get(8)?),
                 },
             });
         }
@@ -178,7 +489,7 @@ impl Library {
                 kind: row.get(0)?,
                 file: File {
                     id: row.get(1)?,
-                    path: RelPath::from_string(row.get(2)?),
+                    path: row.get(2)?,
                     fingerprint: Fingerprint::from_string(row.get(3)?),
                 },
             });
@@ -187,6 +498,244 @@ impl Library {
         Ok(())
     }
 
+    pub fn load_extras(&self, movie: &mut Movie) -> Result<()> {
+        let mut stmt = self.con.prepare(
+            "SELECT e.kind, f.id, f.path, f.fingerprint
+             FROM extra e
+             INNER JOIN file f on f.id = e.file_id
+             WHERE e.movie_id = ?",
+        )?;
+        let mut rows = stmt.query(params![&movie.id.as_bytes()[..]])?;
+
+        while let Some(row) = rows.next()? {
+            movie.extras.push(Extra {
+                kind: row.get(0)?,
+                file: File {
+                    id: row.get(1)?,
+                    path: row.get(2)?,
+                    fingerprint: Fingerprint::from_string(row.get(3)?),
+                },
+            });
+        }
+
+        Ok(())
+    }
+
+    fn video_streams(&self, file: &File) -> Result<Vec<VideoInfo>> {
+        let mut stmt = self.con.prepare(
+            "SELECT codec, width, height, bit_depth, hdr
+             FROM video_stream
+             WHERE file_id = ?
+             ORDER BY number",
+        )?;
+        let mut rows = stmt.query(params![file.id])?;
+
+        let mut streams = vec![];
+
+        while let Some(row) = rows.next()? {
+            streams.push(VideoInfo {
+                codec: row.get(0)?,
+                width: row.get(1)?,
+                height: row.get(2)?,
+                bit_depth: row.get(3)?,
+                hdr: row.get(4)?,
+            });
+        }
+
+        Ok(streams)
+    }
+
+    fn audio_streams(&self, file: &File) -> Result<Vec<AudioInfo>> {
+        let mut stmt = self.con.prepare(
+            "SELECT codec, channels, lang
+             FROM audio_stream
+             WHERE file_id = ?
+             ORDER BY number",
+        )?;
+        let mut rows = stmt.query(params![file.id])?;
+
+        let mut streams = vec![];
+
+        while let Some(row) = rows.next()? {
+            streams.push(AudioInfo {
+                codec: row.get(0)?,
+                channels: row.get(1)?,
+                lang: row.get(2)?,
+            });
+        }
+
+        Ok(streams)
+    }
+
+    pub fn load_streams(&self, movie: &mut Movie) -> Result<()> {
+        movie.video = self.video_streams(&movie.file)?;
+        movie.audio = self.audio_streams(&movie.file)?;
+        Ok(())
+    }
+
+    pub fn load_episode_streams(&self, episode: &mut Episode) -> Result<()> {
+        episode.video = self.video_streams(&episode.file)?;
+        episode.audio = self.audio_streams(&episode.file)?;
+        Ok(())
+    }
+
+    /// Load the versions of a movie with their streams.
+    pub fn load_versions(&self, movie: &mut Movie) -> Result<()> {
+        let mut stmt = self.con.prepare(
+            "SELECT v.id, v.edition, f.id, f.path, f.fingerprint, v.resolution
+             FROM version v
+             INNER JOIN file f on f.id = v.file_id
+             WHERE v.movie_id = ?",
+        )?;
+        let mut rows = stmt.query(params![movie.id])?;
+
+        while let Some(row) = rows.next()? {
+            let mut version = Version::new(
+                File {
+                    id: row.get(2)?,
+                    path: row.get(3)?,
+                    fingerprint: Fingerprint::from_string(row.get(4)?),
+                },
+                row.get(1)?,
+            );
+            version.id = row.get(0)?;
+            version.resolution = row.get(5)?;
+            version.video = self.video_streams(&version.file)?;
+            version.audio = self.audio_streams(&version.file)?;
+            movie.versions.push(version);
+        }
+
+        Ok(())
+    }
+
+    pub fn load_version_subtitles(&self, version: &mut Version) -> Result<()> {
+        let mut stmt = self.con.prepare(
+            "SELECT s.lang, s.format, s.forced, s.sdh, s.confidence, s.source_fingerprint, f.id, f.path, f.fingerprint
+             FROM version_subtitle s
+             INNER JOIN file f on f.id = s.file_id
+             WHERE s.version_id = ?",
+        )?;
+        let mut rows = stmt.query(params![version.id])?;
+
+        while let Some(row) = rows.next()? {
+            version.subtitles.push(Subtitle {
+                lang: row.get(0)?,
+                format: row.get(1)?,
+                forced: row.get(2)?,
+                sdh: row.get(3)?,
+                confidence: row.get(4)?,
+                source_fingerprint: row.get::<_, Option<String>>(5)?.map(Fingerprint::from_string),
+                file: File {
+                    id: row.get(6)?,
+                    path: row.get(7)?,
+                    fingerprint: Fingerprint::from_string(row.get(8)?),
+                },
+            });
+        }
+
+        Ok(())
+    }
+
+    pub fn all_series(&self) -> Result<Vec<Series>> {
+        let mut stmt = self
+            .con
+            .prepare("SELECT id, imdb_id, primary_title, original_title, year FROM series")?;
+        let mut rows = stmt.query(params![])?;
+
+        let mut series = vec![];
+
+        while let Some(row) = rows.next()? {
+            series.push(Series {
+                id: row.get(0)?,
+                imdb_id: TitleId::new(row.get(1)?),
+                primary_title: row.get(2)?,
+                original_title: row.get(3)?,
+                year: row.get(4)?,
+                episodes: vec![],
+            })
+        }
+
+        Ok(series)
+    }
+
+    pub fn find_series(&self, imdb_id: TitleId) -> Result<Option<Series>> {
+        let mut stmt = self
+            .con
+            .prepare("SELECT id, primary_title, original_title, year FROM series WHERE imdb_id = ?")?;
+        let mut rows = stmt.query(params![imdb_id.0])?;
+
+        match rows.next()? {
+            Some(row) => Ok(Some(Series {
+                id: row.get(0)?,
+                imdb_id,
+                primary_title: row.get(1)?,
+                original_title: row.get(2)?,
+                year: row.get(3)?,
+                episodes: vec![],
+            })),
+            None => Ok(None),
+        }
+    }
+
+    pub fn load_episodes(&self, series: &mut Series) -> Result<()> {
+        let mut stmt = self.con.prepare(
+            "SELECT e.id, e.imdb_id, s.number, e.number, f.id, f.path, f.fingerprint
+             FROM episode e
+             INNER JOIN season s on s.id = e.season_id
+             INNER JOIN file f on f.id = e.file_id
+             WHERE s.series_id = ?
+             ORDER BY s.number, e.number",
+        )?;
+        let mut rows = stmt.query(params![series.id])?;
+
+        while let Some(row) = rows.next()? {
+            series.episodes.push(Episode {
+                id: row.get(0)?,
+                imdb_id: TitleId::new(row.get(1)?),
+                season: row.get(2)?,
+                number: row.get(3)?,
+                file: File {
+                    id: row.get(4)?,
+                    path: row.get(5)?,
+                    fingerprint: Fingerprint::from_string(row.get(6)?),
+                },
+                subtitles: vec![],
+                video: vec![],
+                audio: vec![],
+            });
+        }
+
+        Ok(())
+    }
+
+    pub fn load_episode_subtitles(&self, episode: &mut Episode) -> Result<()> {
+        let mut stmt = self.con.prepare(
+            "SELECT s.lang, s.format, s.forced, s.sdh, s.confidence, s.source_fingerprint, f.id, f.path, f.fingerprint
+             FROM episode_subtitle s
+             INNER JOIN file f on f.id = s.file_id
+             WHERE s.episode_id = ?",
+        )?;
+        let mut rows = stmt.query(params![episode.id])?;
+
+        while let Some(row) = rows.next()? {
+            episode.subtitles.push(Subtitle {
+                lang: row.get(0)?,
+                format: row.get(1)?,
+                forced: row.get(2)?,
+                sdh: row.get(3)?,
+                confidence: row.get(4)?,
+                source_fingerprint: row.get::<_, Option<String>>(5)?.map(Fingerprint::from_string),
+                file: File {
+                    id: row.get(6)?,
+                    path: row.get(7)?,
+                    fingerprint: Fingerprint::from_string(row.get(8)?),
+                },
+            });
+        }
+
+        Ok(())
+    }
+
     pub fn save_file(&self, file: &File) -> Result<()> {
         debug!("saving file path={}", file.path);
 
@@ -196,7 +745,7 @@ impl Library {
                 .to_string(),
             named_params! {
                 ":id": file.id,
-                ":path": file.path.as_str(),
+                ":path": file.path,
                 ":fingerprint": file.fingerprint.as_str(),
             },
         )?;
@@ -204,19 +753,97 @@ impl Library {
         Ok(())
     }
 
+    /// Save the streams of a file, the streams of a file which was not probed are kept.
+    pub fn save_streams(&self, file: &File, video: &[VideoInfo], audio: &[AudioInfo]) -> Result<()> {
+        debug!("saving streams path={}", file.path);
+
+        for (number, stream) in video.iter().enumerate() {
+            self.con.execute_named(
+                &insert_into(
+                    "video_stream",
+                    &["file_id", "number", "codec", "width", "height", "bit_depth", "hdr"],
+                )
+                .on_conflict_update(&["file_id", "number"])
+                .to_string(),
+                named_params! {
+                    ":file_id": file.id,
+                    ":number": number as u32,
+                    ":codec": stream.codec,
+                    ":width": stream.width,
+                    ":height": stream.height,
+                    ":bit_depth": stream.bit_depth,
+                    ":hdr": stream.hdr,
+                },
+            )?;
+        }
+
+        for (number, stream) in audio.iter().enumerate() {
+            self.con.execute_named(
+                &insert_into("audio_stream", &["file_id", "number", "codec", "channels", "lang"])
+                    .on_conflict_update(&["file_id", "number"])
+                    .to_string(),
+                named_params! {
+                    ":file_id": file.id,
+                    ":number": number as u32,
+                    ":codec": stream.codec,
+                    ":channels": stream.channels,
+                    ":lang": stream.lang,
+                },
+            )?;
+        }
+
+        Ok(())
+    }
+
+    pub fn save_part(&self, movie_id: &Uuid, part: &Part) -> Result<()> {
+        debug!("saving part number={}", part.number);
+
+        self.save_file(&part.file)?;
+
+        self.con.execute_named(
+            &insert_into("part", &["movie_id", "file_id", "number"])
+                .on_conflict_update(&["movie_id", "file_id"])
+                .to_string(),
+            named_params! {
+                ":movie_id": movie_id,
+                ":file_id": part.file.id,
+                ":number": part.number,
+            },
+        )?;
+
+        Ok(())
+    }
+
     pub fn save_subtitle(&self, movie_id: &Uuid, subtitle: &Subtitle) -> Result<()> {
         debug!("saving subtitle lang={}", subtitle.lang);
 
         self.save_file(&subtitle.file)?;
 
         self.con.execute_named(
-            &insert_into("subtitle", &["movie_id", "file_id", "lang"])
-                .on_conflict_update(&["movie_id", "file_id"])
-                .to_string(),
+            &insert_into(
+                "subtitle",
+                &[
+                    "movie_id",
+                    "file_id",
+                    "lang",
+                    "format",
+                    "forced",
+                    "sdh",
+                    "confidence",
+                    "source_fingerprint",
+                ],
+            )
+            .on_conflict_update(&["movie_id", "file_id"])
+            .to_string(),
             named_params! {
                 ":movie_id": movie_id,
                 ":file_id": subtitle.file.id,
                 ":lang": subtitle.lang,
+                ":format": subtitle.format,
+                ":forced": subtitle.forced,
+                ":sdh": subtitle.sdh,
+                ":confidence": subtitle.confidence,
+                ":source_fingerprint": subtitle.source_fingerprint.as_ref().map(|fp| fp.as_str()),
             },
         )?;
 
@@ -242,15 +869,44 @@ impl Library {
         Ok(())
     }
 
+    pub fn save_extra(&mut self, movie_id: &Uuid, extra: &Extra) -> Result<()> {
+        debug!("saving extra kind={}", extra.kind);
+
+        self.save_file(&extra.file)?;
+
+        self.con.execute_named(
+            &insert_into("extra", &["movie_id", "file_id", "kind"])
+                .on_conflict_update(&["movie_id", "file_id"])
+                .to_string(),
+            named_params! {
+                ":movie_id": movie_id,
+                ":file_id": extra.file.id,
+                ":kind": extra.kind,
+            },
+        )?;
+
+        Ok(())
+    }
+
     pub fn save_movie(&mut self, movie: &Movie) -> Result<()> {
         debug!("saving movie title={}", movie.primary_title);
 
         self.save_file(&movie.file)?;
+        self.save_streams(&movie.file, &movie.video, &movie.audio)?;
 
         self.con.execute_named(
             &insert_into(
                 "movie",
-                &["id", "file_id", "imdb_id", "primary_title", "original_title", "year"],
+                &[
+                    "id",
+                    "file_id",
+                    "imdb_id",
+                    "primary_title",
+                    "original_title",
+                    "year",
+                    "edition",
+                    "resolution",
+                ],
             )
             .on_conflict_update(&["id"])
             .to_string(),
@@ -261,9 +917,15 @@ impl Library {
                 ":primary_title": movie.primary_title,
                 ":original_title": movie.original_title,
                 ":year": movie.year,
+                ":edition": movie.edition,
+                ":resolution": movie.resolution,
             },
         )?;
 
+        for part in &movie.parts {
+            self.save_part(&movie.id, part)?;
+        }
+
         for subtitle in &movie.subtitles {
             self.save_subtitle(&movie.id, &subtitle)?;
         }
@@ -272,16 +934,219 @@ impl Library {
             self.save_image(&movie.id, &image)?;
         }
 
+        for extra in &movie.extras {
+            self.save_extra(&movie.id, extra)?;
+        }
+
+        for version in &movie.versions {
+            self.save_version(&movie.id, version)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn save_version(&self, movie_id: &Uuid, version: &Version) -> Result<()> {
+        debug!("saving version path={}", version.file.path);
+
+        self.save_file(&version.file)?;
+        self.save_streams(&version.file, &version.video, &version.audio)?;
+
+        self.con.execute_named(
+            &insert_into("version", &["id", "movie_id", "file_id", "edition", "resolution"])
+                .on_conflict_update(&["id"])
+                .to_string(),
+            named_params! {
+                ":id": version.id,
+                ":movie_id": movie_id,
+                ":file_id": version.file.id,
+                ":edition": version.edition,
+                ":resolution": version.resolution,
+            },
+        )?;
+
+        for subtitle in &version.subtitles {
+            self.save_version_subtitle(&version.id, subtitle)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn save_version_subtitle(&self, version_id: &Uuid, subtitle: &Subtitle) -> Result<()> {
+        debug!("saving version subtitle lang={}", subtitle.lang);
+
+        self.save_file(&subtitle.file)?;
+
+        self.con.execute_named(
+            &insert_into(
+                "version_subtitle",
+                &[
+                    "version_id",
+                    "file_id",
+                    "lang",
+                    "format",
+                    "forced",
+                    "sdh",
+                    "confidence",
+                    "source_fingerprint",
+                ],
+            )
+            .on_conflict_update(&["version_id", "file_id"])
+            .to_string(),
+            named_params! {
+                ":version_id": version_id,
+                ":file_id": subtitle.file.id,
+                ":lang": subtitle.lang,
+                ":format": subtitle.format,
+                ":forced": subtitle.forced,
+                ":sdh": subtitle.sdh,
+                ":confidence": subtitle.confidence,
+                ":source_fingerprint": subtitle.source_fingerprint.as_ref().map(|fp| fp.as_str()),
+            },
+        )?;
+
+        Ok(())
+    }
+
+    /// Get the id of a series' season, creating the season if it does not exist yet.
+    fn season_id(&self, series_id: &Uuid, number: u16) -> Result<Uuid> {
+        let mut stmt = self
+            .con
+            .prepare("SELECT id FROM season WHERE series_id = ? AND number = ?")?;
+        let mut rows = stmt.query(params![series_id, number])?;
+
+        if let Some(row) = rows.next()? {
+            return Ok(row.get(0)?);
+        }
+
+        let id = Uuid::new_v4();
+        self.con.execute_named(
+            &insert_into("season", &["id", "series_id", "number"]).to_string(),
+            named_params! {
+                ":id": id,
+                ":series_id": series_id,
+                ":number": number,
+            },
+        )?;
+
+        Ok(id)
+    }
+
+    pub fn save_episode_subtitle(&self, episode_id: &Uuid, subtitle: &Subtitle) -> Result<()> {
+        debug!("saving episode subtitle lang={}", subtitle.lang);
+
+        self.save_file(&subtitle.file)?;
+
+        self.con.execute_named(
+            &insert_into(
+                "episode_subtitle",
+                &[
+                    "episode_id",
+                    "file_id",
+                    "lang",
+                    "format",
+                    "forced",
+                    "sdh",
+                    "confidence",
+                    "source_fingerprint",
+                ],
+            )
+            .on_conflict_update(&["episode_id", "file_id"])
+            .to_string(),
+            named_params! {
+                ":episode_id": episode_id,
+                ":file_id": subtitle.file.id,
+                ":lang": subtitle.lang,
+                ":format": subtitle.format,
+                ":forced": subtitle.forced,
+                ":sdh": subtitle.sdh,
+                ":confidence": subtitle.confidence,
+                ":source_fingerprint": subtitle.source_fingerprint.as_ref().map(|fp| fp.as_str()),
+            },
+        )?;
+
+        Ok(())
+    }
+
+    pub fn save_episode(&mut self, series_id: &Uuid, episode: &Episode) -> Result<()> {
+        debug!("saving episode season={} number={}", episode.season, episode.number);
+
+        self.save_file(&episode.file)?;
+        self.save_streams(&episode.file, &episode.video, &episode.audio)?;
+
+        let season_id = self.season_id(series_id, episode.season)?;
+
+        self.con.execute_named(
+            &insert_into("episode", &["id", "season_id", "file_id", "imdb_id", "number"])
+                .on_conflict_update(&["id"])
+                .to_string(),
+            named_params! {
+                ":id": episode.id,
+                ":season_id": season_id,
+                ":file_id": episode.file.id,
+                ":imdb_id": episode.imdb_id.0,
+                ":number": episode.number,
+            },
+        )?;
+
+        for subtitle in &episode.subtitles {
+            self.save_episode_subtitle(&episode.id, subtitle)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn save_series(&mut self, series: &Series) -> Result<()> {
+        debug!("saving series title={}", series.primary_title);
+
+        self.con.execute_named(
+            &insert_into("series", &["id", "imdb_id", "primary_title", "original_title", "year"])
+                .on_conflict_update(&["id"])
+                .to_string(),
+            named_params! {
+                ":id": series.id,
+                ":imdb_id": series.imdb_id.0,
+                ":primary_title": series.primary_title,
+                ":original_title": series.original_title,
+                ":year": series.year,
+            },
+        )?;
+
+        for episode in &series.episodes {
+            self.save_episode(&series.id, episode)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn delete_streams(&self, file: &File) -> Result<()> {
+        debug!("deleting streams path={}", file.path);
+
+        self.con
+            .execute("DELETE FROM video_stream WHERE file_id = ?", params![file.id])?;
+        self.con
+            .execute("DELETE FROM audio_stream WHERE file_id = ?", params![file.id])?;
         Ok(())
     }
 
     pub fn delete_file(&self, file: &File) -> Result<()> {
         debug!("deleting file path={}", file.path);
 
+        self.delete_streams(file)?;
         self.con.execute("DELETE FROM file WHERE id = ?", params![file.id])?;
         Ok(())
     }
 
+    pub fn delete_part(&mut self, movie_id: &Uuid, part: &Part) -> Result<()> {
+        debug!("deleting part number={}", part.number);
+
+        self.con.execute(
+            "DELETE FROM part WHERE movie_id = ? AND file_id = ?",
+            params![movie_id, part.file.id],
+        )?;
+        self.delete_file(&part.file)?;
+        Ok(())
+    }
+
     pub fn delete_subtitle(&mut self, movie_id: &Uuid, subtitle: &Subtitle) -> Result<()> {
         debug!("deleting subtitle lang={}", subtitle.lang);
 
@@ -304,9 +1169,77 @@ impl Library {
         Ok(())
     }
 
+    pub fn delete_extra(&mut self, movie_id: &Uuid, extra: &Extra) -> Result<()> {
+        debug!("deleting extra kind={}", extra.kind);
+
+        self.con.execute(
+            "DELETE FROM extra WHERE movie_id = ? AND file_id = ?",
+            params![movie_id, extra.file.id],
+        )?;
+        self.delete_file(&extra.file)?;
+        Ok(())
+    }
+
+    pub fn delete_version_subtitle(&mut self, version_id: &Uuid, subtitle: &Subtitle) -> Result<()> {
+        debug!("deleting version subtitle lang={}", subtitle.lang);
+
+        self.con.execute(
+            "DELETE FROM version_subtitle WHERE version_id = ? AND file_id = ?",
+            params![version_id, subtitle.file.id],
+        )?;
+        self.delete_file(&subtitle.file)?;
+        Ok(())
+    }
+
+    pub fn delete_version(&mut self, version: &Version) -> Result<()> {
+        debug!("deleting version path={}", version.file.path);
+
+        for subtitle in &version.subtitles {
+            self.delete_version_subtitle(&version.id, subtitle)?;
+        }
+
+        self.con
+            .execute("DELETE FROM version WHERE id = ?", params![version.id])?;
+        self.delete_file(&version.file)?;
+        Ok(())
+    }
+
+    /// Make a version the copy of its movie, once the file of the movie is gone.
+    ///
+    /// The subtitles of the version become the movie's, the previous file and parts of the movie are deleted.
+    pub fn promote_version(&mut self, movie: &mut Movie, index: usize) -> Result<()> {
+        let version = movie.versions.remove(index);
+        debug!("promoting version path={}", version.file.path);
+
+        for part in movie.parts.drain(..) {
+            self.delete_part(&movie.id, &part)?;
+        }
+
+        // the files of the version are kept, only its rows are deleted
+        self.con
+            .execute("DELETE FROM version_subtitle WHERE version_id = ?", params![version.id])?;
+        self.con
+            .execute("DELETE FROM version WHERE id = ?", params![version.id])?;
+
+        let previous = mem::replace(&mut movie.file, version.file);
+        movie.edition = version.edition;
+        movie.resolution = version.resolution;
+        movie.subtitles.extend(version.subtitles);
+        movie.video = version.video;
+        movie.audio = version.audio;
+        self.save_movie(movie)?;
+
+        self.delete_file(&previous)?;
+        Ok(())
+    }
+
     pub fn delete_movie(&mut self, movie: &Movie) -> Result<()> {
         debug!("deleting movie title={}", movie.primary_title);
 
+        for part in &movie.parts {
+            self.delete_part(&movie.id, part)?;
+        }
+
         for subtitle in &movie.subtitles {
             self.delete_subtitle(&movie.id, subtitle)?;
         }
@@ -315,8 +1248,233 @@ impl Library {
             self.delete_image(&movie.id, image)?;
         }
 
+        for extra in &movie.extras {
+            self.delete_extra(&movie.id, extra)?;
+        }
+
+        for version in &movie.versions {
+            self.delete_version(version)?;
+        }
+
         self.con.execute("DELETE FROM movie WHERE id = ?", params![movie.id])?;
         self.delete_file(&movie.file)?;
         Ok(())
     }
+
+    pub fn delete_episode_subtitle(&mut self, episode_id: &Uuid, subtitle: &Subtitle) -> Result<()> {
+        debug!("deleting episode subtitle lang={}", subtitle.lang);
+
+        self.con.execute(
+            "DELETE FROM episode_subtitle WHERE episode_id = ? AND file_id = ?",
+            params![episode_id, subtitle.file.id],
+        )?;
+        self.delete_file(&subtitle.file)?;
+        Ok(())
+    }
+
+    pub fn delete_episode(&mut self, episode: &Episode) -> Result<()> {
+        debug!("deleting episode season={} number={}", episode.season, episode.number);
+
+        for subtitle in &episode.subtitles {
+            self.delete_episode_subtitle(&episode.id, subtitle)?;
+        }
+
+        let season: Option<(Uuid, Uuid)> = {
+            let mut stmt = self.con.prepare(
+                "SELECT s.id, s.series_id
+                 FROM episode e
+                 INNER JOIN season s on s.id = e.season_id
+                 WHERE e.id = ?",
+            )?;
+            let mut rows = stmt.query(params![episode.id])?;
+            match rows.next()? {
+                Some(row) => Some((row.get(0)?, row.get(1)?)),
+                None => None,
+            }
+        };
+
+        self.con
+            .execute("DELETE FROM episode WHERE id = ?", params![episode.id])?;
+        self.delete_file(&episode.file)?;
+
+        // the season and series of the last episode of a show are deleted with it
+        if let Some((season_id, series_id)) = season {
+            self.con.execute(
+                "DELETE FROM season WHERE id = ?1 AND NOT EXISTS (SELECT id FROM episode WHERE season_id = ?1)",
+                params![season_id],
+            )?;
+            self.con.execute(
+                "DELETE FROM series WHERE id = ?1 AND NOT EXISTS (SELECT id FROM season WHERE series_id = ?1)",
+                params![series_id],
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// File of a test library.
+#[cfg(test)]
+fn test_file(path: &str, fingerprint: &str) -> File {
+    File::new(
+        RelPath::from_string(path.into()),
+        Fingerprint::from_string(fingerprint.into()),
+    )
+}
+
+/// Heat (1995), the movie of the test libraries.
+#[cfg(test)]
+fn test_movie(file: File) -> Movie {
+    Movie::new(file, TitleId::new(113_277), "Heat", "Heat", 1995)
+}
+
+#[test]
+fn test_save_subtitle() -> Result<()> {
+    let mut library = Library::open(Path::new(":memory:"))?;
+    let mut movie = test_movie(test_file("Heat.1995/Heat (1995).mkv", "a"));
+    let mut subtitle = Subtitle::new(test_file("Heat.1995/Heat (1995).en.srt", "b"), "eng", "srt");
+    subtitle.source_fingerprint = Some(Fingerprint::from_string("c".into()));
+    movie.subtitles.push(subtitle);
+    library.save_movie(&movie)?;
+
+    assert!(library.has_fingerprint(&Fingerprint::from_string("b".into()))?);
+    assert!(library.has_fingerprint(&Fingerprint::from_string("c".into()))?);
+    Ok(())
+}
+
+#[test]
+fn test_save_streams() -> Result<()> {
+    let mut library = Library::open(Path::new(":memory:"))?;
+    let mut movie = test_movie(test_file("Heat.1995/Heat (1995).mkv", "a"));
+    movie.video.push(VideoInfo {
+        codec: "hevc".into(),
+        width: 3840,
+        height: 1600,
+        bit_depth: Some(10),
+        hdr: Some("HDR10".into()),
+    });
+    movie.audio.push(AudioInfo {
+        codec: "ac3".into(),
+        channels: 6,
+        lang: Some("fra".into()),
+    });
+    library.save_movie(&movie)?;
+
+    let mut loaded = library.all_movies()?.remove(0);
+    library.load_streams(&mut loaded)?;
+    assert_eq!(loaded.video, movie.video);
+    assert_eq!(loaded.audio, movie.audio);
+    assert_eq!(loaded.video[0].resolution(), 2160);
+    Ok(())
+}
+
+#[test]
+fn test_versions() -> Result<()> {
+    let mut library = Library::open(Path::new(":memory:"))?;
+    let mut movie = test_movie(test_file("Heat.(1995)/Heat.(1995).mkv", "a"));
+    movie.resolution = Some(1080);
+    let mut version = Version::new(
+        test_file("Heat.(1995)/Heat.(1995) {edition-Director's Cut} - 2160p.mkv", "b"),
+        Some("Director's Cut".into()),
+    );
+    version.resolution = Some(2160);
+    version.subtitles.push(Subtitle::new(
+        test_file("Heat.(1995)/Heat.(1995) {edition-Director's Cut} - 2160p.en.srt", "c"),
+        "en",
+        "srt",
+    ));
+    movie.versions.push(version);
+    library.save_movie(&movie)?;
+
+    let title = Title {
+        title_id: TitleId::new(113_277),
+        kind: index::TitleKind::Movie,
+        primary_title: "Heat".into(),
+        original_title: None,
+        year: 1995,
+        runtime: 170,
+        vote_count: 0,
+    };
+    assert!(library.has_version(&title, None, Some(1080))?);
+    assert!(library.has_version(&title, None, None)?);
+    assert!(!library.has_version(&title, None, Some(2160))?);
+    assert!(library.has_version(&title, Some("Director's Cut"), Some(2160))?);
+    assert!(!library.has_version(&title, Some("Director's Cut"), Some(1080))?);
+    assert!(!library.has_version(&title, Some("Extended"), None)?);
+
+    let mut loaded = library.all_movies()?.remove(0);
+    library.load_versions(&mut loaded)?;
+    library.load_version_subtitles(&mut loaded.versions[0])?;
+    assert_eq!(loaded.resolution, Some(1080));
+    assert_eq!(loaded.versions[0].edition.as_deref(), Some("Director's Cut"));
+    assert_eq!(loaded.versions[0].resolution, Some(2160));
+    assert_eq!(loaded.versions[0].subtitles[0].lang, "en");
+
+    library.delete_movie(&loaded)?;
+    assert!(!library.has_fingerprint(&Fingerprint::from_string("b".into()))?);
+    Ok(())
+}
+
+#[test]
+fn test_save_series() -> Result<()> {
+    let mut library = Library::open(Path::new(":memory:"))?;
+    let episode =
+        |path: &str, fp: &str, imdb_id, number| Episode::new(test_file(path, fp), TitleId::new(imdb_id), 1, number);
+
+    let mut series = Series::new(TitleId::new(903_747), "Breaking Bad", "Breaking Bad", 2008);
+    let mut pilot = episode("Breaking.Bad.(2008)/Season.01/S01E01.mkv", "a", 959_621, 1);
+    pilot.subtitles.push(Subtitle::new(
+        test_file("Breaking.Bad.(2008)/Season.01/S01E01.en.srt", "b"),
+        "en",
+        "srt",
+    ));
+    series.episodes.push(pilot);
+    library.save_series(&series)?;
+
+    // later imports add episodes to the series and season already in the library
+    let mut found = library
+        .find_series(TitleId::new(903_747))?
+        .expect("series should be saved");
+    found
+        .episodes
+        .push(episode("Breaking.Bad.(2008)/Season.01/S01E02.mkv", "c", 1_054_724, 2));
+    library.save_series(&found)?;
+
+    let mut loaded = library.all_series()?;
+    assert_eq!(loaded.len(), 1);
+    let mut loaded = loaded.remove(0);
+    library.load_episodes(&mut loaded)?;
+    let numbers: Vec<_> = loaded.episodes.iter().map(|e| (e.season, e.number)).collect();
+    assert_eq!(numbers, vec![(1, 1), (1, 2)]);
+
+    library.load_episode_subtitles(&mut loaded.episodes[0])?;
+    assert_eq!(loaded.episodes[0].subtitles[0].lang, "en");
+
+    library.delete_episode(&loaded.episodes[0])?;
+    assert!(!library.has_fingerprint(&Fingerprint::from_string("a".into()))?);
+    assert!(!library.has_fingerprint(&Fingerprint::from_string("b".into()))?);
+    assert!(library.has_fingerprint(&Fingerprint::from_string("c".into()))?);
+    assert_eq!(library.all_series()?.len(), 1);
+
+    // the show is gone with its last episode, so that it can be imported again
+    library.delete_episode(&loaded.episodes[1])?;
+    assert!(library.all_series()?.is_empty());
+    library.save_series(&series)?;
+    assert_eq!(library.all_series()?.len(), 1);
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_non_utf8_path() -> Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = RelPath::new(std::ffi::OsStr::from_bytes(b"Am\xe9lie.2001/Am\xe9lie.2001.mkv"));
+    let mut library = Library::open(Path::new(":memory:"))?;
+    library.save_movie(&test_movie(File::new(
+        path.clone(),
+        Fingerprint::from_string("a".into()),
+    )))?;
+
+    assert_eq!(library.all_movies()?[0].file.path, path);
+    Ok(())
 }
@@ -15,16 +15,57 @@ CREATE TABLE IF NOT EXISTS movie (
     primary_title TEXT NOT NULL,
     original_title TEXT NOT NULL,
     year INTEGER NOT NULL,
+    edition TEXT,
+    resolution INTEGER,
     FOREIGN KEY (file_id) REFERENCES file(id),
     PRIMARY KEY (id)
 );
 
 CREATE INDEX IF NOT EXISTS idx_movie_imdb_id ON movie(imdb_id);
 
+CREATE TABLE IF NOT EXISTS version (
+    id BLOB NOT NULL,
+    movie_id BLOB NOT NULL,
+    file_id BLOB NOT NULL,
+    edition TEXT,
+    resolution INTEGER,
+    FOREIGN KEY (movie_id) REFERENCES movie(id),
+    FOREIGN KEY (file_id) REFERENCES file(id),
+    PRIMARY KEY (id)
+);
+
+CREATE TABLE IF NOT EXISTS version_subtitle (
+    version_id BLOB NOT NULL,
+    file_id BLOB NOT NULL,
+    lang TEXT NOT NULL,
+    format TEXT NOT NULL DEFAULT 'srt',
+    forced INTEGER NOT NULL DEFAULT 0,
+    sdh INTEGER NOT NULL DEFAULT 0,
+    confidence REAL NOT NULL DEFAULT 1.0,
+    source_fingerprint TEXT,
+    FOREIGN KEY (version_id) REFERENCES version(id),
+    FOREIGN KEY (file_id) REFERENCES file(id),
+    PRIMARY KEY (version_id, file_id)
+);
+
+CREATE TABLE IF NOT EXISTS part (
+    movie_id BLOB NOT NULL,
+    file_id BLOB NOT NULL,
+    number INTEGER NOT NULL,
+    FOREIGN KEY (movie_id) REFERENCES movie(id),
+    FOREIGN KEY (file_id) REFERENCES file(id),
+    PRIMARY KEY (movie_id, file_id)
+);
+
 CREATE TABLE IF NOT EXISTS subtitle (
     movie_id BLOB NOT NULL,
     file_id BLOB NOT NULL,
     lang TEXT NOT NULL,
+    format TEXT NOT NULL DEFAULT 'srt',
+    forced INTEGER NOT NULL DEFAULT 0,
+    sdh INTEGER NOT NULL DEFAULT 0,
+    confidence REAL NOT NULL DEFAULT 1.0,
+    source_fingerprint TEXT,
     FOREIGN KEY (movie_id) REFERENCES movie(id),
     FOREIGN KEY (file_id) REFERENCES file(id),
     PRIMARY KEY (movie_id, file_id)
@@ -38,3 +79,82 @@ CREATE TABLE IF NOT EXISTS image (
     FOREIGN KEY (file_id) REFERENCES file(id),
     PRIMARY KEY (movie_id, file_id)
 );
+
+CREATE TABLE IF NOT EXISTS extra (
+    movie_id BLOB NOT NULL,
+    file_id BLOB NOT NULL,
+    kind TEXT NOT NULL,
+    FOREIGN KEY (movie_id) REFERENCES movie(id),
+    FOREIGN KEY (file_id) REFERENCES file(id),
+    PRIMARY KEY (movie_id, file_id)
+);
+
+CREATE TABLE IF NOT EXISTS series (
+    id BLOB NOT NULL,
+    imdb_id INTEGER NOT NULL,
+    primary_title TEXT NOT NULL,
+    original_title TEXT NOT NULL,
+    year INTEGER NOT NULL,
+    PRIMARY KEY (id)
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_series_imdb_id ON series(imdb_id);
+
+CREATE TABLE IF NOT EXISTS season (
+    id BLOB NOT NULL,
+    series_id BLOB NOT NULL,
+    number INTEGER NOT NULL,
+    FOREIGN KEY (series_id) REFERENCES series(id),
+    PRIMARY KEY (id)
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_season_number ON season(series_id, number);
+
+CREATE TABLE IF NOT EXISTS episode (
+    id BLOB NOT NULL,
+    season_id BLOB NOT NULL,
+    file_id BLOB NOT NULL,
+    imdb_id INTEGER NOT NULL,
+    number INTEGER NOT NULL,
+    FOREIGN KEY (season_id) REFERENCES season(id),
+    FOREIGN KEY (file_id) REFERENCES file(id),
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_episode_imdb_id ON episode(imdb_id);
+
+CREATE TABLE IF NOT EXISTS episode_subtitle (
+    episode_id BLOB NOT NULL,
+    file_id BLOB NOT NULL,
+    lang TEXT NOT NULL,
+    format TEXT NOT NULL DEFAULT 'srt',
+    forced INTEGER NOT NULL DEFAULT 0,
+    sdh INTEGER NOT NULL DEFAULT 0,
+    confidence REAL NOT NULL DEFAULT 1.0,
+    source_fingerprint TEXT,
+    FOREIGN KEY (episode_id) REFERENCES episode(id),
+    FOREIGN KEY (file_id) REFERENCES file(id),
+    PRIMARY KEY (episode_id, file_id)
+);
+
+CREATE TABLE IF NOT EXISTS video_stream (
+    file_id BLOB NOT NULL,
+    number INTEGER NOT NULL,
+    codec TEXT NOT NULL,
+    width INTEGER NOT NULL,
+    height INTEGER NOT NULL,
+    bit_depth INTEGER,
+    hdr TEXT,
+    FOREIGN KEY (file_id) REFERENCES file(id),
+    PRIMARY KEY (file_id, number)
+);
+
+CREATE TABLE IF NOT EXISTS audio_stream (
+    file_id BLOB NOT NULL,
+    number INTEGER NOT NULL,
+    codec TEXT NOT NULL,
+    channels INTEGER NOT NULL,
+    lang TEXT,
+    FOREIGN KEY (file_id) REFERENCES file(id),
+    PRIMARY KEY (file_id, number)
+);
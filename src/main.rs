@@ -14,17 +14,21 @@ mod utils;
 use std::fs::File;
 use std::io::BufWriter;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use structopt::StructOpt;
 
+use crate::cmd::parse_offset;
 use crate::config::Config;
-use crate::error::Result;
+use crate::error::{Error, Result};
 use crate::index::Index;
 use crate::library::Library;
 use crate::local_storage::LocalStorage;
+use crate::scan::SymlinkPolicy;
 
 const SRC_FILE_BASICS: &str = "title.basics.tsv.gz";
 const SRC_FILE_RATINGS: &str = "title.ratings.tsv.gz";
+const SRC_FILE_EPISODES: &str = "title.episode.tsv.gz";
 
 macro_rules! flush {
     () => {
@@ -74,6 +78,11 @@ fn check_source_files(data_dir: &Path) -> Result<()> {
         data_dir.join(SRC_FILE_RATINGS),
     )?;
 
+    download_file_if_missing(
+        "https://datasets.imdbws.com/title.episode.tsv.gz",
+        data_dir.join(SRC_FILE_EPISODES),
+    )?;
+
     Ok(())
 }
 
@@ -85,11 +94,17 @@ pub fn load_or_create_index(config: &Config) -> Result<Index> {
 
     task("Loading index", || match Index::load_index(&index_path) {
         Ok(index) => Ok(index),
-        Err(_) => task("Generating index", || {
-            let index = Index::create_index(&data_dir)?;
-            index.save(&index_path)?;
-            Ok(index)
-        }),
+        Err(err) => {
+            // indexes written by older versions are rebuilt, which takes a few minutes
+            if let Error::OutdatedIndex = err {
+                print!("built by an older version of mero. ");
+            }
+            task("Generating index", || {
+                let index = Index::create_index(&data_dir)?;
+                index.save(&index_path)?;
+                Ok(index)
+            })
+        }
     })
 }
 
@@ -107,6 +122,38 @@ enum CmdIgnore {
     },
     #[structopt(name = "list", about = "List ignored files and directories")]
     List,
+    #[structopt(name = "check", about = "Explain which rule ignores a file or directory")]
+    Check {
+        #[structopt(parse(from_os_str))]
+        path: PathBuf,
+        #[structopt(
+            long = "root",
+            help = "Directory that would be scanned, the current directory by default",
+            parse(from_os_str)
+        )]
+        root: Option<PathBuf>,
+    },
+}
+
+#[derive(StructOpt)]
+enum CmdSubs {
+    #[structopt(
+        name = "shift",
+        about = "Shift the timings of the subtitles of a movie or an episode"
+    )]
+    Shift {
+        #[structopt(help = "IMDB id or path of the movie, version or episode in the library")]
+        movie: String,
+        #[structopt(long = "lang", help = "Language of the subtitles, such as en or fre")]
+        lang: String,
+        #[structopt(
+            long = "offset",
+            help = "Offset such as 1.5s or -500ms",
+            parse(try_from_str = "parse_offset"),
+            raw(allow_hyphen_values = "true")
+        )]
+        offset: i64,
+    },
 }
 
 #[derive(StructOpt)]
@@ -118,6 +165,30 @@ enum App {
     Import {
         #[structopt(parse(from_os_str))]
         report: PathBuf,
+        #[structopt(long = "utf8-subtitles", help = "Re-encode text subtitles to UTF-8")]
+        utf8_subtitles: bool,
+        #[structopt(long = "bom", help = "Write a byte order mark in subtitles re-encoded to UTF-8")]
+        bom: bool,
+        #[structopt(
+            long = "srt-subtitles",
+            help = "Convert frame-based subtitles such as MicroDVD to SRT"
+        )]
+        srt_subtitles: bool,
+        #[structopt(
+            long = "skip-duplicate-subtitles",
+            help = "Only import the best subtitle of each language"
+        )]
+        skip_duplicate_subtitles: bool,
+        #[structopt(
+            long = "upgrade",
+            help = "Replace movies already in the library by copies of better quality"
+        )]
+        upgrade: bool,
+        #[structopt(
+            long = "edition",
+            help = "Import the movie as this edition, such as \"Director's Cut\", the scan must hold a single movie"
+        )]
+        edition: Option<String>,
     },
     #[structopt(name = "images", about = "Download images for movies in the database")]
     Images,
@@ -138,6 +209,16 @@ enum App {
         year_gte: Option<u16>,
         #[structopt(long = "year-lte", help = "Year less than or equal to")]
         year_lte: Option<u16>,
+        #[structopt(
+            long = "resolution",
+            help = "Has a video stream of this resolution, such as 2160 or 1080"
+        )]
+        resolution: Option<u32>,
+        #[structopt(
+            long = "audio-lang",
+            help = "Has an audio stream in this language, such as fr or eng"
+        )]
+        audio_lang: Option<String>,
     },
     #[structopt(name = "rehash", about = "Update fingerprints of movies and subtitles")]
     Rehash,
@@ -147,9 +228,38 @@ enum App {
         directory: PathBuf,
         #[structopt(short = "o", help = "Output path for the scan report", parse(from_os_str))]
         out: Option<PathBuf>,
+        #[structopt(
+            short = "j",
+            long = "jobs",
+            help = "Number of files fingerprinted and analyzed at once, one per CPU by default"
+        )]
+        jobs: Option<usize>,
+        #[structopt(long = "clear-cache", help = "Forget the files cached by previous scans")]
+        clear_cache: bool,
+        #[structopt(long = "no-cache", help = "Do not use or update the scan cache")]
+        no_cache: bool,
+        #[structopt(
+            long = "symlinks",
+            default_value = "follow",
+            help = "What to do with symbolic links: follow, skip or record them without following them"
+        )]
+        symlinks: SymlinkPolicy,
+        #[structopt(
+            long = "quiet-period",
+            default_value = "5",
+            help = "Minutes since their last change before files are no longer considered to be downloading"
+        )]
+        quiet_period: u64,
+        #[structopt(
+            long = "probe",
+            help = "Probe every movie with ffprobe to find corrupt or truncated files"
+        )]
+        probe: bool,
     },
     #[structopt(name = "stats", about = "View stats about the library")]
     Stats,
+    #[structopt(name = "subs", about = "Manage the subtitles of movies in the library")]
+    Subs(CmdSubs),
     #[structopt(name = "sync", about = "Synchronize changes made on disk to the library")]
     Sync,
     #[structopt(name = "view", about = "View a scan report file")]
@@ -220,13 +330,34 @@ fn main() -> Result<()> {
                     CmdIgnore::List => {
                         cmd_ignore_list(local_storage);
                     }
+                    CmdIgnore::Check { path, root } => {
+                        let root = root.unwrap_or_else(|| PathBuf::from("."));
+                        cmd_ignore_check(&config, &local_storage, &path, &root)?;
+                    }
                 }
 
                 Ok(())
             })?;
         }
-        App::Import { report } => {
-            open_library(|config, mut library| cmd_import(config, report, &mut library))?;
+        App::Import {
+            report,
+            utf8_subtitles,
+            bom,
+            srt_subtitles,
+            skip_duplicate_subtitles,
+            upgrade,
+            edition,
+        } => {
+            let options = ImportOptions {
+                utf8_subtitles,
+                bom,
+                srt_subtitles,
+                skip_duplicate_subtitles,
+                upgrade,
+                edition,
+                ffprobe: None,
+            };
+            open_library(|config, mut library| cmd_import(config, report, &mut library, options))?;
         }
         App::Images => {
             open_library(|config, mut library| cmd_images(config, &mut library))?;
@@ -239,21 +370,51 @@ fn main() -> Result<()> {
             year,
             year_gte,
             year_lte,
+            resolution,
+            audio_lang,
         } => {
-            open_library(|_, library| cmd_query(&library, title, year, year_gte, year_lte))?;
+            open_library(|_, library| cmd_query(&library, title, year, year_gte, year_lte, resolution, audio_lang))?;
         }
         App::Rehash => {
             open_library(|config, mut library| cmd_rehash(config, &mut library))?;
         }
-        App::Scan { directory, out } => {
+        App::Scan {
+            directory,
+            out,
+            jobs,
+            clear_cache,
+            no_cache,
+            symlinks,
+            quiet_period,
+            probe,
+        } => {
             open_all(|config, index, _| {
                 let local_storage = LocalStorage::open(config.local_storage_path())?;
-                cmd_scan(&directory, out, config, &index, &local_storage)
+                let cache = if no_cache {
+                    CacheMode::Disabled
+                } else if clear_cache {
+                    CacheMode::Clear
+                } else {
+                    CacheMode::Enabled
+                };
+                let options = ScanOptions {
+                    jobs: jobs.unwrap_or_else(utils::default_jobs),
+                    cache,
+                    symlinks,
+                    quiet_period: Duration::from_secs(quiet_period * 60),
+                    probe,
+                };
+                cmd_scan(&directory, out, options, config, &index, &local_storage)
             })?;
         }
         App::Stats => {
             open_library(|_, library| cmd_stats(&library))?;
         }
+        App::Subs(action) => match action {
+            CmdSubs::Shift { movie, lang, offset } => {
+                open_library(|config, mut library| cmd_subs_shift(config, &mut library, movie, lang, offset))?;
+            }
+        },
         App::Sync => {
             open_library(|config, mut library| cmd_sync(config, &mut library))?;
         }
@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use super::scan::{MatchContext, MovieMatch, SubtitleFile};
+use crate::io::Fingerprint;
+use crate::utils;
+use crate::Result;
+
+/// What a scan found about a file, valid as long as the file keeps the same size and modification time.
+#[derive(Debug, Deserialize, Serialize)]
+struct CacheEntry {
+    len: u64,
+    mtime: SystemTime,
+    fingerprint: Option<Fingerprint>,
+    /// Match of a movie, valid while its context stays the same.
+    movie_match: Option<(MatchContext, MovieMatch)>,
+    /// Analysis of a subtitle file, `Some(None)` if it could not be read as a subtitle.
+    subtitle: Option<Option<SubtitleFile>>,
+}
+
+impl CacheEntry {
+    fn new(len: u64, mtime: SystemTime) -> CacheEntry {
+        CacheEntry {
+            len,
+            mtime,
+            fingerprint: None,
+            movie_match: None,
+            subtitle: None,
+        }
+    }
+}
+
+/// Size and modification time of a file. Members of archives are not cached.
+fn stat(path: &Path) -> Option<(u64, SystemTime)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.len(), metadata.modified().ok()?))
+}
+
+/// Canonical path of a file, so that the same file reached through links or relative paths has one entry.
+fn key(path: &Path) -> Option<OsString> {
+    Some(fs::canonicalize(path).ok()?.into_os_string())
+}
+
+/// Cache of the fingerprints, matches and subtitle analyses of scanned files, by canonical path.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct ScanCache {
+    /// Modification time of the index used to match the movies.
+    index_mtime: Option<SystemTime>,
+    entries: HashMap<OsString, CacheEntry>,
+    #[serde(skip)]
+    hits: u64,
+    #[serde(skip)]
+    misses: u64,
+}
+
+impl ScanCache {
+    pub fn open(path: impl AsRef<Path>) -> Result<ScanCache> {
+        let path = path.as_ref();
+        if path.exists() {
+            // caches written by older versions cannot be read, they are filled again
+            Ok(utils::deserialize_bin(path).unwrap_or_default())
+        } else {
+            Ok(ScanCache::default())
+        }
+    }
+
+    /// Save the cache, dropping the files which were removed or changed since they were cached.
+    pub fn save(&mut self, path: impl AsRef<Path>) -> Result {
+        self.entries
+            .retain(|path, entry| stat(Path::new(path)) == Some((entry.len, entry.mtime)));
+        utils::serialize_bin(path, &self)?;
+        Ok(())
+    }
+
+    /// Forget every file.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Forget the matches if the index was updated since they were made.
+    pub fn set_index_mtime(&mut self, index_mtime: Option<SystemTime>) {
+        if self.index_mtime != index_mtime {
+            for entry in self.entries.values_mut() {
+                entry.movie_match = None;
+            }
+            self.index_mtime = index_mtime;
+        }
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    fn get<T>(&mut self, path: &Path, field: impl Fn(&CacheEntry) -> Option<&T>) -> Option<T>
+    where
+        T: Clone,
+    {
+        let entry = key(path).and_then(|key| self.entries.get(&key));
+        let value = match (entry, stat(path)) {
+            (Some(entry), Some(stat)) if (entry.len, entry.mtime) == stat => field(entry).cloned(),
+            _ => None,
+        };
+        if value.is_some() {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+        }
+        value
+    }
+
+    fn entry(&mut self, path: &Path) -> Option<&mut CacheEntry> {
+        let (len, mtime) = stat(path)?;
+        let entry = self
+            .entries
+            .entry(key(path)?)
+            .or_insert_with(|| CacheEntry::new(len, mtime));
+        if (entry.len, entry.mtime) != (len, mtime) {
+            *entry = CacheEntry::new(len, mtime);
+        }
+        Some(entry)
+    }
+
+    pub fn fingerprint(&mut self, path: &Path) -> Option<Fingerprint> {
+        self.get(path, |entry| entry.fingerprint.as_ref())
+    }
+
+    pub fn set_fingerprint(&mut self, path: &Path, fingerprint: &Fingerprint) {
+        if let Some(entry) = self.entry(path) {
+            entry.fingerprint = Some(fingerprint.clone());
+        }
+    }
+
+    pub(super) fn movie_match(&mut self, path: &Path, context: &MatchContext) -> Option<MovieMatch> {
+        self.get(path, |entry| match &entry.movie_match {
+            Some((cached_context, movie_match)) if cached_context == context => Some(movie_match),
+            _ => None,
+        })
+    }
+
+    pub(super) fn set_movie_match(&mut self, path: &Path, context: MatchContext, movie_match: &MovieMatch) {
+        if let Some(entry) = self.entry(path) {
+            entry.movie_match = Some((context, movie_match.clone()));
+        }
+    }
+
+    pub fn subtitle(&mut self, path: &Path) -> Option<Option<SubtitleFile>> {
+        self.get(path, |entry| entry.subtitle.as_ref())
+    }
+
+    pub fn set_subtitle(&mut self, path: &Path, subtitle: Option<&SubtitleFile>) {
+        if let Some(entry) = self.entry(path) {
+            entry.subtitle = Some(subtitle.cloned());
+        }
+    }
+}
+
+impl fmt::Display for ScanCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} files cached, {} hits, {} misses",
+            self.entries.len(),
+            self.hits,
+            self.misses
+        )
+    }
+}
+
+#[test]
+fn test_scan_cache() {
+    let dir = std::env::temp_dir().join("mero-test-scan-cache");
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("movie.mkv");
+    fs::write(&path, "movie").unwrap();
+
+    let mut cache = ScanCache::default();
+    let fingerprint = Fingerprint::from_string("abc".into());
+    assert_eq!(cache.fingerprint(&path), None);
+    cache.set_fingerprint(&path, &fingerprint);
+    assert_eq!(cache.fingerprint(&path), Some(fingerprint));
+    assert_eq!((cache.hits(), cache.misses()), (1, 1));
+
+    // a file which changed size is analyzed again
+    fs::write(&path, "movie, longer").unwrap();
+    assert_eq!(cache.fingerprint(&path), None);
+
+    let cache_path = dir.join("cache.bin");
+    cache.save(&cache_path).unwrap();
+    assert_eq!(ScanCache::open(&cache_path).unwrap().entries.len(), 0);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_scan_cache_key() {
+    let dir = std::env::temp_dir().join("mero-test-scan-cache-key");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("movies")).unwrap();
+    let path = dir.join("movies/movie.mkv");
+    fs::write(&path, "movie").unwrap();
+    std::os::unix::fs::symlink(dir.join("movies"), dir.join("link")).unwrap();
+
+    // the same file reached through a link has the same entry
+    let mut cache = ScanCache::default();
+    let fingerprint = Fingerprint::from_string("abc".into());
+    cache.set_fingerprint(&path, &fingerprint);
+    assert_eq!(cache.fingerprint(&dir.join("link/movie.mkv")), Some(fingerprint));
+    assert_eq!(cache.entries.len(), 1);
+
+    // matches are made again when the NFO files next to the movie change
+    let context = |sidecars| MatchContext::new(&path, sidecars, true);
+    cache.set_movie_match(&path, context(vec![]), &MovieMatch::default());
+    assert!(cache.movie_match(&path, &context(vec![])).is_some());
+    let nfo = ("movie.nfo".to_owned(), 10, SystemTime::UNIX_EPOCH);
+    assert!(cache.movie_match(&path, &context(vec![nfo])).is_none());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
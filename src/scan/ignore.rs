@@ -0,0 +1,241 @@
+//! Gitignore-style rules read from `.meroignore` files and from the global ignore file.
+//!
+//! A rule applies to the paths below the directory of its file. Patterns without a slash match
+//! the name of a file at any depth, other patterns match the path relative to that directory.
+//! `*`, `?`, `[...]` and `**` are supported, a trailing slash only matches directories and a
+//! leading `!` includes again the paths excluded by a previous rule. The last matching rule wins.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use regex::Regex;
+
+/// Name of the files holding the rules of a directory.
+pub const IGNORE_FILE_NAME: &str = ".meroignore";
+
+#[derive(Debug, Clone)]
+pub struct Rule {
+    /// File and line where the rule is defined.
+    pub source: PathBuf,
+    pub line: usize,
+    pub pattern: String,
+    pub negated: bool,
+    dir_only: bool,
+    base: PathBuf,
+    regex: Regex,
+}
+
+impl Rule {
+    fn parse(line: &str, base: &Path, source: &Path, number: usize) -> Option<Rule> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let pattern = line;
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(line) => (true, line),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(line) => (true, line),
+            None => (false, line),
+        };
+        if line.is_empty() {
+            return None;
+        }
+
+        // patterns without a slash match at any depth, others are relative to the base directory
+        let anchored = line.contains('/');
+        let line = line.trim_start_matches('/');
+        let prefix = if anchored { "^" } else { "^(?:.*/)?" };
+        let regex = Regex::new(&format!("{}{}$", prefix, glob_to_regex(line))).ok()?;
+
+        Some(Rule {
+            source: source.to_owned(),
+            line: number,
+            pattern: pattern.to_owned(),
+            negated,
+            dir_only,
+            base: base.to_owned(),
+            regex,
+        })
+    }
+
+    fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        match path.strip_prefix(&self.base) {
+            Ok(relative) => {
+                let relative: Vec<_> = relative
+                    .components()
+                    .filter_map(|c| match c {
+                        Component::Normal(name) => Some(name.to_string_lossy()),
+                        _ => None,
+                    })
+                    .collect();
+                !relative.is_empty() && self.regex.is_match(&relative.join("/"))
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.source.display(), self.line, self.pattern)
+    }
+}
+
+/// Translate a glob pattern to a regular expression.
+fn glob_to_regex(glob: &str) -> String {
+    let mut regex = String::new();
+    let mut chars = glob.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    // `**/` matches any number of directories, including none
+                    chars.next();
+                    regex.push_str("(?:.*/)?");
+                } else {
+                    regex.push_str(".*");
+                }
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' => {
+                let mut class = String::new();
+                let mut closed = false;
+                if let Some(&negation) = chars.peek() {
+                    if negation == '!' || negation == '^' {
+                        chars.next();
+                        class.push('^');
+                    }
+                }
+                for c in chars.by_ref() {
+                    match c {
+                        ']' if !class.is_empty() && class != "^" => {
+                            closed = true;
+                            break;
+                        }
+                        '\\' | '[' | ']' | '&' | '~' => {
+                            class.push('\\');
+                            class.push(c);
+                        }
+                        c => class.push(c),
+                    }
+                }
+                if closed {
+                    regex.push('[');
+                    regex.push_str(&class);
+                    regex.push(']');
+                } else {
+                    regex.push_str(&regex::escape(&format!("[{}", class)));
+                }
+            }
+            '\\' => {
+                if let Some(c) = chars.next() {
+                    regex.push_str(&regex::escape(&c.to_string()));
+                }
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+
+    regex
+}
+
+/// Ordered list of ignore rules.
+#[derive(Debug, Clone, Default)]
+pub struct IgnoreRules {
+    rules: Vec<Rule>,
+}
+
+impl IgnoreRules {
+    pub fn new() -> IgnoreRules {
+        IgnoreRules::default()
+    }
+
+    /// Add the rules of an ignore file, which apply below the `base` directory.
+    ///
+    /// A missing file has no rules.
+    pub fn add_file(&mut self, path: impl AsRef<Path>, base: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let text = match fs::read(path) {
+            Ok(data) => String::from_utf8_lossy(&data).into_owned(),
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        self.add_rules(&text, base.as_ref(), path);
+        Ok(())
+    }
+
+    /// Add the rules of the `.meroignore` file of a directory, if it has one.
+    pub fn add_dir(&mut self, dir: impl AsRef<Path>) -> io::Result<()> {
+        let dir = dir.as_ref();
+        self.add_file(dir.join(IGNORE_FILE_NAME), dir)
+    }
+
+    fn add_rules(&mut self, text: &str, base: &Path, source: &Path) {
+        self.rules.extend(
+            text.lines()
+                .enumerate()
+                .filter_map(|(index, line)| Rule::parse(line, base, source, index + 1)),
+        );
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Forget the rules added after the first `len` ones.
+    pub fn truncate(&mut self, len: usize) {
+        self.rules.truncate(len);
+    }
+
+    /// Last rule matching a path, it ignores the path unless it is negated.
+    pub fn matching(&self, path: &Path, is_dir: bool) -> Option<&Rule> {
+        self.rules.iter().rev().find(|rule| rule.matches(path, is_dir))
+    }
+
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        self.matching(path, is_dir).is_some_and(|rule| !rule.negated)
+    }
+}
+
+#[test]
+fn test_ignore_rules() {
+    let mut rules = IgnoreRules::new();
+    rules.add_rules(
+        "# comment\nExtras/\n*.sample.mkv\n!keep.sample.mkv\n/Movies/*.nfo\nSeries/**/Featurettes\n",
+        Path::new("/downloads"),
+        Path::new("/downloads/.meroignore"),
+    );
+    assert_eq!(rules.len(), 5);
+
+    let ignored = |path: &str, is_dir| rules.is_ignored(Path::new(path), is_dir);
+    assert!(ignored("/downloads/Heat.1995/Extras", true));
+    assert!(!ignored("/downloads/Heat.1995/Extras", false));
+    assert!(ignored("/downloads/Heat.1995/heat.sample.mkv", false));
+    assert!(!ignored("/downloads/Heat.1995/keep.sample.mkv", false));
+    assert!(ignored("/downloads/Movies/Heat.nfo", false));
+    assert!(!ignored("/downloads/Other/Movies/Heat.nfo", false));
+    assert!(ignored("/downloads/Series/Lost/S01/Featurettes", true));
+    assert!(ignored("/downloads/Series/Featurettes", true));
+    assert!(!ignored("/elsewhere/heat.sample.mkv", false));
+
+    let rule = rules
+        .matching(Path::new("/downloads/Heat.1995/keep.sample.mkv"), false)
+        .unwrap();
+    assert_eq!(rule.to_string(), "/downloads/.meroignore:4: !keep.sample.mkv");
+}
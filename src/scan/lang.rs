@@ -0,0 +1,177 @@
+/// Languages as (ISO 639-1, ISO 639-3, ISO 639-2/B, English name).
+///
+/// The ISO 639-3 code is the code returned by whatlang and stored in the library.
+const LANGUAGES: &[(&str, &str, &str, &str)] = &[
+    ("en", "eng", "eng", "english"),
+    ("fr", "fra", "fre", "french"),
+    ("de", "deu", "ger", "german"),
+    ("es", "spa", "spa", "spanish"),
+    ("pt", "por", "por", "portuguese"),
+    ("it", "ita", "ita", "italian"),
+    ("nl", "nld", "dut", "dutch"),
+    ("ru", "rus", "rus", "russian"),
+    ("uk", "ukr", "ukr", "ukrainian"),
+    ("pl", "pol", "pol", "polish"),
+    ("cs", "ces", "cze", "czech"),
+    ("sk", "slk", "slo", "slovak"),
+    ("sv", "swe", "swe", "swedish"),
+    ("da", "dan", "dan", "danish"),
+    ("no", "nob", "nor", "norwegian"),
+    ("nb", "nob", "nob", "bokmal"),
+    ("fi", "fin", "fin", "finnish"),
+    ("hu", "hun", "hun", "hungarian"),
+    ("ro", "ron", "rum", "romanian"),
+    ("bg", "bul", "bul", "bulgarian"),
+    ("hr", "hrv", "hrv", "croatian"),
+    ("sr", "srp", "srp", "serbian"),
+    ("sl", "slv", "slv", "slovenian"),
+    ("tr", "tur", "tur", "turkish"),
+    ("el", "ell", "gre", "greek"),
+    ("he", "heb", "heb", "hebrew"),
+    ("ar", "ara", "ara", "arabic"),
+    ("fa", "pes", "per", "persian"),
+    ("hi", "hin", "hin", "hindi"),
+    ("ja", "jpn", "jpn", "japanese"),
+    ("ko", "kor", "kor", "korean"),
+    ("zh", "cmn", "chi", "chinese"),
+    ("vi", "vie", "vie", "vietnamese"),
+    ("th", "tha", "tha", "thai"),
+    ("id", "ind", "ind", "indonesian"),
+    ("lt", "lit", "lit", "lithuanian"),
+    ("lv", "lav", "lav", "latvian"),
+    ("et", "est", "est", "estonian"),
+    ("ca", "cat", "cat", "catalan"),
+];
+
+/// Parse a language tag such as `en`, `eng`, `fre` or `English` into an ISO 639-3 code.
+pub fn parse_lang_tag(tag: &str) -> Option<&'static str> {
+    let tag = tag.to_lowercase();
+    LANGUAGES
+        .iter()
+        .find(|(alpha2, alpha3, alpha3b, name)| tag == *alpha2 || tag == *alpha3 || tag == *alpha3b || tag == *name)
+        .map(|(_, alpha3, _, _)| *alpha3)
+}
+
+/// Get the ISO 639-1 code of an ISO 639-3 code, used in file names.
+pub fn alpha2(code: &str) -> Option<&'static str> {
+    LANGUAGES
+        .iter()
+        .find(|(_, alpha3, _, _)| *alpha3 == code)
+        .map(|(alpha2, _, _, _)| *alpha2)
+}
+
+/// Tags found at the end of a subtitle's name, as in `Movie.en.forced` or `2_English`.
+#[derive(Debug, Default, PartialEq)]
+pub struct SubtitleTags {
+    pub lang: Option<&'static str>,
+    /// Only the foreign parts and signs are subtitled.
+    pub forced: bool,
+    /// Subtitles for the deaf and hard of hearing.
+    pub sdh: bool,
+}
+
+impl SubtitleTags {
+    /// Only trailing tokens are looked at, so that a movie title is not mistaken for a language.
+    ///
+    /// The first token is the title, as in `It.srt` or `Us.srt`, unless it names a language as in `English.srt`.
+    pub fn parse(stem: &str) -> SubtitleTags {
+        let mut tags = SubtitleTags::default();
+        let tokens: Vec<&str> = stem.split(&['.', '_', ' ', '-'][..]).collect();
+
+        for (pos, token) in tokens.iter().enumerate().rev() {
+            let token = token.to_lowercase();
+            if pos == 0 {
+                if tags.lang.is_none() {
+                    tags.lang = LANGUAGES
+                        .iter()
+                        .find(|(_, _, _, name)| token == *name)
+                        .map(|(_, alpha3, _, _)| *alpha3);
+                }
+                break;
+            }
+
+            match token.as_str() {
+                "forced" => tags.forced = true,
+                "sdh" | "cc" => tags.sdh = true,
+                token => match parse_lang_tag(token) {
+                    Some(lang) if tags.lang.is_none() => tags.lang = Some(lang),
+                    _ => break,
+                },
+            }
+        }
+
+        tags
+    }
+}
+
+/// Confidence given to a language tag from a file name.
+const TAG_CONFIDENCE: f64 = 0.9;
+
+/// Detection on less text than this is less reliable, as with forced subtitles.
+const MIN_DETECT_LEN: usize = 500;
+
+/// Reconcile the language from the file name tags with the language detected from the text.
+///
+/// Returns the language and the confidence in it.
+pub fn reconcile_lang<'a>(
+    tag: Option<&'a str>,
+    detected: Option<(&'a str, f64)>,
+    text_len: usize,
+) -> Option<(&'a str, f64)> {
+    let detected = detected.map(|(lang, confidence)| {
+        let scale = (text_len as f64 / MIN_DETECT_LEN as f64).min(1.0);
+        (lang, confidence * scale)
+    });
+
+    match (tag, detected) {
+        (Some(tag), Some((lang, _))) if tag == lang => Some((tag, 1.0)),
+        (Some(tag), Some((lang, confidence))) => {
+            if confidence > TAG_CONFIDENCE {
+                Some((lang, confidence))
+            } else {
+                Some((tag, TAG_CONFIDENCE))
+            }
+        }
+        (Some(tag), None) => Some((tag, TAG_CONFIDENCE)),
+        (None, detected) => detected,
+    }
+}
+
+#[test]
+fn test_parse_lang_tag() {
+    assert_eq!(parse_lang_tag("en"), Some("eng"));
+    assert_eq!(parse_lang_tag("ENG"), Some("eng"));
+    assert_eq!(parse_lang_tag("fre"), Some("fra"));
+    assert_eq!(parse_lang_tag("French"), Some("fra"));
+    assert_eq!(parse_lang_tag("xx"), None);
+}
+
+#[test]
+fn test_subtitle_tags() {
+    let tags = |stem| {
+        let tags = SubtitleTags::parse(stem);
+        (tags.lang, tags.forced, tags.sdh)
+    };
+    assert_eq!(tags("Movie.2001.en"), (Some("eng"), false, false));
+    assert_eq!(tags("Movie.2001.eng.forced"), (Some("eng"), true, false));
+    assert_eq!(tags("Movie.2001.fr.sdh"), (Some("fra"), false, true));
+    assert_eq!(tags("2_English"), (Some("eng"), false, false));
+    assert_eq!(tags("Movie.2001"), (None, false, false));
+    assert_eq!(tags("English"), (Some("eng"), false, false));
+    assert_eq!(tags("It"), (None, false, false));
+    assert_eq!(tags("Us"), (None, false, false));
+    assert_eq!(tags("It.en"), (Some("eng"), false, false));
+}
+
+#[test]
+fn test_reconcile_lang() {
+    assert_eq!(reconcile_lang(Some("eng"), Some(("eng", 0.5)), 100), Some(("eng", 1.0)));
+    // short forced subtitles are often misdetected
+    assert_eq!(reconcile_lang(Some("eng"), Some(("spa", 1.0)), 50), Some(("eng", 0.9)));
+    assert_eq!(
+        reconcile_lang(Some("eng"), Some(("fra", 1.0)), 5000),
+        Some(("fra", 1.0))
+    );
+    assert_eq!(reconcile_lang(None, Some(("fra", 1.0)), 250), Some(("fra", 0.5)));
+    assert_eq!(reconcile_lang(None, None, 250), None);
+}
@@ -1,8 +1,21 @@
+mod cache;
+mod ignore;
+mod lang;
 mod paths;
+mod quality;
+mod release;
 mod scan;
 mod tree;
 mod vfs;
 
-pub use self::scan::{MovieFile, MovieIdentity, PathSize, Scanner, SubtitleFile};
-pub use self::vfs::walk;
+pub use self::cache::ScanCache;
+pub use self::ignore::IgnoreRules;
+pub use self::lang::{alpha2, parse_lang_tag};
+pub use self::paths::os_path;
 pub use self::paths::{AbsPath, RelPath};
+pub use self::quality::Quality;
+pub use self::scan::{
+    probe_streams, rank_subtitles, AudioInfo, ExtraFile, LinkFile, MovieFile, MovieIdentity, PathSize, Scanner,
+    SubFormat, SubtitleFile, VideoInfo,
+};
+pub use self::vfs::{walk, SymlinkPolicy};
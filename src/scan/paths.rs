@@ -1,41 +1,44 @@
+//! Paths of scanned and imported files.
+//!
+//! Paths are kept as raw OS strings, names which are not UTF-8 are only decoded to be displayed
+//! or parsed.
+
+use std::borrow::Cow;
+use std::ffi::OsString;
 use std::fmt;
 use std::ops::{Deref, DerefMut};
 use std::path::{Path, PathBuf};
 
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::utils;
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct AbsPath(PathBuf);
 
 impl AbsPath {
-    pub fn new(path: impl Into<PathBuf>) -> Option<AbsPath> {
+    pub fn new(path: impl Into<PathBuf>) -> AbsPath {
         let buf = path.into();
         if !buf.is_absolute() {
             panic!("given path is not absolute");
         }
-        if buf.to_str().is_some() {
-            Some(AbsPath(buf))
-        } else {
-            None
-        }
+        AbsPath(buf)
     }
 
     pub fn as_path(&self) -> &Path {
         self.0.as_path()
     }
 
-    pub fn as_str(&self) -> &str {
-        self.0.to_str().unwrap()
-    }
-
-    pub fn file_name(&self) -> &str {
-        self.0.file_name().map(|s| s.to_str().unwrap()).unwrap_or("")
+    pub fn file_name(&self) -> Cow<'_, str> {
+        self.0.file_name().map(utils::decode_file_name).unwrap_or_default()
     }
 
-    pub fn file_stem(&self) -> &str {
-        self.0.file_stem().map(|s| s.to_str().unwrap()).unwrap_or("")
+    pub fn file_stem(&self) -> Cow<'_, str> {
+        self.0.file_stem().map(utils::decode_file_name).unwrap_or_default()
     }
 
-    pub fn extension(&self) -> &str {
-        self.0.extension().map(|s| s.to_str().unwrap()).unwrap_or("")
+    pub fn extension(&self) -> Cow<'_, str> {
+        self.0.extension().map(utils::decode_file_name).unwrap_or_default()
     }
 }
 
@@ -59,12 +62,6 @@ impl AsRef<Path> for AbsPath {
     }
 }
 
-impl AsRef<str> for AbsPath {
-    fn as_ref(&self) -> &str {
-        self.as_str()
-    }
-}
-
 impl fmt::Display for AbsPath {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         self.display().fmt(f)
@@ -75,16 +72,12 @@ impl fmt::Display for AbsPath {
 pub struct RelPath(PathBuf);
 
 impl RelPath {
-    pub fn new(path: impl Into<PathBuf>) -> Option<RelPath> {
+    pub fn new(path: impl Into<PathBuf>) -> RelPath {
         let buf = path.into();
         if buf.is_absolute() {
             panic!("given path is absolute");
         }
-        if buf.to_str().is_some() {
-            Some(RelPath(buf))
-        } else {
-            None
-        }
+        RelPath(buf)
     }
 
     pub fn from_string(path: String) -> RelPath {
@@ -102,20 +95,16 @@ impl RelPath {
         self.0.as_path()
     }
 
-    pub fn as_str(&self) -> &str {
-        self.0.to_str().unwrap()
+    pub fn file_name(&self) -> Cow<'_, str> {
+        self.0.file_name().map(utils::decode_file_name).unwrap_or_default()
     }
 
-    pub fn file_name(&self) -> &str {
-        self.0.file_name().map(|s| s.to_str().unwrap()).unwrap_or("")
+    pub fn file_stem(&self) -> Cow<'_, str> {
+        self.0.file_stem().map(utils::decode_file_name).unwrap_or_default()
     }
 
-    pub fn file_stem(&self) -> &str {
-        self.0.file_stem().map(|s| s.to_str().unwrap()).unwrap_or("")
-    }
-
-    pub fn extension(&self) -> &str {
-        self.0.extension().map(|s| s.to_str().unwrap()).unwrap_or("")
+    pub fn extension(&self) -> Cow<'_, str> {
+        self.0.extension().map(utils::decode_file_name).unwrap_or_default()
     }
 }
 
@@ -139,25 +128,33 @@ impl AsRef<Path> for RelPath {
     }
 }
 
-impl AsRef<str> for RelPath {
-    fn as_ref(&self) -> &str {
-        self.as_str()
-    }
-}
-
 impl fmt::Display for RelPath {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         self.display().fmt(f)
     }
 }
 
+/// Serialization of paths as OS strings, unlike the serialization of `PathBuf` it does not fail
+/// on paths which are not UTF-8.
+pub mod os_path {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+        path.as_os_str().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PathBuf, D::Error> {
+        OsString::deserialize(deserializer).map(PathBuf::from)
+    }
+}
+
 #[test]
 fn test_rel_path_new() {
-    RelPath::new("foo.txt").unwrap();
+    RelPath::new("foo.txt");
 }
 
 #[test]
 #[should_panic]
 fn test_rel_path_new_fail() {
-    RelPath::new("/foo.txt").unwrap();
+    RelPath::new("/foo.txt");
 }
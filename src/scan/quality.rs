@@ -0,0 +1,103 @@
+//! Quality of the copies of a movie, to replace the library copy by a better one.
+
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use super::scan::{probe_streams, VideoInfo};
+use crate::error::Result;
+
+/// How much more a codec compresses than H.264 for the same picture.
+fn codec_efficiency(codec: &str) -> f64 {
+    match codec {
+        "hevc" | "av1" => 2.0,
+        "vp9" => 1.6,
+        "mpeg4" | "msmpeg4v3" | "wmv3" => 0.7,
+        "mpeg2video" => 0.5,
+        _ => 1.0,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quality {
+    /// Resolution of the main video stream, see `VideoInfo::resolution`.
+    pub resolution: u32,
+    pub codec: String,
+    /// Bits per second of the whole file.
+    pub bit_rate: Option<u64>,
+    pub size: u64,
+}
+
+impl Quality {
+    /// Quality of a file from its video streams, duration in milliseconds and size.
+    pub fn new(video: &[VideoInfo], duration: Option<i64>, size: u64) -> Quality {
+        let main = video.iter().max_by_key(|v| v.width * v.height);
+        Quality {
+            resolution: main.map_or(0, |v| v.resolution()),
+            codec: main.map(|v| v.codec.clone()).unwrap_or_default(),
+            bit_rate: duration.filter(|&d| d > 0).map(|d| size * 8 * 1000 / d as u64),
+            size,
+        }
+    }
+
+    /// Probe a file with a given ffprobe binary to find its quality.
+    pub fn probe(ffprobe: impl AsRef<OsStr>, path: impl AsRef<Path>) -> Result<Quality> {
+        let path = path.as_ref();
+        let (video, _, duration) = probe_streams(ffprobe, path)?;
+        Ok(Quality::new(&video, duration, fs::metadata(path)?.len()))
+    }
+
+    /// Bit rate a H.264 stream would need for the same picture.
+    fn weighted_bit_rate(&self) -> Option<f64> {
+        self.bit_rate
+            .map(|bit_rate| bit_rate as f64 * codec_efficiency(&self.codec))
+    }
+
+    /// Compare the resolution, then the bit rate weighted by the efficiency of the codec, then the size.
+    pub fn is_better_than(&self, other: &Quality) -> bool {
+        if self.resolution != other.resolution {
+            return self.resolution > other.resolution;
+        }
+        match (self.weighted_bit_rate(), other.weighted_bit_rate()) {
+            (Some(bit_rate), Some(other_bit_rate)) if bit_rate != other_bit_rate => bit_rate > other_bit_rate,
+            _ => self.size > other.size,
+        }
+    }
+}
+
+impl fmt::Display for Quality {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}p {}", self.resolution, self.codec)?;
+        if let Some(bit_rate) = self.bit_rate {
+            write!(f, ", {:.1} Mb/s", bit_rate as f64 / 1_000_000.0)?;
+        }
+        write!(f, ", {:.2} GiB", self.size as f64 / (1024 * 1024 * 1024) as f64)
+    }
+}
+
+#[test]
+fn test_quality() {
+    let video = |codec: &str, width, height| VideoInfo {
+        codec: codec.into(),
+        width,
+        height,
+        bit_depth: None,
+        hdr: None,
+    };
+    const GIB: u64 = 1024 * 1024 * 1024;
+    let two_hours = Some(2 * 3600 * 1000);
+
+    let dvd = Quality::new(&[video("mpeg2video", 720, 576)], two_hours, 8 * GIB);
+    let bluray = Quality::new(&[video("h264", 1920, 800)], two_hours, 8 * GIB);
+    let small_hevc = Quality::new(&[video("hevc", 1920, 1080)], two_hours, 5 * GIB);
+    let uhd = Quality::new(&[video("hevc", 3840, 1600)], two_hours, 4 * GIB);
+
+    assert_eq!(bluray.resolution, 1080);
+    assert!(bluray.is_better_than(&dvd));
+    assert!(small_hevc.is_better_than(&bluray));
+    assert!(!bluray.is_better_than(&small_hevc));
+    assert!(uhd.is_better_than(&small_hevc));
+    assert!(!bluray.is_better_than(&bluray));
+    assert_eq!(uhd.to_string(), "2160p hevc, 4.8 Mb/s, 4.00 GiB");
+}
@@ -0,0 +1,423 @@
+use std::borrow::Cow;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::scan::{parse_episode_token, parse_part_token};
+
+/// Information extracted from a scene or P2P style release name.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct ReleaseInfo {
+    pub title: String,
+    pub year: Option<i32>,
+    pub resolution: Option<String>,
+    pub source: Option<String>,
+    pub codec: Option<String>,
+    pub edition: Option<String>,
+    pub group: Option<String>,
+    pub repack: bool,
+    pub proper: bool,
+}
+
+type Patterns = &'static [(&'static [&'static str], &'static str)];
+
+// Patterns are matched in order, multi-token patterns must come before their prefixes.
+
+const RESOLUTIONS: Patterns = &[
+    (&["480p"], "480p"),
+    (&["576p"], "576p"),
+    (&["720p"], "720p"),
+    (&["1080p"], "1080p"),
+    (&["1080i"], "1080i"),
+    (&["2160p"], "2160p"),
+    (&["4k"], "2160p"),
+    (&["uhd"], "2160p"),
+];
+
+const SOURCES: Patterns = &[
+    (&["blu", "ray"], "BluRay"),
+    (&["bluray"], "BluRay"),
+    (&["bdrip"], "BDRip"),
+    (&["brrip"], "BRRip"),
+    (&["bdremux"], "Remux"),
+    (&["remux"], "Remux"),
+    (&["web", "dl"], "WEB-DL"),
+    (&["webdl"], "WEB-DL"),
+    (&["web", "rip"], "WEBRip"),
+    (&["webrip"], "WEBRip"),
+    (&["web"], "WEB"),
+    (&["hdtv"], "HDTV"),
+    (&["pdtv"], "PDTV"),
+    (&["dvdrip"], "DVDRip"),
+    (&["dvdscr"], "DVDScr"),
+    (&["dvd"], "DVD"),
+    (&["hdrip"], "HDRip"),
+    (&["hdcam"], "CAM"),
+    (&["cam"], "CAM"),
+    (&["telesync"], "TS"),
+];
+
+const CODECS: Patterns = &[
+    (&["x264"], "H.264"),
+    (&["h264"], "H.264"),
+    (&["h", "264"], "H.264"),
+    (&["avc"], "H.264"),
+    (&["x265"], "H.265"),
+    (&["h265"], "H.265"),
+    (&["h", "265"], "H.265"),
+    (&["hevc"], "H.265"),
+    (&["xvid"], "XviD"),
+    (&["divx"], "DivX"),
+    (&["av1"], "AV1"),
+    (&["vc1"], "VC-1"),
+    (&["vc", "1"], "VC-1"),
+    (&["mpeg2"], "MPEG-2"),
+];
+
+const EDITIONS: Patterns = &[
+    (&["director's", "cut"], "Director's Cut"),
+    (&["directors", "cut"], "Director's Cut"),
+    (&["extended", "cut"], "Extended"),
+    (&["extended", "edition"], "Extended"),
+    (&["extended"], "Extended"),
+    (&["final", "cut"], "Final Cut"),
+    (&["theatrical", "cut"], "Theatrical"),
+    (&["theatrical"], "Theatrical"),
+    (&["unrated"], "Unrated"),
+    (&["uncut"], "Uncut"),
+    (&["remastered"], "Remastered"),
+    (&["special", "edition"], "Special Edition"),
+    (&["ultimate", "edition"], "Ultimate Edition"),
+    (&["criterion"], "Criterion"),
+    (&["imax"], "IMAX"),
+];
+
+/// Tokens that end the title but carry no information we keep.
+const NOISE: &[&str] = &[
+    "hdr", "hdr10", "dv", "10bit", "8bit", "dts", "ac3", "aac", "dd5", "ddp5", "atmos", "truehd", "flac", "mp3",
+    "multi", "dual", "subbed", "dubbed", "limited", "internal", "hc", "rerip", "nf", "amzn",
+];
+
+struct Token<'t> {
+    text: &'t str,
+    lower: String,
+    /// Inside square brackets, never part of the title.
+    bracketed: bool,
+    /// Directly preceded by a dash, a potential release group.
+    after_dash: bool,
+}
+
+fn is_separator(c: char) -> bool {
+    match c {
+        c if c.is_whitespace() => true,
+        '_' | '-' | '.' | '(' | ')' | '[' | ']' | '{' | '}' | ':' | ',' | '+' => true,
+        _ => false,
+    }
+}
+
+fn tokenize(name: &str) -> (Vec<Token<'_>>, Vec<&str>) {
+    let mut tokens = vec![];
+    let mut brackets = vec![];
+
+    let mut depth = 0;
+    let mut bracket_start = 0;
+    let mut start = None;
+    let mut last_sep = None;
+
+    for (pos, c) in name.char_indices().chain(Some((name.len(), ' '))) {
+        if is_separator(c) {
+            if let Some(start) = start.take() {
+                let text = &name[start..pos];
+                tokens.push(Token {
+                    text,
+                    lower: text.to_lowercase(),
+                    bracketed: depth > 0,
+                    after_dash: last_sep == Some('-'),
+                });
+            }
+
+            match c {
+                '[' => {
+                    depth += 1;
+                    bracket_start = pos + 1;
+                }
+                ']' if depth > 0 => {
+                    depth -= 1;
+                    brackets.push(&name[bracket_start..pos]);
+                }
+                _ => {}
+            }
+
+            last_sep = Some(c);
+        } else if start.is_none() {
+            start = Some(pos);
+        }
+    }
+
+    (tokens, brackets)
+}
+
+fn is_year(token: &str) -> bool {
+    token.len() == 4 && token.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Find the first pattern that matches the tokens starting at `pos`.
+fn match_at(tokens: &[Token], pos: usize, patterns: Patterns) -> Option<(usize, &'static str)> {
+    for (pattern, value) in patterns {
+        if tokens.len() - pos >= pattern.len() && pattern.iter().zip(&tokens[pos..]).all(|(p, t)| *p == t.lower) {
+            return Some((pattern.len(), value));
+        }
+    }
+    None
+}
+
+/// Take the `{edition-Name}` tag out of a name, as written by imports, it can name any edition.
+fn split_edition_tag(name: &str) -> (Cow<'_, str>, Option<String>) {
+    const PREFIX: &str = "{edition-";
+
+    let start = match name.find(PREFIX) {
+        Some(start) => start,
+        None => return (Cow::Borrowed(name), None),
+    };
+    let end = match name[start..].find('}') {
+        Some(end) => start + end,
+        None => return (Cow::Borrowed(name), None),
+    };
+
+    let edition = name[start + PREFIX.len()..end].trim();
+    let rest = format!("{} {}", &name[..start], &name[end + 1..]);
+    (Cow::Owned(rest), Some(edition.to_owned()).filter(|e| !e.is_empty()))
+}
+
+impl ReleaseInfo {
+    pub fn parse(name: &str) -> ReleaseInfo {
+        let (name, edition) = split_edition_tag(name);
+        let (mut tokens, brackets) = tokenize(&name);
+        let mut info = ReleaseInfo::default();
+
+        // A trailing "-GROUP" right after a tag is the release group, e.g. "x264-GROUP".
+        if let Some(last) = tokens.iter().rposition(|t| !t.bracketed) {
+            if last > 0 && tokens[last].after_dash && tokens[last].text.chars().all(|c| c.is_alphanumeric()) {
+                let prev = &tokens[last - 1];
+                let prev_is_tag = [RESOLUTIONS, SOURCES, CODECS, EDITIONS]
+                    .iter()
+                    .any(|patterns| patterns.iter().any(|(p, _)| p.last() == Some(&prev.lower.as_str())))
+                    || NOISE.contains(&prev.lower.as_str())
+                    || is_year(&prev.lower);
+                if prev_is_tag {
+                    info.group = Some(tokens.remove(last).text.to_owned());
+                }
+            }
+        }
+
+        // Otherwise a leading "[GROUP]" names the group or the site it comes from.
+        if info.group.is_none() && tokens.first().map(|t| t.bracketed).unwrap_or(false) {
+            info.group = brackets.first().map(|s| s.to_string());
+        }
+
+        let mut title_end = None;
+        let mut has_title_token = false;
+        let mut year_pos = None;
+
+        let mut pos = 0;
+        while pos < tokens.len() {
+            let token = &tokens[pos];
+            let mut len = 1;
+            let mut is_tag = true;
+
+            if let Some((n, value)) = match_at(&tokens, pos, RESOLUTIONS) {
+                info.resolution.get_or_insert_with(|| value.to_owned());
+                len = n;
+            } else if let Some((n, value)) = match_at(&tokens, pos, SOURCES) {
+                info.source.get_or_insert_with(|| value.to_owned());
+                len = n;
+            } else if let Some((n, value)) = match_at(&tokens, pos, CODECS) {
+                info.codec.get_or_insert_with(|| value.to_owned());
+                len = n;
+            } else if token.lower == "repack" {
+                info.repack = true;
+            } else if token.lower == "proper" {
+                info.proper = true;
+            } else if NOISE.contains(&token.lower.as_str())
+                || parse_episode_token(&token.lower).is_some()
+                || parse_part_token(&token.lower).is_some()
+            {
+                // Only ends the title.
+            } else {
+                is_tag = false;
+                // The year is the last year-looking token that has a title before it,
+                // so that titles such as "1917 (2019)" keep their number. Once a tag has
+                // ended the title, as in "Heat.REPACK.1995", the first one is kept.
+                if is_year(&token.lower) && has_title_token && (title_end.is_none() || year_pos.is_none()) {
+                    year_pos = Some(pos);
+                }
+                if !token.bracketed && title_end.is_none() {
+                    has_title_token = true;
+                }
+            }
+
+            // Tags only end the title once there is a title.
+            if is_tag && has_title_token && title_end.is_none() && !token.bracketed {
+                title_end = Some(pos);
+            }
+
+            pos += len;
+        }
+
+        if let Some(year_pos) = year_pos {
+            info.year = tokens[year_pos].lower.parse().ok();
+            title_end = Some(title_end.map_or(year_pos, |end| end.min(year_pos)));
+        }
+
+        // Edition words are only editions once the title has ended, "Uncut Gems" and "The Final Cut" are titles.
+        if let Some(end) = title_end {
+            let mut pos = end;
+            while pos < tokens.len() && info.edition.is_none() {
+                match match_at(&tokens, pos, EDITIONS) {
+                    Some((_, value)) => info.edition = Some(value.to_owned()),
+                    None => pos += 1,
+                }
+            }
+        }
+
+        let title_end = title_end.unwrap_or(tokens.len());
+        info.title = tokens[..title_end]
+            .iter()
+            .filter(|t| !t.bracketed)
+            .map(|t| t.text)
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        if edition.is_some() {
+            info.edition = edition;
+        }
+
+        info
+    }
+
+    /// Tags describing the release, without the title and year.
+    pub fn tags(&self) -> Vec<String> {
+        let mut tags: Vec<String> = vec![];
+        tags.extend(self.resolution.clone());
+        tags.extend(self.source.clone());
+        tags.extend(self.codec.clone());
+        tags.extend(self.edition.clone());
+        if self.repack {
+            tags.push("REPACK".into());
+        }
+        if self.proper {
+            tags.push("PROPER".into());
+        }
+        if let Some(group) = &self.group {
+            tags.push(format!("group {}", group));
+        }
+        tags
+    }
+}
+
+impl fmt::Display for ReleaseInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.tags().join(", "))
+    }
+}
+
+#[test]
+fn test_parse_release_names() {
+    let table = vec![
+        (
+            "[YTS] Heat 1995 1080p BluRay x264-GROUP",
+            "Heat",
+            Some(1995),
+            "1080p, BluRay, H.264, group GROUP",
+        ),
+        ("Movie.2010.REPACK.PROPER", "Movie", Some(2010), "REPACK, PROPER"),
+        ("American.Psycho.[1999]", "American Psycho", Some(1999), ""),
+        ("2001: A Space Odyssey (1968)", "2001 A Space Odyssey", Some(1968), ""),
+        ("1981.(2009)", "1981", Some(2009), ""),
+        (
+            "Blade.Runner.1982.The.Final.Cut.1080p.BluRay.DTS.x264-ESiR",
+            "Blade Runner",
+            Some(1982),
+            "1080p, BluRay, H.264, Final Cut, group ESiR",
+        ),
+        (
+            "Apocalypse.Now.1979.Directors.Cut.720p.BRRip.XviD.AC3-RARBG",
+            "Apocalypse Now",
+            Some(1979),
+            "720p, BRRip, XviD, Director's Cut, group RARBG",
+        ),
+        (
+            "The Lord of the Rings The Two Towers (2002) Extended Edition 2160p UHD BluRay x265 10bit HDR",
+            "The Lord of the Rings The Two Towers",
+            Some(2002),
+            "2160p, BluRay, H.265, Extended",
+        ),
+        (
+            "Spider-Man.2002.WEB-DL.H.264",
+            "Spider Man",
+            Some(2002),
+            "WEB-DL, H.264",
+        ),
+        (
+            "Alien (1979) [Remastered] [1080p] [BluRay]",
+            "Alien",
+            Some(1979),
+            "1080p, BluRay, Remastered",
+        ),
+        (
+            "Blade.Runner.2049.2017.2160p.WEBRip.HEVC-GROUP",
+            "Blade Runner 2049",
+            Some(2017),
+            "2160p, WEBRip, H.265, group GROUP",
+        ),
+        (
+            "the.matrix.1999.proper.dvdrip.divx-grp",
+            "the matrix",
+            Some(1999),
+            "DVDRip, DivX, PROPER, group grp",
+        ),
+        ("Heat.1080p.BluRay", "Heat", None, "1080p, BluRay"),
+        (
+            "The.Wire.S01E02.720p.HDTV.x264-LOL",
+            "The Wire",
+            None,
+            "720p, HDTV, H.264, group LOL",
+        ),
+        ("Extended", "Extended", None, ""),
+        ("Uncut Gems (2019)", "Uncut Gems", Some(2019), ""),
+        ("The Final Cut (2004)", "The Final Cut", Some(2004), ""),
+        ("Heat.1080p.Extended", "Heat", None, "1080p, Extended"),
+        ("Heat.REPACK.1995.1080p", "Heat", Some(1995), "1080p, REPACK"),
+        (
+            "Kingdom.of.Heaven.Directors.Cut.2005.1080p",
+            "Kingdom of Heaven Directors Cut",
+            Some(2005),
+            "1080p",
+        ),
+        (
+            "Blade.Runner.The.Final.Cut.1982",
+            "Blade Runner The Final Cut",
+            Some(1982),
+            "",
+        ),
+        (
+            "Heat.(1995) {edition-Director's Cut}",
+            "Heat",
+            Some(1995),
+            "Director's Cut",
+        ),
+        (
+            "Alien (1979) {edition-4K Restoration} 1080p",
+            "Alien",
+            Some(1979),
+            "1080p, 4K Restoration",
+        ),
+    ];
+
+    for (name, title, year, tags) in table {
+        let info = ReleaseInfo::parse(name);
+        assert_eq!(info.title, title, "title of {}", name);
+        assert_eq!(info.year, year, "year of {}", name);
+        assert_eq!(info.to_string(), tags, "tags of {}", name);
+    }
+}
@@ -1,7 +1,11 @@
+use std::cmp::Ordering;
 use std::collections::HashSet;
-use std::fs;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
 use std::io::BufReader;
+use std::mem;
 use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
 
 use chardet;
 use encoding_rs::Encoding;
@@ -10,20 +14,54 @@ use serde::{Deserialize, Serialize};
 use subparse::{self, SubtitleFormat};
 use whatlang;
 
+use super::cache::ScanCache;
+use super::lang::{parse_lang_tag, reconcile_lang, SubtitleTags};
+use super::paths::os_path;
+use super::release::ReleaseInfo;
 use super::vfs::File;
-use crate::error::Result;
-use crate::index::{Index, Scored, Title};
-use crate::io::{fingerprint, Fingerprint};
-use crate::utils::SafeBuffer;
+use crate::error::{Error, Result};
+use crate::index::{Episode, Index, Scored, Title, TitleId, TitleKind};
+use crate::io::{archive, fingerprint, Fingerprint};
+use crate::service::ffprobe::{self, MediaInfo};
+use crate::utils::{self, NonNan, SafeBuffer};
 
 lazy_static! {
     static ref VIDEO_EXT: Vec<&'static str> =
         vec!["mkv", "mp4", "avi", "m4v", "webm", "flv", "vob", "mov", "wmv", "ogv", "ogg"];
-    static ref SUBTITLE_EXT: Vec<&'static str> = vec!["srt", "sub", "ssa", "ass"];
+    static ref SUBTITLE_EXT: Vec<&'static str> = vec!["srt", "sub", "ssa", "ass", "idx", "sup"];
 }
 
+/// Minimum name similarity for a subtitle to be assigned to a movie by name.
+const MIN_SUBTITLE_SIMILARITY: f64 = 0.5;
+/// Minimum similarity difference with the second best movie.
+const MIN_SUBTITLE_MARGIN: f64 = 0.2;
+/// Subtitles can end a few minutes after the runtime listed by IMDB, in milliseconds.
+const RUNTIME_MARGIN: i64 = 5 * 60 * 1000;
+
+/// Subtitles can end a little after the duration found by ffprobe, in milliseconds.
+const DURATION_MARGIN: i64 = 60 * 1000;
+
+/// Frame rate of most movies, used for frame-based subtitles until the movie's frame rate is known.
+const DEFAULT_FRAME_RATE: f64 = 24000.0 / 1001.0;
+
+/// ISO 639-2 code of subtitles whose language could not be found.
+const UNKNOWN_LANG: &str = "und";
+
+/// NFO files larger than this are not release descriptions.
+const MAX_NFO_SIZE: u64 = 1024 * 1024;
+
+/// Extensions appended by download clients to the files they are still writing, as in `Movie.mkv.part`.
+const PARTIAL_DOWNLOAD_EXT: &[&str] = &["part", "!qb", "crdownload"];
+
+/// Movies lasting less than this fraction of their runtime are considered truncated.
+const MIN_RUNTIME_RATIO: f64 = 0.5;
+
+/// Prefix of the directories where downloads are being unpacked.
+const UNPACK_DIR_PREFIX: &str = "_UNPACK_";
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct PathSize {
+    #[serde(with = "os_path")]
     pub path: PathBuf,
     pub size: u64,
 }
@@ -37,17 +75,74 @@ impl From<&File> for PathSize {
     }
 }
 
+/// Symbolic link recorded without being followed.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LinkFile {
+    #[serde(with = "os_path")]
+    pub path: PathBuf,
+    #[serde(with = "os_path")]
+    pub target: PathBuf,
+}
+
+/// The name that produced a match.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum MatchSource {
+    FileName,
+    ParentDirectory,
+    GrandparentDirectory,
+    ImdbId,
+}
+
+impl fmt::Display for MatchSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MatchSource::FileName => write!(f, "file name"),
+            MatchSource::ParentDirectory => write!(f, "parent directory"),
+            MatchSource::GrandparentDirectory => write!(f, "grandparent directory"),
+            MatchSource::ImdbId => write!(f, "id"),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct MovieIdentity {
     pub title: Title,
+    /// Set when the file is an episode, `title` is then the series.
+    pub episode: Option<Episode>,
+    pub source: MatchSource,
+}
+
+impl MovieIdentity {
+    /// Title id of the movie or of the episode, unique per library entry.
+    pub fn title_id(&self) -> TitleId {
+        match &self.episode {
+            Some(episode) => episode.title_id,
+            None => self.title.title_id,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct MovieFile {
     path: PathSize,
+    pub release: ReleaseInfo,
     pub identity: Option<Scored<MovieIdentity>>,
+    /// Whether a year or an episode number could be parsed from the file name.
+    pub parsed: bool,
     pub fingerprint: Fingerprint,
     pub subtitles: Vec<SubtitleFile>,
+    pub extras: Vec<ExtraFile>,
+    /// Other parts of a movie split across files, in order. The movie file is the first part.
+    pub parts: Vec<PartFile>,
+    /// Duration found by ffprobe in milliseconds.
+    pub duration: Option<i64>,
+    /// Set if the file is still being downloaded, it is not imported until a later scan.
+    pub pending: Option<Pending>,
+    /// Set if probing the file found that it is corrupt or truncated, it is not imported.
+    pub broken: Option<Broken>,
+    /// Streams found by ffprobe, empty if the movie was not probed.
+    pub video: Vec<VideoInfo>,
+    pub audio: Vec<AudioInfo>,
 }
 
 impl MovieFile {
@@ -69,85 +164,1331 @@ impl MovieFile {
             Some(scored) => Some(&scored.value),
         }
     }
+
+    /// Resolution of the main video stream, or else the one of the release name, such as 2160 or 1080.
+    pub fn resolution(&self) -> Option<u32> {
+        match self.video.iter().max_by_key(|v| v.width * v.height) {
+            Some(video) => Some(video.resolution()),
+            None => self
+                .release
+                .resolution
+                .as_ref()
+                .and_then(|r| r.trim_end_matches(&['p', 'i'][..]).parse().ok()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PartFile {
+    pub path: PathSize,
+    /// Part number found in the file name.
+    pub number: u16,
+    pub fingerprint: Fingerprint,
+}
+
+impl PartFile {
+    pub fn path(&self) -> &Path {
+        &self.path.path
+    }
+
+    pub fn size(&self) -> u64 {
+        self.path.size
+    }
+
+    pub fn pathsize(&self) -> &PathSize {
+        &self.path
+    }
+}
+
+/// Format of a subtitle file.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum SubFormat {
+    SubRip,
+    SubStationAlpha,
+    MicroDvd,
+    /// Images in a `.sub` file, indexed by an `.idx` file.
+    VobSub,
+    /// Blu-ray images in a `.sup` file.
+    Pgs,
+}
+
+impl SubFormat {
+    fn from_subparse(format: SubtitleFormat) -> Option<SubFormat> {
+        match format {
+            SubtitleFormat::SubRip => Some(SubFormat::SubRip),
+            SubtitleFormat::SubStationAlpha => Some(SubFormat::SubStationAlpha),
+            SubtitleFormat::MicroDVD => Some(SubFormat::MicroDvd),
+            SubtitleFormat::VobSubIdx | SubtitleFormat::VobSubSub => None,
+        }
+    }
+
+    /// Whether the timings are in frames, and depend on the frame rate of the movie.
+    pub fn is_frame_based(&self) -> bool {
+        *self == SubFormat::MicroDvd
+    }
+
+    /// Preference between subtitles of different formats, higher is better.
+    fn preference(&self) -> u8 {
+        match self {
+            SubFormat::SubRip => 4,
+            SubFormat::SubStationAlpha => 3,
+            SubFormat::MicroDvd => 2,
+            SubFormat::VobSub => 1,
+            SubFormat::Pgs => 0,
+        }
+    }
+
+    /// Name stored in the library.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubFormat::SubRip => "srt",
+            SubFormat::SubStationAlpha => "ssa",
+            SubFormat::MicroDvd => "microdvd",
+            SubFormat::VobSub => "vobsub",
+            SubFormat::Pgs => "pgs",
+        }
+    }
+}
+
+impl fmt::Display for SubFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SubtitleFile {
+    pub path: PathSize,
+    pub lang: String,
+    pub ext: String,
+    pub format: SubFormat,
+    pub forced: bool,
+    pub sdh: bool,
+    /// Confidence in the language, from the file name tags and the detection.
+    pub confidence: f64,
+    /// Number of cues, zero if they could not be read.
+    pub entries: usize,
+    /// Start of the first cue in milliseconds, if the timings could be read.
+    pub first_cue: Option<i64>,
+    /// End of the last cue in milliseconds, if the timings could be read.
+    pub last_cue: Option<i64>,
+    /// Detected character encoding of text subtitles.
+    pub encoding: Option<String>,
+    /// Frame rate used to read the timings of frame-based subtitles.
+    pub frame_rate: Option<f64>,
+    /// The `.sub` file holding the images of a VobSub subtitle, `path` being its `.idx` file.
+    pub data: Option<PathSize>,
+    /// Set when the timings do not fit the duration of the movie.
+    pub sync: Option<SyncIssue>,
+    pub fingerprint: Fingerprint,
+}
+
+/// Reason to believe a subtitle was made for another cut of the movie, or is out of sync.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum SyncIssue {
+    /// The first cue starts this long after a quarter of the movie, in milliseconds.
+    StartsLate(i64),
+    /// The last cue ends this long after the movie, in milliseconds.
+    EndsAfter(i64),
+    /// The last cue ends this long before the movie, in milliseconds.
+    EndsEarly(i64),
+}
+
+impl fmt::Display for SyncIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SyncIssue::StartsLate(ms) => write!(f, "starts {} min late", ms / 60_000),
+            SyncIssue::EndsAfter(ms) => write!(f, "ends {} min after the movie", ms / 60_000),
+            SyncIssue::EndsEarly(ms) => write!(f, "ends {} min before the movie", ms / 60_000),
+        }
+    }
+}
+
+impl SubtitleFile {
+    /// Subtitles with the same language and flags get the same name in the library.
+    fn same_kind(&self, other: &SubtitleFile) -> bool {
+        self.lang == other.lang && self.forced == other.forced && self.sdh == other.sdh
+    }
+
+    /// Share of the runtime covered by the subtitle, in percent.
+    fn coverage(&self, runtime: Option<u16>) -> u32 {
+        match (self.last_cue, runtime) {
+            (Some(last_cue), Some(runtime)) if runtime > 0 => {
+                (last_cue * 100 / (i64::from(runtime) * 60 * 1000)).clamp(0, 100) as u32
+            }
+            _ => 0,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path.path
+    }
+
+    pub fn size(&self) -> u64 {
+        self.path.size
+    }
+
+    pub fn pathsize(&self) -> &PathSize {
+        &self.path
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ExtraKind {
+    Sample,
+    Trailer,
+    Featurette,
+    BehindTheScenes,
+    DeletedScene,
+    Interview,
+    Other,
+}
+
+impl ExtraKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExtraKind::Sample => "sample",
+            ExtraKind::Trailer => "trailer",
+            ExtraKind::Featurette => "featurette",
+            ExtraKind::BehindTheScenes => "behind the scenes",
+            ExtraKind::DeletedScene => "deleted scene",
+            ExtraKind::Interview => "interview",
+            ExtraKind::Other => "other",
+        }
+    }
+}
+
+impl fmt::Display for ExtraKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ExtraFile {
+    pub path: PathSize,
+    pub kind: ExtraKind,
+    pub fingerprint: Fingerprint,
+}
+
+impl ExtraFile {
+    pub fn path(&self) -> &Path {
+        &self.path.path
+    }
+
+    pub fn size(&self) -> u64 {
+        self.path.size
+    }
+
+    pub fn pathsize(&self) -> &PathSize {
+        &self.path
+    }
+}
+
+fn is_video(file: &File) -> bool {
+    file.is_file() && VIDEO_EXT.contains(&file.ext().to_lowercase().as_str())
+}
+
+/// Check if a file is a video being downloaded, such as `Movie.mkv.part`.
+fn is_partial_video(file: &File) -> bool {
+    file.is_file()
+        && PARTIAL_DOWNLOAD_EXT.contains(&file.ext().to_lowercase().as_str())
+        && Path::new(file.stem())
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| VIDEO_EXT.contains(&ext.to_lowercase().as_str()))
+}
+
+/// Why a file is not imported yet.
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize, Serialize)]
+pub enum Pending {
+    /// The file has the extension of a partial download.
+    Partial,
+    /// The file is in a directory where a download is being unpacked.
+    Unpacking,
+    /// The file was modified during the quiet period before the scan.
+    Recent,
+}
+
+impl fmt::Display for Pending {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Pending::Partial => "partial download",
+            Pending::Unpacking => "being unpacked",
+            Pending::Recent => "modified recently",
+        })
+    }
+}
+
+/// Why probing a video found it to be unusable.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub enum Broken {
+    /// ffprobe could not open the file.
+    Unreadable,
+    NoVideo,
+    /// The file lasts far less than the runtime of its title, in minutes.
+    Truncated {
+        duration: i64,
+        runtime: i64,
+    },
+}
+
+impl fmt::Display for Broken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Broken::Unreadable => write!(f, "ffprobe could not open it"),
+            Broken::NoVideo => write!(f, "no video stream"),
+            Broken::Truncated { duration, runtime } => {
+                write!(f, "lasts {} min instead of {} min", duration, runtime)
+            }
+        }
+    }
+}
+
+/// Video stream of a movie.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct VideoInfo {
+    pub codec: String,
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: Option<u8>,
+    /// HDR format, such as `HDR10`, `HLG` or `Dolby Vision`.
+    pub hdr: Option<String>,
+}
+
+impl VideoInfo {
+    /// Height of the standard format the video fits in, such as 2160 or 1080.
+    ///
+    /// Movies cropped to a wide aspect ratio are shorter than their format, the width is checked too.
+    pub fn resolution(&self) -> u32 {
+        const FORMATS: [(u32, u32); 5] = [(3840, 2160), (1920, 1080), (1280, 720), (1024, 576), (720, 480)];
+        FORMATS
+            .iter()
+            .find(|&&(width, height)| self.width >= width * 9 / 10 || self.height >= height * 9 / 10)
+            .map_or(self.height, |&(_, height)| height)
+    }
+}
+
+impl fmt::Display for VideoInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}p {}", self.resolution(), self.codec)?;
+        if let Some(bit_depth) = self.bit_depth {
+            write!(f, " {}-bit", bit_depth)?;
+        }
+        if let Some(hdr) = &self.hdr {
+            write!(f, " {}", hdr)?;
+        }
+        Ok(())
+    }
+}
+
+/// Audio stream of a movie.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct AudioInfo {
+    pub codec: String,
+    pub channels: u32,
+    /// ISO 639-3 code of the language, if the stream is tagged with a known one.
+    pub lang: Option<String>,
+}
+
+impl fmt::Display for AudioInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.codec)?;
+        match self.channels {
+            1 => write!(f, " mono")?,
+            2 => write!(f, " stereo")?,
+            6 => write!(f, " 5.1")?,
+            8 => write!(f, " 7.1")?,
+            channels => write!(f, " {}ch", channels)?,
+        }
+        if let Some(lang) = &self.lang {
+            write!(f, " {}", lang)?;
+        }
+        Ok(())
+    }
+}
+
+/// Keep the streams of a probed movie, without its cover art.
+fn stream_info(info: &MediaInfo) -> (Vec<VideoInfo>, Vec<AudioInfo>) {
+    let video = info
+        .video
+        .iter()
+        .filter(|stream| !stream.is_attached_pic())
+        .map(|stream| VideoInfo {
+            codec: stream.codec_name.clone(),
+            width: stream.width,
+            height: stream.height,
+            bit_depth: stream.bit_depth(),
+            hdr: stream.hdr().map(String::from),
+        })
+        .collect();
+    let audio = info
+        .audio
+        .iter()
+        .map(|stream| AudioInfo {
+            codec: stream.codec_name.clone(),
+            channels: stream.channels,
+            lang: stream
+                .tags
+                .get("language")
+                .and_then(|tag| parse_lang_tag(tag))
+                .map(String::from),
+        })
+        .collect();
+    (video, audio)
+}
+
+/// Probe a file with a given ffprobe binary, returns its streams and its duration in milliseconds.
+pub fn probe_streams(ffprobe: impl AsRef<OsStr>, path: &Path) -> Result<(Vec<VideoInfo>, Vec<AudioInfo>, Option<i64>)> {
+    let info = ffprobe::scan_with(ffprobe, path)?;
+    let (video, audio) = stream_info(&info);
+    Ok((video, audio, info.duration.map(|duration| (duration * 1000.0) as i64)))
+}
+
+/// Check a probed movie for missing video streams and truncation.
+///
+/// Movies split in parts are not checked for truncation, each part is shorter than the runtime.
+fn find_broken(movie: &MovieFile, info: &MediaInfo) -> Option<Broken> {
+    if info.video.is_empty() {
+        return Some(Broken::NoVideo);
+    }
+
+    let runtime = i64::from(movie.identity()?.title.runtime);
+    let duration = (info.duration? / 60.0) as i64;
+    if movie.parts.is_empty() && runtime > 0 && (duration as f64) < runtime as f64 * MIN_RUNTIME_RATIO {
+        return Some(Broken::Truncated { duration, runtime });
+    }
+    None
+}
+
+fn find_pending(file: &File, quiet_period: Duration, now: SystemTime) -> Option<Pending> {
+    if is_partial_video(file) {
+        return Some(Pending::Partial);
+    }
+
+    let mut parent = file.parent();
+    while let Some(dir) = parent {
+        if dir.name().starts_with(UNPACK_DIR_PREFIX) {
+            return Some(Pending::Unpacking);
+        }
+        parent = dir.parent();
+    }
+
+    // files modified in the future are not pending, they would never stop being pending
+    match now.duration_since(file.mtime()) {
+        Ok(age) if age < quiet_period => Some(Pending::Recent),
+        _ => None,
+    }
+}
+
+fn is_subtitle(file: &File) -> bool {
+    file.is_file() && SUBTITLE_EXT.contains(&file.ext().to_lowercase().as_str())
+}
+
+fn token_splitter(c: char) -> bool {
+    match c {
+        c if c.is_whitespace() => true,
+        '_' | '-' | '.' | '(' | ')' | '[' | ']' | ':' => true,
+        _ => false,
+    }
+}
+
+fn text_to_tokens<'t>(text: &'t str, tokens: &mut Vec<&'t str>) {
+    tokens.clear();
+    for token in text.split(token_splitter) {
+        if !token.is_empty() {
+            tokens.push(token);
+        }
+    }
+}
+
+fn is_year(token: &str) -> bool {
+    token.len() == 4 && token.chars().all(|c| c.is_digit(10))
+}
+
+fn parse_file_name(stem: &str) -> Option<(String, i32)> {
+    let release = ReleaseInfo::parse(stem);
+    Some((release.title.to_lowercase(), release.year?))
+}
+
+#[derive(Debug, PartialEq)]
+struct EpisodeName {
+    title: String,
+    year: Option<i32>,
+    season: u16,
+    episode: u16,
+}
+
+fn is_number(text: &str, max_len: usize) -> bool {
+    !text.is_empty() && text.len() <= max_len && text.chars().all(|c| c.is_ascii_digit())
+}
+
+const PART_PREFIXES: &[&str] = &["cd", "disc", "disk", "part", "pt"];
+
+fn strip_part_prefix(text: &str) -> Option<&str> {
+    PART_PREFIXES
+        .iter()
+        .filter(|prefix| text.starts_with(*prefix))
+        .map(|prefix| &text[prefix.len()..])
+        .next()
+}
+
+/// Parse a part token such as `cd1` or `part2`.
+pub(super) fn parse_part_token(token: &str) -> Option<u16> {
+    let number = strip_part_prefix(token)?;
+    if is_number(number, 2) {
+        number.parse().ok().filter(|&n| n > 0)
+    } else {
+        None
+    }
+}
+
+/// Parse the part of a movie split across files, as in `movie.cd1.avi` or `Movie (2001) - Part 2.avi`.
+///
+/// Returns the lowercased name without the part token, used to group the parts together, and the
+/// part number.
+fn parse_part(stem: &str) -> Option<(String, u16)> {
+    let is_sep = |c: char| !c.is_alphanumeric();
+    let lower = stem.to_lowercase();
+
+    let mut found = None;
+    for (pos, _) in lower.char_indices() {
+        if pos > 0 && !lower[..pos].ends_with(is_sep) {
+            continue;
+        }
+
+        let rest = match strip_part_prefix(&lower[pos..]) {
+            Some(rest) => rest,
+            None => continue,
+        };
+        let rest = rest.strip_prefix(&[' ', '.', '_', '-'][..]).unwrap_or(rest);
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        if !is_number(&rest[..digits], 2) || rest[digits..].starts_with(char::is_alphanumeric) {
+            continue;
+        }
+
+        if let Some(number) = rest[..digits].parse().ok().filter(|&n| n > 0) {
+            let base = format!(
+                "{}{}",
+                lower[..pos].trim_end_matches(&[' ', '.', '_', '-'][..]),
+                &rest[digits..]
+            );
+            found = Some((base, number));
+        }
+    }
+
+    found
+}
+
+/// Parse a season and episode token such as `s01e02` or `1x02`.
+///
+/// Multi-episode tokens such as `s01e02e03` yield the first episode.
+pub(super) fn parse_episode_token(token: &str) -> Option<(u16, u16)> {
+    let (season, episode) = if token.starts_with('s') {
+        let pos = token.find('e')?;
+        (&token[1..pos], token[pos + 1..].split('e').next()?)
+    } else {
+        let pos = token.find('x')?;
+        (&token[..pos], &token[pos + 1..])
+    };
+
+    if is_number(season, 2) && is_number(episode, 3) {
+        Some((season.parse().ok()?, episode.parse().ok()?))
+    } else {
+        None
+    }
+}
+
+fn parse_episode_name(stem: &str) -> Option<EpisodeName> {
+    let stem = stem.to_lowercase();
+    let mut tokens = Vec::new();
+    text_to_tokens(&stem, &mut tokens);
+
+    let (pos, (season, episode)) = tokens
+        .iter()
+        .enumerate()
+        .find_map(|(pos, t)| parse_episode_token(t).map(|se| (pos, se)))?;
+
+    let mut title_tokens = &tokens[..pos];
+    let mut year = None;
+
+    // Series sharing a name are often disambiguated with the year, e.g. "Doctor Who 2005 S01E01".
+    if let Some((last, rest)) = title_tokens.split_last() {
+        if is_year(last) && !rest.is_empty() {
+            year = last.parse().ok();
+            title_tokens = rest;
+        }
+    }
+
+    if title_tokens.is_empty() {
+        return None;
+    }
+
+    Some(EpisodeName {
+        title: title_tokens.join(" "),
+        year,
+        season,
+        episode,
+    })
+}
+
+fn find_movie(index: &Index, title: &str, year: Option<i32>, source: MatchSource) -> Option<Scored<MovieIdentity>> {
+    let scored = index.find(title, year)?;
+
+    Some(Scored::new(
+        scored.score,
+        MovieIdentity {
+            title: scored.value.clone(),
+            episode: None,
+            source,
+        },
+    ))
+}
+
+/// Minimum score of a match made without a year.
+const YEARLESS_MIN_SCORE: f64 = 0.80;
+/// Minimum score difference between the best and the second best match made without a year.
+const YEARLESS_MIN_MARGIN: f64 = 0.10;
+
+/// Match a file whose name has no year.
+///
+/// Without a year, remakes and titles sharing a name cannot be told apart, so the best
+/// match is only kept when it is both close to the name and clearly ahead of the others.
+fn find_movie_without_year(index: &Index, title: &str, source: MatchSource) -> Option<Scored<MovieIdentity>> {
+    if title.is_empty() {
+        return None;
+    }
+
+    let candidates = index.find_all(title, None);
+    let best = candidates.first()?;
+
+    if *best.score < YEARLESS_MIN_SCORE {
+        return None;
+    }
+
+    if let Some(second) = candidates.get(1) {
+        if *best.score - *second.score < YEARLESS_MIN_MARGIN {
+            return None;
+        }
+    }
+
+    Some(Scored::new(
+        best.score,
+        MovieIdentity {
+            title: best.value.clone(),
+            episode: None,
+            source,
+        },
+    ))
+}
+
+fn find_episode(index: &Index, name: &EpisodeName) -> Option<Scored<MovieIdentity>> {
+    let scored = index.find_series(&name.title, name.year)?;
+    let episode = index.get_episode(scored.value.title_id, name.season, name.episode)?;
+
+    Some(Scored::new(
+        scored.score,
+        MovieIdentity {
+            title: scored.value.clone(),
+            episode: Some(episode.clone()),
+            source: MatchSource::FileName,
+        },
+    ))
+}
+
+/// Find an IMDB id such as `tt1234567` in the given text.
+///
+/// This finds ids in links `imdb.com/title/tt1234567` as well as in tags such as
+/// `{imdb-tt1234567}` or `[tt1234567]`.
+fn parse_imdb_id(text: &str) -> Option<TitleId> {
+    let bytes = text.as_bytes();
+    let mut pos = 0;
+
+    while let Some(found) = text[pos..].find("tt") {
+        let start = pos + found;
+        let end = start + 2 + bytes[start + 2..].iter().take_while(|b| b.is_ascii_digit()).count();
+
+        let boundary_before = start == 0 || !bytes[start - 1].is_ascii_alphanumeric();
+        let boundary_after = end == bytes.len() || !bytes[end].is_ascii_alphanumeric();
+
+        if boundary_before && boundary_after && (7..=8).contains(&(end - start - 2)) {
+            return text[start + 2..end].parse().ok().map(TitleId::new);
+        }
+
+        pos = start + 2;
+    }
+
+    None
+}
+
+fn read_nfo_imdb_id(nfo: &File) -> Option<TitleId> {
+    if nfo.len() > MAX_NFO_SIZE {
+        return None;
+    }
+    // NFO files are often written in CP437 for the ASCII art, the link itself is plain ASCII.
+    let bytes = archive::read(nfo.path()).ok()?;
+    parse_imdb_id(&String::from_utf8_lossy(&bytes))
+}
+
+/// Find an IMDB id for a movie file, in its path or in a NFO file next to it.
+///
+/// A NFO file is used when it has the same stem as the movie or when the movie is the
+/// only video file in its folder.
+fn find_imdb_id(file: &File) -> Option<TitleId> {
+    let mut names = vec![file.stem().to_owned()];
+    if let Some(parent) = file.parent() {
+        names.push(parent.name().to_owned());
+        if let Some(grandparent) = parent.parent() {
+            names.push(grandparent.name().to_owned());
+        }
+    }
+
+    if let Some(title_id) = names.iter().find_map(|name| parse_imdb_id(name)) {
+        return Some(title_id);
+    }
+
+    let alone = !file.siblings().any(|s| is_video(&s));
+
+    file.siblings()
+        .filter(|s| s.is_file() && s.ext().eq_ignore_ascii_case("nfo"))
+        .filter(|s| alone || s.stem() == file.stem())
+        .find_map(|nfo| read_nfo_imdb_id(&nfo))
+}
+
+/// Resolve a movie through an IMDB id, skipping fuzzy matching entirely.
+///
+/// Ids that are not in the index, or that are not movies, are ignored.
+fn find_movie_by_id(index: &Index, file: &File) -> Option<Scored<MovieIdentity>> {
+    let title = index.get_title(find_imdb_id(file)?)?;
+
+    if title.kind != TitleKind::Movie {
+        return None;
+    }
+
+    Some(Scored::new(
+        NonNan::new(1.0),
+        MovieIdentity {
+            title: title.clone(),
+            episode: None,
+            source: MatchSource::ImdbId,
+        },
+    ))
+}
+
+/// Extras folders, as used by Plex and Jellyfin.
+fn extra_kind_from_folder(name: &str) -> Option<ExtraKind> {
+    match name.to_lowercase().as_str() {
+        "sample" | "samples" => Some(ExtraKind::Sample),
+        "trailers" => Some(ExtraKind::Trailer),
+        "featurettes" => Some(ExtraKind::Featurette),
+        "behind the scenes" => Some(ExtraKind::BehindTheScenes),
+        "deleted scenes" => Some(ExtraKind::DeletedScene),
+        "interviews" => Some(ExtraKind::Interview),
+        "extras" | "scenes" | "shorts" | "other" => Some(ExtraKind::Other),
+        _ => None,
+    }
+}
+
+fn extra_kind_from_token(token: &str) -> Option<ExtraKind> {
+    match token {
+        "sample" => Some(ExtraKind::Sample),
+        "trailer" | "trailers" | "teaser" => Some(ExtraKind::Trailer),
+        "featurette" | "featurettes" => Some(ExtraKind::Featurette),
+        "behindthescenes" => Some(ExtraKind::BehindTheScenes),
+        "deleted" => Some(ExtraKind::DeletedScene),
+        "interview" | "interviews" => Some(ExtraKind::Interview),
+        "scene" | "short" | "other" => Some(ExtraKind::Other),
+        _ => None,
+    }
+}
+
+/// Extras named like `Heat (1995)-trailer.mkv`, `Heat.1995.Deleted.Scenes.mkv` or `sample.mkv`.
+///
+/// Without a year, only the `-trailer` suffix and the sample token are trusted, so that movies
+/// such as "The Interview" are not mistaken for extras.
+fn extra_kind_from_name(stem: &str) -> Option<ExtraKind> {
+    let stem = stem.to_lowercase();
+
+    if let Some(pos) = stem.rfind('-') {
+        if let Some(kind) = extra_kind_from_token(stem[pos + 1..].trim()) {
+            return Some(kind);
+        }
+    }
+
+    let mut tokens = Vec::new();
+    text_to_tokens(&stem, &mut tokens);
+
+    // Tokens before the year belong to the title, as in "Trailer Park Boys 2014".
+    let year_pos = tokens.iter().rposition(|t| is_year(t));
+    let tokens = &tokens[year_pos.map_or(0, |pos| pos + 1)..];
+
+    for (pos, token) in tokens.iter().enumerate() {
+        let kind = match extra_kind_from_token(token) {
+            Some(ExtraKind::Sample) => ExtraKind::Sample,
+            _ if year_pos.is_none() => continue,
+            Some(ExtraKind::Other) => continue,
+            Some(kind) => kind,
+            None if *token == "behind" && tokens[pos + 1..].starts_with(&["the", "scenes"]) => {
+                ExtraKind::BehindTheScenes
+            }
+            None => continue,
+        };
+
+        return Some(kind);
+    }
+
+    None
+}
+
+/// Find if a video file is an extra, and the folder in which its movie should be.
+fn classify_extra(file: &File) -> Option<(ExtraKind, Option<File>)> {
+    let parent = file.parent();
+
+    if let Some(parent) = &parent {
+        if let Some(kind) = extra_kind_from_folder(parent.name()) {
+            return Some((kind, parent.parent()));
+        }
+    }
+
+    extra_kind_from_name(file.stem()).map(|kind| (kind, parent))
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub(super) struct MovieMatch {
+    identity: Option<Scored<MovieIdentity>>,
+    parsed: bool,
+    release: ReleaseInfo,
+}
+
+/// What the match of a movie depends on besides its own file, a cached match is made again when it changes.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub(super) struct MatchContext {
+    /// Path the movie was found at, its folder names are matched.
+    path: OsString,
+    /// Name, size and modification time of the NFO files next to the movie which were not ignored.
+    nfos: Vec<(String, u64, SystemTime)>,
+    /// The movie is the only video of its folder, so that any NFO file of the folder describes it.
+    alone: bool,
+}
+
+impl MatchContext {
+    pub(super) fn new(path: &Path, nfos: Vec<(String, u64, SystemTime)>, alone: bool) -> MatchContext {
+        MatchContext {
+            path: path.as_os_str().to_owned(),
+            nfos,
+            alone,
+        }
+    }
+
+    fn of(file: &File) -> MatchContext {
+        let nfos = file
+            .siblings()
+            .filter(|s| s.is_file() && s.ext().eq_ignore_ascii_case("nfo"))
+            .map(|s| (s.name().to_owned(), s.len(), s.mtime()))
+            .collect();
+        let alone = !file.siblings().any(|s| is_video(&s));
+        MatchContext::new(file.path(), nfos, alone)
+    }
+}
+
+/// Match a movie file using its name, then the names of its parent and grandparent directories.
+/// An IMDB id found in the path or in a NFO file takes precedence over all the names.
+///
+/// Releases often look like `Some Movie (2004)/movie.mkv` where the file name is useless,
+/// so the best match among all the names is kept. Directory names must contain a year.
+fn identify_movie(index: &Index, file: &File) -> MovieMatch {
+    if let Some(identity) = find_movie_by_id(index, file) {
+        return MovieMatch {
+            identity: Some(identity),
+            parsed: true,
+            release: ReleaseInfo::parse(file.stem()),
+        };
+    }
+
+    let mut names = vec![(MatchSource::FileName, file.stem().to_owned())];
+    if let Some(parent) = file.parent() {
+        names.push((MatchSource::ParentDirectory, parent.name().to_owned()));
+        if let Some(grandparent) = parent.parent() {
+            names.push((MatchSource::GrandparentDirectory, grandparent.name().to_owned()));
+        }
+    }
+
+    let mut result = MovieMatch {
+        identity: None,
+        parsed: false,
+        release: ReleaseInfo::parse(file.stem()),
+    };
+
+    for (source, name) in names {
+        let identity = match parse_file_name(&name) {
+            Some((title, year)) => {
+                result.parsed = true;
+                find_movie(index, &title, Some(year), source)
+            }
+            None if source == MatchSource::FileName => {
+                let title = ReleaseInfo::parse(&name).title.to_lowercase();
+                find_movie_without_year(index, &title, source)
+            }
+            None => None,
+        };
+
+        if let Some(identity) = identity {
+            let is_better = match &result.identity {
+                Some(best) => identity.score > best.score,
+                None => true,
+            };
+
+            if is_better {
+                result.identity = Some(identity);
+                result.release = ReleaseInfo::parse(&name);
+            }
+        }
+    }
+
+    result
+}
+
+type PartGroup = (Option<File>, String, Vec<(u16, usize)>);
+
+/// Merge the parts of movies split across files into the first part.
+///
+/// Parts must be in the same folder, have the same name besides the part token and the same
+/// identity.
+fn group_parts(results: Vec<(File, MovieFile)>) -> Vec<(File, MovieFile)> {
+    // (folder, name without the part token, part numbers and positions in the results)
+    let mut groups: Vec<PartGroup> = vec![];
+
+    for (pos, (file, movie)) in results.iter().enumerate() {
+        if movie.identity().and_then(|i| i.episode.as_ref()).is_some() {
+            continue;
+        }
+        if let Some((base, number)) = parse_part(file.stem()) {
+            let parent = file.parent();
+            match groups.iter_mut().find(|(p, b, _)| *p == parent && *b == base) {
+                Some((_, _, parts)) => parts.push((number, pos)),
+                None => groups.push((parent, base, vec![(number, pos)])),
+            }
+        }
+    }
+
+    let mut results: Vec<Option<(File, MovieFile)>> = results.into_iter().map(Some).collect();
+
+    for (_, _, mut parts) in groups {
+        parts.sort();
+        let same_identity = parts.windows(2).all(|w| {
+            let left = results[w[0].1]
+                .as_ref()
+                .and_then(|(_, m)| m.identity().map(|i| i.title_id()));
+            let right = results[w[1].1]
+                .as_ref()
+                .and_then(|(_, m)| m.identity().map(|i| i.title_id()));
+            w[0].0 != w[1].0 && left == right
+        });
+        if parts.len() < 2 || !same_identity {
+            continue;
+        }
+
+        let first = parts[0].1;
+        for &(number, pos) in &parts[1..] {
+            if let Some((file, _)) = results[pos].take() {
+                if let Some((_, movie)) = results[first].as_mut() {
+                    movie.parts.push(PartFile {
+                        path: From::from(&file),
+                        number,
+                        fingerprint: Fingerprint::null(),
+                    });
+                }
+            }
+        }
+    }
+
+    results.into_iter().flatten().collect()
+}
+
+/// Find the movie an extra belongs to among the results.
+///
+/// The movie must be in the given folder. When the folder contains several movies, the
+/// movie whose name starts the extra's name is used, as in `Heat (1995)-trailer.mkv`.
+fn find_extra_movie(results: &[(File, MovieFile)], extra: &File, movie_dir: Option<&File>) -> Option<usize> {
+    let movie_dir = movie_dir?;
+
+    let candidates: Vec<usize> = results
+        .iter()
+        .enumerate()
+        .filter(|(_, (file, movie))| {
+            file.parent().as_ref() == Some(movie_dir) && movie.identity().and_then(|i| i.episode.as_ref()).is_none()
+        })
+        .map(|(pos, _)| pos)
+        .collect();
+
+    match candidates.len() {
+        0 => None,
+        1 => Some(candidates[0]),
+        _ => {
+            let extra_stem = extra.stem().to_lowercase();
+            candidates
+                .into_iter()
+                .find(|&pos| extra_stem.starts_with(&results[pos].0.stem().to_lowercase()))
+        }
+    }
+}
+
+/// Find the language of a VobSub subtitle from the `id: en, index: 0` line of its `.idx` file.
+fn parse_idx_lang(idx: &str) -> Option<&'static str> {
+    idx.lines()
+        .filter_map(|line| line.trim().strip_prefix("id:"))
+        .filter_map(|rest| rest.split(',').next())
+        .filter_map(|code| parse_lang_tag(code.trim()))
+        .next()
+}
+
+/// Find the start of the last cue of a VobSub subtitle from the `timestamp: 00:00:01:000` lines of
+/// its `.idx` file.
+fn parse_idx_timestamps(idx: &str) -> impl Iterator<Item = i64> + '_ {
+    idx.lines()
+        .filter_map(|line| line.trim().strip_prefix("timestamp:"))
+        .filter_map(|rest| {
+            let parts: Vec<i64> = rest
+                .split(',')
+                .next()?
+                .trim()
+                .split(':')
+                .map(|n| n.parse().ok())
+                .collect::<Option<_>>()?;
+            match parts[..] {
+                [h, m, s, ms] => Some(((h * 60 + m) * 60 + s) * 1000 + ms),
+                _ => None,
+            }
+        })
+}
+
+/// Tokens of the path from a folder to a file, used to compare the names of subtitles and movies.
+fn path_tokens(dir: &File, file: &File) -> HashSet<String> {
+    let mut tokens = Vec::new();
+    let mut names = vec![file.stem().to_lowercase()];
+
+    let mut parent = file.parent();
+    while let Some(p) = parent {
+        if p == *dir {
+            break;
+        }
+        names.push(p.name().to_lowercase());
+        parent = p.parent();
+    }
+
+    let mut result = HashSet::new();
+    for name in &names {
+        text_to_tokens(name, &mut tokens);
+        result.extend(tokens.iter().map(|t| t.to_string()));
+    }
+    result
+}
+
+/// Share of the smallest set of tokens found in the other one.
+fn name_similarity(left: &HashSet<String>, right: &HashSet<String>) -> f64 {
+    let min = left.len().min(right.len());
+    if min == 0 {
+        return 0.0;
+    }
+    left.intersection(right).count() as f64 / min as f64
+}
+
+fn is_ancestor(dir: &File, file: &File) -> bool {
+    let mut parent = file.parent();
+    while let Some(p) = parent {
+        if p == *dir {
+            return true;
+        }
+        parent = p.parent();
+    }
+    false
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-pub struct SubtitleFile {
-    pub path: PathSize,
-    pub lang: String,
-    pub ext: String,
-    pub fingerprint: Fingerprint,
+/// Whether the last cue of a subtitle fits in the runtime of a movie.
+fn fits_runtime(last_cue: i64, runtime: u16) -> bool {
+    let runtime = i64::from(runtime) * 60 * 1000;
+    last_cue >= runtime * 7 / 10 && last_cue <= runtime + RUNTIME_MARGIN
 }
 
-impl SubtitleFile {
-    pub fn path(&self) -> &Path {
-        &self.path.path
+/// Find the movie a subtitle belongs to.
+///
+/// Subtitles named after a movie in the same folder belong to it. Otherwise, the movies in the
+/// closest folder containing the subtitle are candidates, as with `Subs/<movie name>/2_English.srt`
+/// or a shared `Subs/` folder. The candidate is picked by the similarity of the names, and when
+/// that is ambiguous, by comparing the last cue of the subtitle with the runtime of the movies.
+fn assign_subtitle(results: &[(File, MovieFile)], file: &File, sub: &SubtitleFile) -> Option<usize> {
+    let same_stem = results
+        .iter()
+        .enumerate()
+        .filter(|(_, (movie, _))| movie.parent() == file.parent() && file.name().starts_with(movie.stem()))
+        .max_by_key(|(_, (movie, _))| movie.stem().len());
+    if let Some((pos, _)) = same_stem {
+        return Some(pos);
     }
 
-    pub fn size(&self) -> u64 {
-        self.path.size
+    let mut dir = file.parent();
+    let (dir, candidates) = loop {
+        let d = dir?;
+        let candidates: Vec<usize> = results
+            .iter()
+            .enumerate()
+            .filter(|(_, (movie, _))| is_ancestor(&d, movie))
+            .map(|(pos, _)| pos)
+            .collect();
+        if !candidates.is_empty() {
+            break (d, candidates);
+        }
+        dir = d.parent();
+    };
+
+    // a single movie in the folder gets all the subtitles around it
+    if candidates.len() == 1 && results[candidates[0]].0.parent().as_ref() == Some(&dir) {
+        return Some(candidates[0]);
     }
 
-    pub fn pathsize(&self) -> &PathSize {
-        &self.path
+    let sub_tokens = path_tokens(&dir, file);
+    let mut scores: Vec<(f64, usize)> = candidates
+        .iter()
+        .map(|&pos| (name_similarity(&sub_tokens, &path_tokens(&dir, &results[pos].0)), pos))
+        .collect();
+    scores.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal));
+
+    let best = scores[0];
+    let second = scores.get(1).map_or(0.0, |s| s.0);
+    if best.0 >= MIN_SUBTITLE_SIMILARITY && best.0 - second >= MIN_SUBTITLE_MARGIN {
+        return Some(best.1);
     }
-}
 
-fn is_video(file: &File) -> bool {
-    file.is_file() && VIDEO_EXT.contains(&file.ext().to_lowercase().as_str())
+    // the names are not enough, compare the last cue with the runtime of the movies
+    let last_cue = sub.last_cue?;
+    let fitting: Vec<usize> = candidates
+        .into_iter()
+        .filter(|&pos| match results[pos].1.identity() {
+            Some(identity) => fits_runtime(last_cue, identity.title.runtime),
+            None => false,
+        })
+        .collect();
+
+    match fitting[..] {
+        [pos] => Some(pos),
+        _ => None,
+    }
 }
 
-fn is_subtitle(file: &File) -> bool {
-    file.is_file() && SUBTITLE_EXT.contains(&file.ext().to_lowercase().as_str())
+/// Rank subtitles with the same language and flags, the best being ranked 0.
+///
+/// Subtitles are compared by their number of entries, then by the share of the runtime they
+/// cover, their size and finally their format. Returns the rank of each subtitle, in order.
+pub fn rank_subtitles(subtitles: &[SubtitleFile], runtime: Option<u16>) -> Vec<usize> {
+    let key = |sub: &SubtitleFile| (sub.entries, sub.coverage(runtime), sub.size(), sub.format.preference());
+
+    subtitles
+        .iter()
+        .enumerate()
+        .map(|(pos, sub)| {
+            subtitles
+                .iter()
+                .enumerate()
+                .filter(|(other_pos, other)| {
+                    // ties are broken by position
+                    other.same_kind(sub) && (key(other) > key(sub) || (key(other) == key(sub) && *other_pos < pos))
+                })
+                .count()
+        })
+        .collect()
 }
 
-fn token_splitter(c: char) -> bool {
-    match c {
-        c if c.is_whitespace() => true,
-        '_' | '-' | '.' | '(' | ')' | '[' | ']' | ':' => true,
-        _ => false,
+/// Correct the timings of a movie's frame-based subtitles with the movie's real frame rate.
+fn set_frame_rate(movie: &mut MovieFile, info: Option<&MediaInfo>) {
+    let frame_rate = match info {
+        Some(info) => info.video.iter().filter_map(|v| v.frame_rate()).next(),
+        None => None,
+    };
+
+    if let Some(frame_rate) = frame_rate {
+        for sub in movie.subtitles.iter_mut() {
+            if let Some(old) = sub.frame_rate {
+                let rescale = |cue: i64| (cue as f64 * old / frame_rate) as i64;
+                sub.first_cue = sub.first_cue.map(rescale);
+                sub.last_cue = sub.last_cue.map(rescale);
+                sub.frame_rate = Some(frame_rate);
+            }
+        }
     }
 }
 
-fn text_to_tokens<'t>(text: &'t str, tokens: &mut Vec<&'t str>) {
-    tokens.clear();
-    for token in text.split(token_splitter) {
-        if !token.is_empty() {
-            tokens.push(token);
-        }
+/// Compare the timings of a subtitle with the duration of its movie, in milliseconds.
+///
+/// Forced subtitles only cover a few scenes, so they are only checked for ending after the movie.
+fn check_sync(sub: &SubtitleFile, duration: i64, margin: i64) -> Option<SyncIssue> {
+    let last_cue = sub.last_cue?;
+
+    if last_cue > duration + margin {
+        return Some(SyncIssue::EndsAfter(last_cue - duration));
+    }
+    if sub.forced {
+        return None;
+    }
+    if last_cue < duration * 7 / 10 {
+        return Some(SyncIssue::EndsEarly(duration - last_cue));
+    }
+    match sub.first_cue {
+        Some(first_cue) if first_cue > duration / 4 => Some(SyncIssue::StartsLate(first_cue - duration / 4)),
+        _ => None,
     }
 }
 
-fn is_year(token: &str) -> bool {
-    token.len() == 4 && token.chars().all(|c| c.is_digit(10))
+/// Flag the subtitles of a movie whose timings do not fit its duration.
+///
+/// The duration found by ffprobe is preferred to the runtime listed by IMDB, which is less precise.
+fn check_subtitles_sync(movie: &mut MovieFile) {
+    // subtitles of a movie split in parts are not checked, they might only cover one part
+    if !movie.parts.is_empty() {
+        return;
+    }
+
+    let runtime = movie
+        .identity()
+        .map(|identity| i64::from(identity.title.runtime) * 60 * 1000)
+        .filter(|&runtime| runtime > 0);
+    let (duration, margin) = match (movie.duration, runtime) {
+        (Some(duration), _) => (duration, DURATION_MARGIN),
+        (None, Some(runtime)) => (runtime, RUNTIME_MARGIN),
+        (None, None) => return,
+    };
+
+    for sub in movie.subtitles.iter_mut() {
+        sub.sync = check_sync(sub, duration, margin);
+    }
 }
 
-fn parse_file_name(stem: &str) -> Option<(String, i32)> {
-    let stem = stem.to_lowercase();
-    let mut tokens = Vec::new();
-    text_to_tokens(&stem, &mut tokens);
+/// Probe a movie having subtitles with ffprobe, check their timings and fingerprint the movie and its parts
+/// if they were not found in the cache.
+///
+/// With `probe`, every movie is probed to find the broken ones. The streams of probed movies are recorded.
+fn probe_and_fingerprint(mut movie: MovieFile, probe: bool, ffprobe: &Path) -> Result<MovieFile> {
+    // files being downloaded are incomplete, they are read by a later scan
+    if movie.pending.is_some() {
+        return Ok(movie);
+    }
+
+    if probe || !movie.subtitles.is_empty() {
+        // ffprobe is optional, it is only required for frame-based subtitles
+        let info = if archive::is_member(movie.path()) {
+            // ffprobe reads files from the disk, movies inside archives are not probed
+            None
+        } else {
+            match ffprobe::scan_with(ffprobe, movie.path()) {
+                Ok(info) => Some(info),
+                // ffprobe ran but failed to read the file
+                Err(Error::SpawnError(_)) if probe => {
+                    movie.broken = Some(Broken::Unreadable);
+                    None
+                }
+                Err(err) => {
+                    if probe || movie.subtitles.iter().any(|s| s.format.is_frame_based()) {
+                        println!("Unable to probe {}: {}", movie.path().display(), err);
+                    }
+                    None
+                }
+            }
+        };
+        if let Some(info) = &info {
+            if probe {
+                movie.broken = find_broken(&movie, info);
+            }
+            let (video, audio) = stream_info(info);
+            movie.video = video;
+            movie.audio = audio;
+        }
+        movie.duration = info
+            .as_ref()
+            .and_then(|info| info.duration)
+            .map(|duration| (duration * 1000.0) as i64);
+        set_frame_rate(&mut movie, info.as_ref());
+        check_subtitles_sync(&mut movie);
+    }
 
-    let (year_pos, year_token) = tokens.iter().enumerate().rev().find(|(_, t)| is_year(t))?;
+    // a null fingerprint was not found in the cache
+    if movie.fingerprint == Fingerprint::null() {
+        println!("Fingerprinting {}", movie.path().display());
+        movie.fingerprint = fingerprint::file(movie.path())?;
+    }
+    for part in movie.parts.iter_mut() {
+        if part.fingerprint == Fingerprint::null() {
+            part.fingerprint = fingerprint::file(part.path())?;
+        }
+    }
 
-    let title = tokens[..year_pos].join(" ");
-    let year = year_token.parse().expect("invalid year");
+    Ok(movie)
+}
 
-    Some((title, year))
+/// Movies found by a scan, the subtitles that could not be assigned to any of them, and the links
+/// which were not followed.
+pub struct ScanResults {
+    pub movies: Vec<MovieFile>,
+    pub unassigned_subtitles: Vec<PathSize>,
+    pub links: Vec<LinkFile>,
 }
 
 pub struct Scanner {
     buff: SafeBuffer,
+    /// Number of worker threads fingerprinting files and analyzing subtitles.
+    jobs: usize,
+    cache: ScanCache,
+    quiet_period: Duration,
+    probe: bool,
+    ffprobe: PathBuf,
 }
 
 impl Scanner {
     pub fn new() -> Scanner {
+        Scanner::with_jobs(1)
+    }
+
+    pub fn with_jobs(jobs: usize) -> Scanner {
         Scanner {
             buff: SafeBuffer::new(),
+            jobs: jobs.max(1),
+            cache: ScanCache::default(),
+            quiet_period: Duration::from_secs(0),
+            probe: false,
+            ffprobe: PathBuf::from(ffprobe::FFPROBE),
         }
     }
 
+    /// Probe every movie with ffprobe to find the corrupt and truncated ones.
+    pub fn set_probe(&mut self, probe: bool) {
+        self.probe = probe;
+    }
+
+    /// Run another ffprobe binary than the one found in the PATH.
+    pub fn set_ffprobe(&mut self, ffprobe: impl Into<PathBuf>) {
+        self.ffprobe = ffprobe.into();
+    }
+
+    /// Consider files modified less than `quiet_period` ago as still being downloaded.
+    pub fn set_quiet_period(&mut self, quiet_period: Duration) {
+        self.quiet_period = quiet_period;
+    }
+
+    /// Use a cache of previous scans, files which did not change since are not read again.
+    pub fn set_cache(&mut self, cache: ScanCache) {
+        self.cache = cache;
+    }
+
+    pub fn take_cache(&mut self) -> ScanCache {
+        mem::take(&mut self.cache)
+    }
+
     fn analyze_subtitle(&mut self, file: &File) -> Option<SubtitleFile> {
-        let mut fd = BufReader::new(fs::File::open(file.path()).ok()?);
+        match file.ext().to_lowercase().as_str() {
+            "idx" => return self.analyze_vobsub(file),
+            "sup" => return self.analyze_pgs(file),
+            _ => {}
+        }
+
+        let mut fd = BufReader::new(archive::open(file.path()).ok()?);
 
         // Only read the first 512 bytes to scan for the format.
         // VoSub being images, the files are really large. Since
@@ -156,9 +1497,8 @@ impl Scanner {
         self.buff.read_exact(&mut fd, 512).ok()?;
 
         let format = subparse::get_subtitle_format(&format!(".{}", file.ext().to_lowercase()), &self.buff)?;
-        if format == SubtitleFormat::VobSubSub || format == SubtitleFormat::VobSubIdx {
-            return None;
-        }
+        // VobSub .sub files are imported along with their .idx file.
+        let sub_format = SubFormat::from_subparse(format)?;
 
         // Once we know this subtitle file is actually something we care about,
         // we can read it fully into a re-usable buffer. The bytes are appended
@@ -172,11 +1512,19 @@ impl Scanner {
         let encoding = Encoding::for_label(label.as_bytes())?;
 
         // parse the subtitle file
-        let sub = subparse::parse_bytes(format, &self.buff, encoding, 30.0).ok()?;
+        // frame-based timings are corrected once the subtitle's movie is known
+        let sub = subparse::parse_bytes(format, &self.buff, encoding, DEFAULT_FRAME_RATE).ok()?;
 
         // join all the text segments into a string
         let mut text = String::new();
-        for entry in sub.get_subtitle_entries().ok()? {
+        let mut first_cue = None;
+        let mut last_cue = None;
+        let entries = sub.get_subtitle_entries().ok()?;
+        let entry_count = entries.len();
+        for entry in entries {
+            let start = entry.timespan.start.msecs();
+            first_cue = Some(first_cue.map_or(start, |cue: i64| cue.min(start)));
+            last_cue = last_cue.max(Some(entry.timespan.end.msecs()));
             if let Some(line) = entry.line {
                 text.push_str(&line);
             }
@@ -186,108 +1534,294 @@ impl Scanner {
             return None;
         }
 
-        // detect language
-        let lang = whatlang::detect(&text)?.lang();
+        // detect language and reconcile it with the tags from the file name
+        let tags = SubtitleTags::parse(file.stem());
+        let detected = whatlang::detect(&text).map(|info| (info.lang(), info.confidence()));
+        let (lang, confidence) = reconcile_lang(
+            tags.lang,
+            detected.as_ref().map(|(lang, confidence)| (lang.code(), *confidence)),
+            text.len(),
+        )?;
 
         let fp = fingerprint::bytes(&self.buff);
 
         Some(SubtitleFile {
             path: file.into(),
             ext: format.get_name()[1..4].to_owned(),
-            lang: lang.code().to_owned(),
+            lang: lang.to_owned(),
+            format: sub_format,
+            forced: tags.forced,
+            sdh: tags.sdh,
+            confidence,
+            entries: entry_count,
+            first_cue,
+            last_cue,
+            encoding: Some(encoding.name().to_owned()),
+            frame_rate: if sub_format.is_frame_based() {
+                Some(DEFAULT_FRAME_RATE)
+            } else {
+                None
+            },
+            data: None,
+            sync: None,
             fingerprint: fp,
         })
     }
 
-    /// Scan for subtitles around a movie file.
-    fn scan_subtitles(&mut self, movie: &File, ignored: &HashSet<File>) -> Vec<SubtitleFile> {
-        let mut subs = vec![];
+    /// Analyze a VobSub subtitle from its `.idx` file, which must have a `.sub` file next to it.
+    fn analyze_vobsub(&mut self, file: &File) -> Option<SubtitleFile> {
+        let data = file
+            .siblings()
+            .find(|s| s.stem() == file.stem() && s.ext().eq_ignore_ascii_case("sub"))?;
 
-        // if there's only a single movie file in the folder, scan in subfolders
-        let alone = movie.siblings().filter(|s| !ignored.contains(s)).count() == 0;
+        self.buff.clear();
+        self.buff.read_to_end(archive::open(file.path()).ok()?).ok()?;
+        if self.buff.is_empty() {
+            return None;
+        }
 
-        for file in movie.siblings() {
-            // subtitle files with the same stem
-            if is_subtitle(&file) && file.name().starts_with(movie.stem()) {
-                println!("Analyzing subtitle {}", file.path().display());
-                if let Some(sub) = self.analyze_subtitle(&file) {
-                    subs.push(sub);
-                }
-            }
+        let idx = String::from_utf8_lossy(&self.buff);
+        let tags = SubtitleTags::parse(file.stem());
+        let (lang, confidence) = match parse_idx_lang(&idx) {
+            Some(lang) => (lang, 1.0),
+            None => reconcile_lang(tags.lang, None, 0)?,
+        };
 
-            if alone {
-                for desc in file.descendants() {
-                    if is_subtitle(&desc) {
-                        println!("Analyzing subtitle {}", desc.path().display());
-                        if let Some(sub) = self.analyze_subtitle(&desc) {
-                            subs.push(sub);
-                        }
-                    }
-                }
-            }
+        Some(SubtitleFile {
+            path: file.into(),
+            ext: "idx".to_owned(),
+            lang: lang.to_owned(),
+            format: SubFormat::VobSub,
+            forced: tags.forced,
+            sdh: tags.sdh,
+            confidence,
+            entries: idx.lines().filter(|line| line.trim().starts_with("timestamp:")).count(),
+            first_cue: parse_idx_timestamps(&idx).min(),
+            last_cue: parse_idx_timestamps(&idx).max(),
+            encoding: None,
+            frame_rate: None,
+            data: Some(From::from(&data)),
+            sync: None,
+            fingerprint: fingerprint::bytes(&self.buff),
+        })
+    }
+
+    /// Analyze a PGS subtitle, its language can only be found from the file name.
+    fn analyze_pgs(&mut self, file: &File) -> Option<SubtitleFile> {
+        self.buff.clear();
+        self.buff.read_exact(archive::open(file.path()).ok()?, 2).ok()?;
+        if &self.buff[..] != b"PG" {
+            return None;
         }
-        subs
+
+        let tags = SubtitleTags::parse(file.stem());
+        let (lang, confidence) = reconcile_lang(tags.lang, None, 0).unwrap_or((UNKNOWN_LANG, 0.0));
+
+        Some(SubtitleFile {
+            path: file.into(),
+            ext: "sup".to_owned(),
+            lang: lang.to_owned(),
+            format: SubFormat::Pgs,
+            forced: tags.forced,
+            sdh: tags.sdh,
+            confidence,
+            entries: 0,
+            first_cue: None,
+            last_cue: None,
+            encoding: None,
+            frame_rate: None,
+            data: None,
+            sync: None,
+            fingerprint: fingerprint::file(file.path()).ok()?,
+        })
     }
 
     /// Scan for files that look like movies.
-    pub fn scan_movies<'i>(&mut self, root: &File, index: &Index) -> Result<Vec<MovieFile>> {
-        let mut ignored: HashSet<File> = HashSet::new();
+    pub fn scan_movies(&mut self, root: &File, index: &Index) -> Result<ScanResults> {
         let mut results: Vec<(File, MovieFile)> = Vec::new();
+        let mut extras: Vec<(File, ExtraKind, Option<File>)> = Vec::new();
+        let now = SystemTime::now();
 
         for child in root.descendants() {
-            if is_video(&child) {
-                if let Some((title, year)) = parse_file_name(child.stem()) {
-                    // Once we find a movie we try to look for peers that are small.
-                    // Usually featurettes, samples and extras and mark them as ignored.
-                    if let Some(parent) = child.parent() {
-                        if parent != *root {
-                            let size = child.metadata().len() as f64;
-
-                            for peer in parent.descendants() {
-                                if peer.metadata().len() as f64 / size <= 0.40 {
-                                    ignored.insert(peer);
-                                }
-                            }
-                        }
-                    }
-
-                    let mut identity = None;
+            if is_video(&child) || is_partial_video(&child) {
+                let pending = find_pending(&child, self.quiet_period, now);
 
-                    if let Some(scored) = index.find(&title, Some(year)) {
-                        let title = scored.value;
-                        identity = Some(Scored::new(scored.score, MovieIdentity { title: title.clone() }));
-                        // println!("Looking up info on themoviedb.org for {}", child.path().display());
-                        // if let Some(tmdb_title) = self.tmdb.find(title.title_id)? {
-
-                        // }
+                if let Some((kind, movie_dir)) = classify_extra(&child) {
+                    // Samples are dropped, other extras are attached to their movie below.
+                    // Extras being downloaded are attached by a later scan.
+                    if kind != ExtraKind::Sample && pending.is_none() {
+                        extras.push((child, kind, movie_dir));
                     }
+                } else {
+                    let MovieMatch {
+                        identity,
+                        parsed,
+                        release,
+                    } = match self.cache.movie_match(child.path(), &MatchContext::of(&child)) {
+                        Some(movie_match) => movie_match,
+                        None => {
+                            let movie_match = match parse_episode_name(child.stem()) {
+                                Some(name) => MovieMatch {
+                                    identity: find_episode(index, &name),
+                                    parsed: true,
+                                    release: ReleaseInfo::parse(child.stem()),
+                                },
+                                None => identify_movie(index, &child),
+                            };
+                            self.cache
+                                .set_movie_match(child.path(), MatchContext::of(&child), &movie_match);
+                            movie_match
+                        }
+                    };
 
                     results.push((
                         child.clone(),
                         MovieFile {
                             path: From::from(&child),
+                            release,
                             identity: identity,
-                            // We use a null fingerprint here because we want to avoid fingerprinting
-                            // files that will be removed as ignored.
+                            parsed,
                             fingerprint: Fingerprint::null(),
                             subtitles: vec![],
+                            extras: vec![],
+                            parts: vec![],
+                            duration: None,
+                            pending,
+                            broken: None,
+                            video: vec![],
+                            audio: vec![],
                         },
                     ));
                 }
             }
         }
 
-        // Remove every file that was flagged as ignored from the list.
-        results.retain(|(file, _)| !ignored.contains(&file));
+        let mut results = group_parts(results);
+
+        // The files are fingerprinted and analyzed on the worker pool, the results are then
+        // attached to the movies in the same order as in a sequential scan.
+        // Files found in the cache are not read again.
+        let mut found_extras = vec![];
+        for (extra, kind, movie_dir) in extras {
+            match find_extra_movie(&results, &extra, movie_dir.as_ref()) {
+                Some(pos) => {
+                    let fingerprint = self.cache.fingerprint(extra.path());
+                    found_extras.push((pos, extra, kind, fingerprint));
+                }
+                None => println!("No movie found for {} {}", kind, extra.path().display()),
+            }
+        }
+
+        let found_extras = utils::parallel_map(
+            found_extras,
+            self.jobs,
+            || (),
+            |_, (pos, extra, kind, fingerprint)| -> Result<(usize, ExtraFile)> {
+                let fingerprint = match fingerprint {
+                    Some(fingerprint) => fingerprint,
+                    None => {
+                        println!("Fingerprinting {} {}", kind, extra.path().display());
+                        fingerprint::file(extra.path())?
+                    }
+                };
+                Ok((
+                    pos,
+                    ExtraFile {
+                        path: From::from(&extra),
+                        kind,
+                        fingerprint,
+                    },
+                ))
+            },
+        );
+        for found in found_extras {
+            let (pos, extra) = found?;
+            self.cache.set_fingerprint(extra.path(), &extra.fingerprint);
+            results[pos].1.extras.push(extra);
+        }
+
+        let subtitles: Vec<_> = root
+            .descendants()
+            .filter(is_subtitle)
+            .map(|file| {
+                let cached = self.cache.subtitle(file.path());
+                (file, cached)
+            })
+            .collect();
+
+        let subtitles = utils::parallel_map(subtitles, self.jobs, Scanner::new, |scanner, (file, cached)| {
+            let sub = match cached {
+                Some(sub) => sub,
+                None => {
+                    println!("Analyzing subtitle {}", file.path().display());
+                    scanner.analyze_subtitle(&file)
+                }
+            };
+            (file, sub)
+        });
+
+        let mut unassigned_subtitles = vec![];
+
+        for (file, sub) in subtitles {
+            self.cache.set_subtitle(file.path(), sub.as_ref());
+            if let Some(sub) = sub {
+                match assign_subtitle(&results, &file, &sub) {
+                    Some(pos) => results[pos].1.subtitles.push(sub),
+                    None => unassigned_subtitles.push(sub.path),
+                }
+            }
+        }
+
+        // Fingerprint each remaining movie file, the fingerprints found in the cache are kept.
+        let mut movies = vec![];
+        for (_, mut movie) in results {
+            if movie.pending.is_some() {
+                movies.push(movie);
+                continue;
+            }
+            if let Some(fingerprint) = self.cache.fingerprint(movie.path()) {
+                movie.fingerprint = fingerprint;
+            }
+            for part in movie.parts.iter_mut() {
+                if let Some(fingerprint) = self.cache.fingerprint(part.path()) {
+                    part.fingerprint = fingerprint;
+                }
+            }
+            movies.push(movie);
+        }
+
+        let (probe, ffprobe) = (self.probe, &self.ffprobe);
+        let movies = utils::parallel_map(
+            movies,
+            self.jobs,
+            || (),
+            |_, movie| probe_and_fingerprint(movie, probe, ffprobe),
+        )
+        .into_iter()
+        .collect::<Result<Vec<_>>>()?;
 
-        // Fingerprint and scan for subtitles for each remaining movie file.
-        for (file, movie) in results.iter_mut() {
-            println!("Scanning subtitles for {}", movie.path().display());
-            movie.fingerprint = fingerprint::file(&movie.path())?;
-            movie.subtitles = self.scan_subtitles(&file, &ignored);
+        for movie in movies.iter().filter(|movie| movie.pending.is_none()) {
+            self.cache.set_fingerprint(movie.path(), &movie.fingerprint);
+            for part in movie.parts.iter() {
+                self.cache.set_fingerprint(part.path(), &part.fingerprint);
+            }
         }
 
-        Ok(results.into_iter().map(|(_, movie)| movie).collect())
+        let links = root
+            .descendants()
+            .filter_map(|file| {
+                file.link_target().map(|target| LinkFile {
+                    path: file.path().to_owned(),
+                    target: target.to_owned(),
+                })
+            })
+            .collect();
+
+        Ok(ScanResults {
+            movies,
+            unassigned_subtitles,
+            links,
+        })
     }
 }
 
@@ -318,3 +1852,539 @@ fn test_parse_file_name_with_year() {
 
     assert_eq!(parse_file_name("1981.(2009)"), Some(("1981".to_string(), 2009)));
 }
+
+#[test]
+fn test_parse_episode_name() {
+    assert_eq!(
+        parse_episode_name("The.Wire.S01E02.720p.HDTV"),
+        Some(EpisodeName {
+            title: "the wire".to_string(),
+            year: None,
+            season: 1,
+            episode: 2,
+        })
+    );
+
+    assert_eq!(
+        parse_episode_name("Doctor Who (2005) - 1x02 - The End of the World"),
+        Some(EpisodeName {
+            title: "doctor who".to_string(),
+            year: Some(2005),
+            season: 1,
+            episode: 2,
+        })
+    );
+
+    assert_eq!(
+        parse_episode_name("Lost.s02e10e11"),
+        Some(EpisodeName {
+            title: "lost".to_string(),
+            year: None,
+            season: 2,
+            episode: 10,
+        })
+    );
+}
+
+#[test]
+fn test_parse_episode_name_movie() {
+    assert_eq!(parse_episode_name("American Psycho 1999"), None);
+    assert_eq!(parse_episode_name("Heat.1995.1080p.x264"), None);
+    assert_eq!(parse_episode_name("S01E02"), None);
+}
+
+#[cfg(test)]
+fn test_index() -> Index {
+    Index::from_titles(&[
+        (1, "Some Movie", 2004),
+        (2, "Heat", 1995),
+        (3, "Alien", 1979),
+        (4, "Aliens", 1986),
+    ])
+}
+
+#[cfg(test)]
+fn find_test_file(root: &File, name: &str) -> File {
+    root.descendants()
+        .find(|f| f.name() == name)
+        .expect("test file not found")
+}
+
+#[test]
+fn test_identify_movie_sources() {
+    let index = test_index();
+    let root = super::vfs::walk(
+        "testdata/scan",
+        &Default::default(),
+        &Default::default(),
+        Default::default(),
+    )
+    .unwrap();
+
+    let file = identify_movie(&index, &find_test_file(&root, "Alien 1979.mkv"));
+    let identity = file.identity.unwrap().value;
+    assert_eq!(identity.title.title_id.0, 3);
+    assert_eq!(identity.source, MatchSource::FileName);
+
+    let file = identify_movie(&index, &find_test_file(&root, "abc-xyz.mkv"));
+    let identity = file.identity.unwrap().value;
+    assert_eq!(identity.title.title_id.0, 1);
+    assert_eq!(identity.source, MatchSource::ParentDirectory);
+    assert_eq!(file.release.resolution.as_deref(), Some("1080p"));
+
+    let file = identify_movie(&index, &find_test_file(&root, "xyz.avi"));
+    let identity = file.identity.unwrap().value;
+    assert_eq!(identity.title.title_id.0, 2);
+    assert_eq!(identity.source, MatchSource::GrandparentDirectory);
+}
+
+#[test]
+fn test_find_movie_without_year() {
+    let index = test_index();
+    let find = |index: &Index, title| find_movie_without_year(index, title, MatchSource::FileName);
+
+    // the best match is kept when it is clearly ahead of the others
+    assert_eq!(find(&index, "heat").unwrap().value.title.title_id.0, 2);
+    assert_eq!(find(&index, "alien").unwrap().value.title.title_id.0, 3);
+
+    // names far from every title are not matched
+    assert!(find(&index, "holiday video").is_none());
+
+    // remakes share their name, which cannot tell them apart without a year
+    let remakes = Index::from_titles(&[(5, "The Thing", 1982), (6, "The Thing", 2011)]);
+    assert_eq!(remakes.find_all("the thing", None).len(), 2);
+    assert!(find(&remakes, "the thing").is_none());
+}
+
+#[test]
+fn test_scan_unparsed() {
+    use std::fs;
+
+    let dir = std::env::temp_dir().join(format!("mero-test-scan-unparsed-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("Heat.mkv"), "heat").unwrap();
+    fs::write(dir.join("Holiday Video.mkv"), "holiday").unwrap();
+
+    let root = super::vfs::walk(&dir, &Default::default(), &Default::default(), Default::default()).unwrap();
+    let mut scanner = Scanner::new();
+    scanner.set_quiet_period(Duration::from_secs(0));
+    let mut movies = scanner.scan_movies(&root, &test_index()).unwrap().movies;
+    fs::remove_dir_all(&dir).unwrap();
+
+    movies.sort_by_key(|m| m.path().to_owned());
+    assert_eq!(movies.len(), 2);
+    // matched without a year, so not parsed either
+    assert_eq!(movies[0].identity().unwrap().title.title_id.0, 2);
+
+    // the file without a year nor a match is kept unparsed, which view reports, instead of being dropped
+    assert!(movies[1].path().ends_with("Holiday Video.mkv"));
+    assert!(!movies[1].parsed);
+    assert!(movies[1].identity.is_none());
+}
+
+#[test]
+fn test_parse_imdb_id() {
+    assert_eq!(
+        parse_imdb_id("https://www.imdb.com/title/tt0113277/"),
+        Some(TitleId::new(113277))
+    );
+    assert_eq!(
+        parse_imdb_id("Heat (1995) {imdb-tt0113277}"),
+        Some(TitleId::new(113277))
+    );
+    assert_eq!(parse_imdb_id("Heat.1995.[tt0113277]"), Some(TitleId::new(113277)));
+    assert_eq!(parse_imdb_id("Heat.1995.[tt10872600]"), Some(TitleId::new(10872600)));
+    assert_eq!(parse_imdb_id("Pretty.Little.Liars.tt123"), None);
+    assert_eq!(parse_imdb_id("Scott.tt0113277"), Some(TitleId::new(113277)));
+    assert_eq!(parse_imdb_id("Matt0113277"), None);
+}
+
+#[test]
+fn test_identify_movie_by_id() {
+    let index = test_index();
+    let root = super::vfs::walk(
+        "testdata/scan",
+        &Default::default(),
+        &Default::default(),
+        Default::default(),
+    )
+    .unwrap();
+
+    let file = identify_movie(&index, &find_test_file(&root, "release.mkv"));
+    let identity = file.identity.unwrap();
+    assert_eq!(identity.value.title.title_id.0, 4);
+    assert_eq!(identity.value.source, MatchSource::ImdbId);
+    assert_eq!(*identity.score, 1.0);
+
+    // The id is not in the index, fuzzy matching is used instead.
+    let file = identify_movie(&index, &find_test_file(&root, "video.mkv"));
+    let identity = file.identity.unwrap().value;
+    assert_eq!(identity.title.title_id.0, 3);
+    assert_eq!(identity.source, MatchSource::ParentDirectory);
+}
+
+#[test]
+fn test_extra_kind_from_name() {
+    assert_eq!(extra_kind_from_name("Heat.1995.1080p.sample"), Some(ExtraKind::Sample));
+    assert_eq!(extra_kind_from_name("sample-heat"), Some(ExtraKind::Sample));
+    assert_eq!(extra_kind_from_name("Heat (1995)-trailer"), Some(ExtraKind::Trailer));
+    assert_eq!(
+        extra_kind_from_name("Heat 1995 Behind the Scenes"),
+        Some(ExtraKind::BehindTheScenes)
+    );
+    assert_eq!(
+        extra_kind_from_name("Heat.1995.Deleted.Scenes"),
+        Some(ExtraKind::DeletedScene)
+    );
+    assert_eq!(extra_kind_from_name("Trailer Park Boys 2014"), None);
+    assert_eq!(extra_kind_from_name("The Interview"), None);
+    assert_eq!(extra_kind_from_name("Spider-Man"), None);
+    assert_eq!(extra_kind_from_name("Heat.1995.1080p.BluRay"), None);
+}
+
+#[test]
+fn test_scan_extras() {
+    let root = super::vfs::walk(
+        "testdata/scan",
+        &Default::default(),
+        &Default::default(),
+        Default::default(),
+    )
+    .unwrap();
+
+    let trailer = find_test_file(&root, "Heat (1995)-trailer.mkv");
+    let (kind, movie_dir) = classify_extra(&trailer).unwrap();
+    assert_eq!(kind, ExtraKind::Trailer);
+    assert_eq!(movie_dir.unwrap().name(), "Video");
+
+    let featurette = find_test_file(&root, "making of.mkv");
+    let (kind, movie_dir) = classify_extra(&featurette).unwrap();
+    assert_eq!(kind, ExtraKind::Featurette);
+    assert_eq!(movie_dir.unwrap().name(), "Video");
+
+    assert!(classify_extra(&find_test_file(&root, "xyz.avi")).is_none());
+}
+
+#[test]
+fn test_parse_part() {
+    assert_eq!(parse_part("movie.cd1"), Some(("movie".to_string(), 1)));
+    assert_eq!(
+        parse_part("Movie (2001) - Part 2"),
+        Some(("movie (2001)".to_string(), 2))
+    );
+    assert_eq!(
+        parse_part("Movie.2001.DVDRip.CD2-GRP"),
+        Some(("movie.2001.dvdrip-grp".to_string(), 2))
+    );
+    assert_eq!(parse_part("movie_disc_3"), Some(("movie".to_string(), 3)));
+    assert_eq!(parse_part("Movie 2001"), None);
+    assert_eq!(parse_part("Apartment 12"), None);
+    assert_eq!(parse_part("movie.cd0"), None);
+}
+
+#[test]
+fn test_parse_idx_lang() {
+    let idx = "# VobSub index file, v7 (do not modify this line!)\nsize: 720x480\n\n# English\nid: en, index: 0\ntimestamp: 00:00:01:000, filepos: 000000000\n";
+    assert_eq!(parse_idx_lang(idx), Some("eng"));
+    assert_eq!(parse_idx_lang("size: 720x480\n"), None);
+}
+
+#[test]
+fn test_assign_subtitle() {
+    let index = test_index();
+    let root = super::vfs::walk(
+        "testdata/subs",
+        &Default::default(),
+        &Default::default(),
+        Default::default(),
+    )
+    .unwrap();
+
+    let mut results: Vec<(File, MovieFile)> = ["Heat.1995.mkv", "Alien.1979.mkv", "movie.mkv"]
+        .iter()
+        .map(|name| {
+            let file = find_test_file(&root, name);
+            let MovieMatch {
+                identity,
+                parsed,
+                release,
+            } = identify_movie(&index, &file);
+            let movie = MovieFile {
+                path: From::from(&file),
+                release,
+                identity,
+                parsed,
+                fingerprint: Fingerprint::null(),
+                subtitles: vec![],
+                extras: vec![],
+                parts: vec![],
+                duration: None,
+                pending: None,
+                broken: None,
+                video: vec![],
+                audio: vec![],
+            };
+            (file, movie)
+        })
+        .collect();
+    results[1].1.identity.as_mut().unwrap().value.title.runtime = 60;
+
+    let assign = |name: &str, last_cue: Option<i64>| {
+        let file = find_test_file(&root, name);
+        let sub = SubtitleFile {
+            path: From::from(&file),
+            lang: "eng".to_string(),
+            ext: "srt".to_string(),
+            format: SubFormat::SubRip,
+            forced: false,
+            sdh: false,
+            confidence: 1.0,
+            entries: 0,
+            first_cue: None,
+            last_cue,
+            encoding: None,
+            frame_rate: None,
+            data: None,
+            sync: None,
+            fingerprint: Fingerprint::null(),
+        };
+        assign_subtitle(&results, &file, &sub)
+    };
+
+    assert_eq!(assign("Heat.1995.en.srt", None), Some(0));
+    assert_eq!(assign("2_English.srt", None), Some(0));
+    assert_eq!(assign("Alien.1979.en.srt", None), Some(1));
+    assert_eq!(assign("French.srt", None), Some(2));
+    // Nothing in the name, the last cue only fits the runtime of Alien.
+    assert_eq!(assign("English.srt", None), None);
+    assert_eq!(assign("English.srt", Some(58 * 60 * 1000)), Some(1));
+}
+
+#[test]
+fn test_rank_subtitles() {
+    let sub = |lang: &str, forced: bool, entries: usize, last_cue: i64, size: u64, format: SubFormat| SubtitleFile {
+        path: PathSize {
+            path: PathBuf::new(),
+            size,
+        },
+        lang: lang.to_string(),
+        ext: "srt".to_string(),
+        format,
+        forced,
+        sdh: false,
+        confidence: 1.0,
+        entries,
+        first_cue: None,
+        last_cue: Some(last_cue * 60 * 1000),
+        encoding: None,
+        frame_rate: None,
+        data: None,
+        sync: None,
+        fingerprint: Fingerprint::null(),
+    };
+
+    let subtitles = vec![
+        sub("eng", false, 1000, 95, 1000, SubFormat::SubRip),
+        sub("eng", false, 1200, 95, 1000, SubFormat::SubRip),
+        sub("eng", true, 20, 80, 100, SubFormat::SubRip),
+        sub("fra", false, 1000, 95, 1000, SubFormat::SubRip),
+        // same entries, but covers less of the runtime
+        sub("eng", false, 1000, 50, 2000, SubFormat::SubRip),
+        // same entries and coverage, the text format wins
+        sub("fra", false, 1000, 95, 1000, SubFormat::VobSub),
+    ];
+    assert_eq!(rank_subtitles(&subtitles, Some(100)), vec![1, 0, 0, 0, 2, 1]);
+}
+
+#[test]
+fn test_check_sync() {
+    let sub = |forced: bool, first_cue: i64, last_cue: i64| SubtitleFile {
+        path: PathSize {
+            path: PathBuf::new(),
+            size: 0,
+        },
+        lang: "eng".to_string(),
+        ext: "srt".to_string(),
+        format: SubFormat::SubRip,
+        forced,
+        sdh: false,
+        confidence: 1.0,
+        entries: 0,
+        first_cue: Some(first_cue * 60 * 1000),
+        last_cue: Some(last_cue * 60 * 1000),
+        encoding: None,
+        frame_rate: None,
+        data: None,
+        sync: None,
+        fingerprint: Fingerprint::null(),
+    };
+    let check = |sub: SubtitleFile| check_sync(&sub, 100 * 60 * 1000, RUNTIME_MARGIN);
+
+    assert_eq!(check(sub(false, 1, 98)), None);
+    assert_eq!(check(sub(false, 1, 110)), Some(SyncIssue::EndsAfter(10 * 60 * 1000)));
+    assert_eq!(check(sub(false, 1, 60)), Some(SyncIssue::EndsEarly(40 * 60 * 1000)));
+    assert_eq!(check(sub(false, 30, 98)), Some(SyncIssue::StartsLate(5 * 60 * 1000)));
+    // forced subtitles only cover a few scenes
+    assert_eq!(check(sub(true, 30, 60)), None);
+    assert_eq!(check(sub(true, 30, 110)), Some(SyncIssue::EndsAfter(10 * 60 * 1000)));
+}
+
+#[test]
+fn test_scan_jobs() {
+    let index = test_index();
+    let root = super::vfs::walk(
+        "testdata/parallel",
+        &Default::default(),
+        &Default::default(),
+        Default::default(),
+    )
+    .unwrap();
+
+    let scan = |jobs| {
+        let results = Scanner::with_jobs(jobs).scan_movies(&root, &index).unwrap();
+        format!("{:?} {:?}", results.movies, results.unassigned_subtitles)
+    };
+
+    let sequential = scan(1);
+    assert!(sequential.contains("Heat.1995-trailer.mkv"));
+    assert!(sequential.contains("English.srt"));
+    assert_eq!(scan(4), sequential);
+}
+
+#[test]
+fn test_scan_pending() {
+    use std::fs;
+
+    let index = test_index();
+    let dir = std::env::temp_dir().join("mero-test-scan-pending");
+    let _ = fs::remove_dir_all(&dir);
+    for path in &[
+        "Heat.1995/Heat.1995.mkv.part",
+        "_UNPACK_Alien.1979/Alien.1979.mkv",
+        "Aliens.1986/Aliens.1986.mkv",
+    ] {
+        fs::create_dir_all(dir.join(path).parent().unwrap()).unwrap();
+        fs::write(dir.join(path), "movie").unwrap();
+    }
+    let root = super::vfs::walk(&dir, &Default::default(), &Default::default(), Default::default()).unwrap();
+
+    let scan = |quiet_period| {
+        let mut scanner = Scanner::new();
+        scanner.set_quiet_period(quiet_period);
+        let mut pending: Vec<_> = scanner
+            .scan_movies(&root, &index)
+            .unwrap()
+            .movies
+            .into_iter()
+            .map(|movie| (movie.path().file_name().unwrap().to_owned(), movie.pending))
+            .collect();
+        pending.sort_by(|a, b| a.0.cmp(&b.0));
+        pending
+    };
+
+    assert_eq!(
+        scan(Duration::from_secs(0)),
+        vec![
+            ("Alien.1979.mkv".into(), Some(Pending::Unpacking)),
+            ("Aliens.1986.mkv".into(), None),
+            ("Heat.1995.mkv.part".into(), Some(Pending::Partial)),
+        ]
+    );
+    assert_eq!(scan(Duration::from_secs(3600))[1].1, Some(Pending::Recent));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_scan_broken() {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    /// Remove the test folder even if an assert fails.
+    struct Cleanup(PathBuf);
+
+    impl Drop for Cleanup {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    let index = test_index();
+    let dir = std::env::temp_dir().join(format!("mero-test-scan-broken-{}", std::process::id()));
+    let _cleanup = Cleanup(dir.clone());
+    fs::create_dir_all(dir.join("bin")).unwrap();
+    fs::create_dir_all(dir.join("movies")).unwrap();
+
+    let ffprobe = dir.join("bin/ffprobe");
+    fs::write(
+        &ffprobe,
+        r#"#!/bin/sh
+video='{"codec_type": "video", "index": 0, "codec_name": "h264", "width": 1920, "height": 1080}'
+audio='{"codec_type": "audio", "index": 1, "codec_name": "aac", "channels": 2}'
+font='{"codec_type": "attachment", "index": 2}'
+hdr='{"codec_type": "video", "index": 0, "codec_name": "hevc", "width": 3840, "height": 1608, "pix_fmt": "yuv420p10le", "color_transfer": "smpte2084"}'
+french='{"codec_type": "audio", "index": 1, "codec_name": "eac3", "channels": 6, "tags": {"language": "fre"}}'
+cover='{"codec_type": "video", "index": 3, "codec_name": "mjpeg", "width": 600, "height": 900, "disposition": {"attached_pic": 1}}'
+for path; do :; done
+case "$(basename "$path")" in
+    Heat.1995.mkv) echo "{\"format\": {\"duration\": \"6000.0\"}, \"streams\": [$hdr, $french, $font, $cover]}" ;;
+    Aliens.1986.mkv) echo "{\"format\": {\"duration\": \"6000.0\"}, \"streams\": [$audio]}" ;;
+    Some.Movie.2004.mkv) echo "{\"format\": {\"duration\": \"600.0\"}, \"streams\": [$video, $audio]}" ;;
+    *) exit 1 ;;
+esac
+"#,
+    )
+    .unwrap();
+    fs::set_permissions(&ffprobe, fs::Permissions::from_mode(0o755)).unwrap();
+
+    for name in &[
+        "Heat.1995.mkv",
+        "Alien.1979.mkv",
+        "Aliens.1986.mkv",
+        "Some.Movie.2004.mkv",
+    ] {
+        fs::write(dir.join("movies").join(name), "movie").unwrap();
+    }
+    // the fake ffprobe fails on the movie of the archive, which is not on the disk
+    fs::copy("testdata/archive/movie.tar", dir.join("movies/movie.tar")).unwrap();
+    let root = super::vfs::walk(
+        dir.join("movies"),
+        &Default::default(),
+        &Default::default(),
+        Default::default(),
+    )
+    .unwrap();
+
+    let mut scanner = Scanner::new();
+    scanner.set_probe(true);
+    scanner.set_ffprobe(&ffprobe);
+    let movies = scanner.scan_movies(&root, &index).unwrap().movies;
+    let heat = movies.iter().find(|m| m.path().ends_with("Heat.1995.mkv")).unwrap();
+    assert_eq!(heat.video.len(), 1);
+    assert_eq!(heat.video[0].to_string(), "2160p hevc 10-bit HDR10");
+    assert_eq!(heat.audio[0].to_string(), "eac3 5.1 fra");
+
+    let mut broken: Vec<_> = movies
+        .into_iter()
+        .map(|movie| (movie.path().file_name().unwrap().to_owned(), movie.broken))
+        .collect();
+    broken.sort_by(|a, b| a.0.cmp(&b.0));
+
+    assert_eq!(
+        broken,
+        vec![
+            ("Alien.1979.mkv".into(), Some(Broken::Unreadable)),
+            ("Aliens.1986.mkv".into(), Some(Broken::NoVideo)),
+            ("Heat (1995).mkv".into(), None),
+            ("Heat.1995.mkv".into(), None),
+            (
+                "Some.Movie.2004.mkv".into(),
+                Some(Broken::Truncated {
+                    duration: 10,
+                    runtime: 100
+                })
+            ),
+        ]
+    );
+}
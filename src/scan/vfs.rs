@@ -1,25 +1,48 @@
 use std::cmp::{Eq, PartialEq};
-use std::collections::BTreeSet;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeSet, HashMap};
 use std::fmt;
-use std::fs::Metadata;
+use std::fs;
 use std::hash::{Hash, Hasher};
 use std::io;
 use std::path::{Path, PathBuf};
-use std::rc::Rc;
-use std::time::SystemTime;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use super::ignore::IgnoreRules;
 use super::paths::AbsPath;
 use super::tree::{self, NodeId, Tree};
+use crate::io::archive;
 
 struct FileNode {
     path: AbsPath,
-    metadata: Metadata,
+    /// Name of the file decoded to text, for names which are not UTF-8.
+    name: String,
+    /// Directories and archives, whose members are the children of the node.
+    is_dir: bool,
+    len: u64,
     mtime: SystemTime,
+    /// Target of a symbolic link recorded without being followed.
+    link: Option<PathBuf>,
+}
+
+impl FileNode {
+    fn new(path: AbsPath, is_dir: bool, len: u64, mtime: SystemTime) -> FileNode {
+        FileNode {
+            name: path.file_name().into_owned(),
+            path,
+            is_dir,
+            len,
+            mtime,
+            link: None,
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct File {
-    tree: Rc<Tree<FileNode>>,
+    tree: Arc<Tree<FileNode>>,
     node: NodeId,
 }
 
@@ -52,32 +75,41 @@ impl File {
         &self.data().path
     }
 
+    /// Name of the file, decoded to text if it is not UTF-8.
     pub fn name(&self) -> &str {
-        self.path().file_name().and_then(|s| s.to_str()).unwrap_or("")
+        &self.data().name
     }
 
     pub fn stem(&self) -> &str {
-        self.path().file_stem().and_then(|s| s.to_str()).unwrap_or("")
+        Path::new(self.name())
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("")
     }
 
     pub fn ext(&self) -> &str {
-        self.path().extension().and_then(|s| s.to_str()).unwrap_or("")
+        Path::new(self.name())
+            .extension()
+            .and_then(|s| s.to_str())
+            .unwrap_or("")
     }
 
-    pub fn metadata(&self) -> &Metadata {
-        &self.data().metadata
+    /// Regular files and members of archives, links recorded without being followed are not files.
+    pub fn is_file(&self) -> bool {
+        !self.data().is_dir && self.data().link.is_none()
     }
 
-    pub fn is_file(&self) -> bool {
-        self.metadata().is_file()
+    /// Target of a symbolic link recorded without being followed.
+    pub fn link_target(&self) -> Option<&Path> {
+        self.data().link.as_deref()
     }
 
     pub fn is_dir(&self) -> bool {
-        self.metadata().is_dir()
+        self.data().is_dir
     }
 
     pub fn len(&self) -> u64 {
-        self.metadata().len()
+        self.data().len
     }
 
     pub fn mtime(&self) -> SystemTime {
@@ -114,7 +146,7 @@ impl File {
 }
 
 pub struct ChildrenIter<'a> {
-    tree: Rc<Tree<FileNode>>,
+    tree: Arc<Tree<FileNode>>,
     iter: tree::ChildrenIter<'a, FileNode>,
 }
 
@@ -130,7 +162,7 @@ impl Iterator for ChildrenIter<'_> {
 }
 
 pub struct SiblingsIter<'a> {
-    tree: Rc<Tree<FileNode>>,
+    tree: Arc<Tree<FileNode>>,
     iter: tree::SiblingsIter<'a, FileNode>,
 }
 
@@ -146,7 +178,7 @@ impl Iterator for SiblingsIter<'_> {
 }
 
 pub struct DescendantsIter<'a> {
-    tree: Rc<Tree<FileNode>>,
+    tree: Arc<Tree<FileNode>>,
     iter: tree::DescendantsIter<'a, FileNode>,
 }
 
@@ -161,60 +193,391 @@ impl Iterator for DescendantsIter<'_> {
     }
 }
 
-pub fn walk(root: impl AsRef<Path>, ignored: &BTreeSet<PathBuf>) -> io::Result<File> {
-    let mut tree = Tree::new();
+/// What the walker does with symbolic links found below the root.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum SymlinkPolicy {
+    /// Walk the target of the link as if it was at the path of the link.
+    #[default]
+    Follow,
+    Skip,
+    /// Keep the link in the tree, without reading its target.
+    Record,
+}
+
+impl FromStr for SymlinkPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<SymlinkPolicy, String> {
+        match s {
+            "follow" => Ok(SymlinkPolicy::Follow),
+            "skip" => Ok(SymlinkPolicy::Skip),
+            "record" => Ok(SymlinkPolicy::Record),
+            _ => Err(format!("invalid symlink policy {}, expected follow, skip or record", s)),
+        }
+    }
+}
+
+/// Identity of a directory, to detect the links that lead back to a directory already walked.
+#[derive(Debug, PartialEq, Eq, Hash)]
+enum DirId {
+    Inode(u64, u64),
+    Path(PathBuf),
+}
+
+#[cfg(unix)]
+fn dir_id(_path: &Path, metadata: &fs::Metadata) -> io::Result<DirId> {
+    use std::os::unix::fs::MetadataExt;
+    Ok(DirId::Inode(metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn dir_id(path: &Path, _metadata: &fs::Metadata) -> io::Result<DirId> {
+    Ok(DirId::Path(path.canonicalize()?))
+}
 
-    match walk_rec(&mut tree, root.as_ref().to_owned(), None, ignored)? {
+/// Walk a directory into a tree of files.
+///
+/// Files keep the path under which they were found, even below a followed link, so that reports and
+/// ignored paths use the same paths as the user. The `rules` apply to the whole tree, along with the
+/// rules of the `.meroignore` files found while walking it.
+pub fn walk(
+    root: impl AsRef<Path>,
+    ignored: &BTreeSet<PathBuf>,
+    rules: &IgnoreRules,
+    symlinks: SymlinkPolicy,
+) -> io::Result<File> {
+    let mut walker = Walker {
+        tree: Tree::new(),
+        ignored,
+        rules: rules.clone(),
+        symlinks,
+        visited: HashMap::new(),
+    };
+
+    match walker.walk_rec(std::path::absolute(root)?, None)? {
         Some(node) => Ok(File {
-            tree: Rc::new(tree),
+            tree: Arc::new(walker.tree),
             node,
         }),
         None => Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid root path")),
     }
 }
 
-fn walk_rec(
-    tree: &mut Tree<FileNode>,
-    path: PathBuf,
-    parent: Option<NodeId>,
-    ignored: &BTreeSet<PathBuf>,
-) -> io::Result<Option<NodeId>> {
-    if ignored.contains(&path) {
-        return Ok(None);
-    }
+struct Walker<'a> {
+    tree: Tree<FileNode>,
+    ignored: &'a BTreeSet<PathBuf>,
+    /// Rules of the global ignore file and of the `.meroignore` files of the directories being walked.
+    rules: IgnoreRules,
+    symlinks: SymlinkPolicy,
+    /// Directories already walked, with the path where they were found.
+    visited: HashMap<DirId, PathBuf>,
+}
 
-    let metadata = path.metadata()?;
+impl Walker<'_> {
+    /// Check if a path is ignored, by its own path or by the path it resolves to.
+    fn is_ignored(&self, path: &Path) -> bool {
+        if self.ignored.is_empty() {
+            return false;
+        }
+        self.ignored.contains(path) || path.canonicalize().is_ok_and(|real| self.ignored.contains(&real))
+    }
 
-    if !metadata.is_dir() && !metadata.is_file() {
-        return Ok(None);
+    fn insert(&mut self, parent: Option<NodeId>, file_node: FileNode) -> NodeId {
+        match parent {
+            Some(parent) => self.tree.insert_below(parent, file_node),
+            None => self.tree.insert_root(file_node),
+        }
     }
 
-    let path = path.canonicalize()?;
-    if let Some(path) = AbsPath::new(&path) {
-        let file_node = FileNode {
-            path: path,
-            mtime: metadata.modified()?,
-            metadata,
+    fn walk_rec(&mut self, path: PathBuf, parent: Option<NodeId>) -> io::Result<Option<NodeId>> {
+        if self.is_ignored(&path) {
+            return Ok(None);
+        }
+
+        // the root is always followed, it was given by the user
+        let link_metadata = path.symlink_metadata()?;
+        let is_link = link_metadata.file_type().is_symlink();
+
+        if parent.is_some() && !self.rules.is_empty() {
+            let is_dir = link_metadata.is_dir() || (is_link && path.is_dir());
+            if self.rules.is_ignored(&path, is_dir) {
+                return Ok(None);
+            }
+        }
+        if parent.is_some() && is_link {
+            match self.symlinks {
+                SymlinkPolicy::Follow => {}
+                SymlinkPolicy::Skip => return Ok(None),
+                SymlinkPolicy::Record => {
+                    let mut file_node = FileNode::new(
+                        AbsPath::new(&path),
+                        false,
+                        link_metadata.len(),
+                        link_metadata.modified().unwrap_or(UNIX_EPOCH),
+                    );
+                    file_node.link = Some(fs::read_link(&path)?);
+                    return Ok(Some(self.insert(parent, file_node)));
+                }
+            }
+        }
+
+        let metadata = match path.metadata() {
+            Ok(metadata) => metadata,
+            Err(err) if parent.is_some() && is_link => {
+                eprintln!("Warning: broken link {} was ignored: {}", path.display(), err);
+                return Ok(None);
+            }
+            Err(err) => return Err(err),
         };
 
-        let node = match parent {
-            Some(parent) => tree.insert_below(parent, file_node),
-            None => tree.insert_root(file_node),
+        if !metadata.is_dir() && !metadata.is_file() {
+            return Ok(None);
+        }
+
+        if metadata.is_dir() {
+            match self.visited.entry(dir_id(&path, &metadata)?) {
+                Entry::Occupied(visited) => {
+                    eprintln!(
+                        "Warning: {} was ignored because it leads to {} which was already walked",
+                        path.display(),
+                        visited.get().display()
+                    );
+                    return Ok(None);
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(path.clone());
+                }
+            }
+        }
+
+        let path = AbsPath::new(path);
+
+        // archives are read as directories
+        let members = if metadata.is_file() && archive::is_archive(&path) {
+            match archive::list(&path) {
+                Ok(members) => Some(members),
+                Err(err) => {
+                    eprintln!("Warning: archive {} could not be read: {}", path.display(), err);
+                    None
+                }
+            }
+        } else {
+            None
         };
 
-        let file_node = tree.data(node);
+        let is_dir = metadata.is_dir() || members.is_some();
+        let node = self.insert(
+            parent,
+            FileNode::new(path, is_dir, metadata.len(), metadata.modified()?),
+        );
+
+        if let Some(members) = members {
+            insert_members(&mut self.tree, node, members, &self.rules);
+        } else if metadata.is_dir() {
+            // the rules of a directory only apply to its descendants
+            let rules_len = self.rules.len();
+            self.rules.add_dir(self.tree.data(node).path.as_path())?;
 
-        if file_node.metadata.is_dir() {
-            for entry in file_node.path.read_dir()? {
+            for entry in self.tree.data(node).path.read_dir()? {
                 let entry = entry?;
-                walk_rec(tree, entry.path(), Some(node), ignored)?;
+                self.walk_rec(entry.path(), Some(node))?;
             }
+
+            self.rules.truncate(rules_len);
         }
 
         Ok(Some(node))
-    } else {
-        // TODO: warn!
-        eprintln!("Warning: path {} was ignored because it is not utf-8", path.display());
-        Ok(None)
     }
 }
+
+/// Insert the members of an archive below its node, creating the directories missing from the archive.
+fn insert_members(tree: &mut Tree<FileNode>, archive: NodeId, members: Vec<archive::Entry>, rules: &IgnoreRules) {
+    let archive_path = tree.data(archive).path.as_path().to_owned();
+    let mtime = tree.data(archive).mtime;
+    let mut dirs: HashMap<PathBuf, NodeId> = HashMap::new();
+
+    for member in members {
+        // members are ignored with the directories of the archive which contain them
+        let is_ignored = member.path.ancestors().any(|path| {
+            let is_dir = member.is_dir || path != member.path;
+            !path.as_os_str().is_empty() && rules.is_ignored(&archive_path.join(path), is_dir)
+        });
+        if is_ignored {
+            continue;
+        }
+
+        let mut parent = archive;
+        let mut dir = PathBuf::new();
+
+        if let Some(member_dir) = member.path.parent() {
+            for component in member_dir.components() {
+                dir.push(component);
+                parent = match dirs.get(&dir) {
+                    Some(&node) => node,
+                    None => {
+                        let file_node = FileNode::new(AbsPath::new(archive_path.join(&dir)), true, 0, mtime);
+                        let node = tree.insert_below(parent, file_node);
+                        dirs.insert(dir.clone(), node);
+                        node
+                    }
+                };
+            }
+        }
+
+        let file_node = FileNode::new(
+            AbsPath::new(archive_path.join(&member.path)),
+            member.is_dir,
+            member.len,
+            member.mtime,
+        );
+
+        // directories may be listed after their members
+        if member.is_dir {
+            dirs.entry(member.path)
+                .or_insert_with(|| tree.insert_below(parent, file_node));
+        } else {
+            tree.insert_below(parent, file_node);
+        }
+    }
+}
+
+#[test]
+fn test_walk_archive() {
+    let root = walk(
+        "testdata/archive",
+        &Default::default(),
+        &Default::default(),
+        Default::default(),
+    )
+    .unwrap();
+    let mut names: Vec<_> = root
+        .descendants()
+        .map(|f| (f.path().strip_prefix(root.path()).unwrap().to_owned(), f.is_dir()))
+        .collect();
+    names.sort();
+    assert_eq!(
+        names,
+        vec![
+            (PathBuf::from("movie.tar"), true),
+            (PathBuf::from("movie.tar/Heat (1995).mkv"), false),
+            (PathBuf::from("subs.zip"), true),
+            (PathBuf::from("subs.zip/Heat.1995.en.srt"), false),
+            (PathBuf::from("subs.zip/Subs"), true),
+            (PathBuf::from("subs.zip/Subs/Heat.1995.fr.srt"), false),
+        ]
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_walk_symlinks() {
+    use std::os::unix::fs::symlink;
+
+    let dir = std::env::temp_dir().join("mero-test-walk-symlinks");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("movies")).unwrap();
+    fs::write(dir.join("movies/Heat.1995.mkv"), "heat").unwrap();
+    symlink(dir.join("movies/Heat.1995.mkv"), dir.join("Heat.mkv")).unwrap();
+    // a link to its own parent would be walked forever
+    symlink(&dir, dir.join("movies/loop")).unwrap();
+
+    let walk_names = |symlinks| {
+        let root = walk(&dir, &Default::default(), &Default::default(), symlinks).unwrap();
+        let mut names: Vec<_> = root
+            .descendants()
+            .map(|f| {
+                (
+                    f.path().strip_prefix(&dir).unwrap().to_owned(),
+                    f.link_target().is_some(),
+                )
+            })
+            .collect();
+        names.sort();
+        names
+    };
+
+    assert_eq!(
+        walk_names(SymlinkPolicy::Follow),
+        vec![
+            (PathBuf::from("Heat.mkv"), false),
+            (PathBuf::from("movies"), false),
+            (PathBuf::from("movies/Heat.1995.mkv"), false),
+        ]
+    );
+    assert_eq!(
+        walk_names(SymlinkPolicy::Skip),
+        vec![
+            (PathBuf::from("movies"), false),
+            (PathBuf::from("movies/Heat.1995.mkv"), false),
+        ]
+    );
+    assert_eq!(
+        walk_names(SymlinkPolicy::Record),
+        vec![
+            (PathBuf::from("Heat.mkv"), true),
+            (PathBuf::from("movies"), false),
+            (PathBuf::from("movies/Heat.1995.mkv"), false),
+            (PathBuf::from("movies/loop"), true),
+        ]
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_walk_non_utf8() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let dir = std::env::temp_dir().join("mero-test-walk-non-utf8");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(OsStr::from_bytes(b"Am\xe9lie.2001.mkv"));
+    fs::write(&path, "amelie").unwrap();
+
+    let root = walk(&dir, &Default::default(), &Default::default(), Default::default()).unwrap();
+    let file = root.children().next().unwrap();
+    assert_eq!(file.path(), path);
+    assert_eq!(file.name(), "Amélie.2001.mkv");
+    assert_eq!(file.stem(), "Amélie.2001");
+
+    // paths are kept as they are in scan reports
+    let pathsize = super::PathSize::from(&file);
+    let bytes = bincode::serialize(&pathsize).unwrap();
+    let pathsize: super::PathSize = bincode::deserialize(&bytes).unwrap();
+    assert_eq!(pathsize.path, path);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_walk_ignore_files() {
+    let dir = std::env::temp_dir().join("mero-test-walk-ignore");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("Heat.1995/Extras")).unwrap();
+    fs::write(dir.join("Heat.1995/Heat.1995.mkv"), "heat").unwrap();
+    fs::write(dir.join("Heat.1995/Heat.1995.sample.mkv"), "sample").unwrap();
+    fs::write(dir.join("Heat.1995/Extras/Making of.mkv"), "extra").unwrap();
+    fs::write(dir.join(".meroignore"), "Extras/\n*.sample.mkv\n").unwrap();
+    fs::write(dir.join("Heat.1995/.meroignore"), "!Heat.1995.sample.mkv\n").unwrap();
+
+    let mut rules = IgnoreRules::new();
+    rules.add_file(dir.join("global-ignore"), &dir).unwrap();
+    let root = walk(&dir, &Default::default(), &rules, Default::default()).unwrap();
+    let mut names: Vec<_> = root
+        .descendants()
+        .filter(|f| f.is_file() && f.ext() == "mkv")
+        .map(|f| f.path().strip_prefix(&dir).unwrap().to_owned())
+        .collect();
+    names.sort();
+    assert_eq!(
+        names,
+        vec![
+            PathBuf::from("Heat.1995/Heat.1995.mkv"),
+            PathBuf::from("Heat.1995/Heat.1995.sample.mkv"),
+        ]
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
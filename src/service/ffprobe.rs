@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::ffi::OsStr;
 use std::path::Path;
 use std::process::Command;
 
@@ -8,15 +9,25 @@ use crate::error::{Error, Result};
 
 #[derive(Debug, Deserialize)]
 pub struct MediaInfo {
+    /// Duration of the container in seconds.
+    pub duration: Option<f64>,
     pub video: Vec<VideoStream>,
     pub audio: Vec<AudioStream>,
     pub subtitle: Vec<SubtitleStream>,
 }
 
+/// Binary run by `scan`, found in the PATH.
+pub const FFPROBE: &str = "ffprobe";
+
 pub fn scan(path: impl AsRef<Path>) -> Result<MediaInfo> {
-    // ffprobe -v quiet -print_format json -show_streams <path>
-    let output = Command::new("ffprobe")
-        .args(&["-v", "quiet", "-print_format", "json", "-show_streams"])
+    scan_with(FFPROBE, path)
+}
+
+/// Probe a file with a given ffprobe binary.
+pub fn scan_with(ffprobe: impl AsRef<OsStr>, path: impl AsRef<Path>) -> Result<MediaInfo> {
+    // ffprobe -v quiet -print_format json -show_format -show_streams <path>
+    let output = Command::new(ffprobe)
+        .args(["-v", "quiet", "-print_format", "json", "-show_format", "-show_streams"])
         .arg(path.as_ref())
         .output()?;
 
@@ -34,17 +45,33 @@ pub fn scan(path: impl AsRef<Path>) -> Result<MediaInfo> {
             Stream::Video(inner) => video.push(inner),
             Stream::Audio(inner) => audio.push(inner),
             Stream::Subtitle(inner) => subtitle.push(inner),
+            Stream::Other => {}
         }
     }
 
-    Ok(MediaInfo { video, audio, subtitle })
+    let duration = probe.format.and_then(|f| f.duration.parse().ok());
+
+    Ok(MediaInfo {
+        duration,
+        video,
+        audio,
+        subtitle,
+    })
 }
 
 #[derive(Debug, Deserialize)]
 struct Probe {
+    #[serde(default)]
+    format: Option<Format>,
     streams: Vec<Stream>,
 }
 
+#[derive(Debug, Deserialize)]
+struct Format {
+    #[serde(default)]
+    duration: String,
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(tag = "codec_type")]
 enum Stream {
@@ -54,6 +81,9 @@ enum Stream {
     Audio(AudioStream),
     #[serde(rename = "subtitle")]
     Subtitle(SubtitleStream),
+    /// Attachments such as fonts, and data streams.
+    #[serde(other)]
+    Other,
 }
 
 #[derive(Debug, Deserialize)]
@@ -62,15 +92,87 @@ pub struct VideoStream {
     pub codec_name: String,
     pub width: u32,
     pub height: u32,
+    /// Frame rate as a fraction, such as `24000/1001`.
+    #[serde(default)]
+    pub r_frame_rate: String,
+    /// Pixel format, such as `yuv420p10le`.
+    #[serde(default)]
+    pub pix_fmt: String,
+    #[serde(default)]
+    pub bits_per_raw_sample: String,
+    /// Transfer characteristics, `smpte2084` for HDR10 and `arib-std-b67` for HLG.
+    #[serde(default)]
+    pub color_transfer: String,
+    #[serde(default)]
+    pub side_data_list: Vec<SideData>,
+    /// Flags such as `attached_pic`, set for cover art.
+    #[serde(default)]
+    pub disposition: HashMap<String, i64>,
+    #[serde(default)]
     pub tags: HashMap<String, String>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct SideData {
+    #[serde(default)]
+    pub side_data_type: String,
+}
+
+impl VideoStream {
+    pub fn frame_rate(&self) -> Option<f64> {
+        let mut parts = self.r_frame_rate.splitn(2, '/');
+        let num: f64 = parts.next()?.parse().ok()?;
+        let den: f64 = parts.next().unwrap_or("1").parse().ok()?;
+        if num > 0.0 && den > 0.0 {
+            Some(num / den)
+        } else {
+            None
+        }
+    }
+
+    pub fn is_attached_pic(&self) -> bool {
+        self.disposition.get("attached_pic") == Some(&1)
+    }
+
+    /// Bits per sample, from the pixel format if ffprobe does not report it.
+    pub fn bit_depth(&self) -> Option<u8> {
+        if let Ok(bits) = self.bits_per_raw_sample.parse() {
+            return Some(bits);
+        }
+        // the depth ends the name of the format, as in yuv420p10le, unless it is 8 bits
+        let format = self.pix_fmt.trim_end_matches("le").trim_end_matches("be");
+        match format.rsplit_once('p') {
+            Some((_, "")) => Some(8),
+            Some((_, bits)) => bits.parse().ok(),
+            None => None,
+        }
+    }
+
+    /// HDR format of the stream, `None` for SDR.
+    pub fn hdr(&self) -> Option<&'static str> {
+        if self
+            .side_data_list
+            .iter()
+            .any(|data| data.side_data_type == "DOVI configuration record")
+        {
+            return Some("Dolby Vision");
+        }
+        match self.color_transfer.as_str() {
+            "smpte2084" => Some("HDR10"),
+            "arib-std-b67" => Some("HLG"),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct AudioStream {
     pub index: u32,
     pub codec_name: String,
+    #[serde(default)]
     pub bit_rate: String,
     pub channels: u32,
+    #[serde(default)]
     pub tags: HashMap<String, String>,
 }
 
@@ -78,5 +180,6 @@ pub struct AudioStream {
 pub struct SubtitleStream {
     pub index: u32,
     pub codec_name: String,
+    #[serde(default)]
     pub tags: HashMap<String, String>,
 }
@@ -1,4 +1,4 @@
-mod ffprobe;
+pub mod ffprobe;
 pub mod tmdb;
 
 pub use self::tmdb::TMDB;
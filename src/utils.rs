@@ -1,9 +1,13 @@
+use std::borrow::Cow;
 use std::cmp;
+use std::ffi::OsStr;
 use std::fmt;
 use std::fs::File;
 use std::io::{self, BufReader, BufWriter, Read};
 use std::ops::Deref;
 use std::path::Path;
+use std::sync::Mutex;
+use std::thread;
 
 use libflate::finish::AutoFinish;
 use libflate::gzip::{Decoder, Encoder};
@@ -61,6 +65,25 @@ pub fn clean_path(source: &str) -> String {
     dest
 }
 
+/// Decode a file name to text. Names which are not UTF-8 are decoded as Windows-1252, the usual
+/// encoding of old Latin-1 names, so that they are still readable.
+pub fn decode_file_name(name: &OsStr) -> Cow<'_, str> {
+    if let Some(name) = name.to_str() {
+        return Cow::Borrowed(name);
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        let (name, _) = encoding_rs::WINDOWS_1252.decode_without_bom_handling(name.as_bytes());
+        Cow::Owned(name.into_owned())
+    }
+    #[cfg(not(unix))]
+    {
+        Cow::Owned(name.to_string_lossy().into_owned())
+    }
+}
+
 pub struct SafeBuffer(Vec<u8>);
 
 impl SafeBuffer {
@@ -172,6 +195,94 @@ impl Deref for SafeBuffer {
     }
 }
 
+/// Number of worker threads used when none is given, one per CPU.
+pub fn default_jobs() -> usize {
+    thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
+
+/// Map items on a pool of `jobs` worker threads, each worker having its own state created by `init`.
+///
+/// The results are in the order of the items, as if they had been mapped one after the other.
+pub fn parallel_map<T, S, R, I, F>(items: Vec<T>, jobs: usize, init: I, func: F) -> Vec<R>
+where
+    T: Send,
+    R: Send,
+    I: Fn() -> S + Sync,
+    F: Fn(&mut S, T) -> R + Sync,
+{
+    let len = items.len();
+    let jobs = cmp::min(jobs, len);
+
+    if jobs <= 1 {
+        let mut state = init();
+        return items.into_iter().map(|item| func(&mut state, item)).collect();
+    }
+
+    let queue = Mutex::new(items.into_iter().enumerate());
+    let mut results: Vec<Option<R>> = (0..len).map(|_| None).collect();
+
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..jobs)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut state = init();
+                    let mut done = vec![];
+                    loop {
+                        // the lock is released before the item is mapped
+                        let next = queue.lock().expect("worker panicked").next();
+                        match next {
+                            Some((pos, item)) => done.push((pos, func(&mut state, item))),
+                            None => return done,
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        for worker in workers {
+            for (pos, result) in worker.join().expect("worker panicked") {
+                results[pos] = Some(result);
+            }
+        }
+    });
+
+    results
+        .into_iter()
+        .map(|result| result.expect("every item is mapped"))
+        .collect()
+}
+
+#[test]
+fn test_decode_file_name() {
+    assert_eq!(decode_file_name(OsStr::new("Amélie.mkv")), "Amélie.mkv");
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        assert_eq!(decode_file_name(OsStr::from_bytes(b"Am\xe9lie.mkv")), "Amélie.mkv");
+    }
+}
+
+#[test]
+fn test_parallel_map() {
+    let items: Vec<u64> = (0..100).collect();
+    let squares: Vec<u64> = items.iter().map(|n| n * n).collect();
+
+    assert_eq!(parallel_map(items.clone(), 1, || (), |_, n| n * n), squares);
+    assert_eq!(parallel_map(items.clone(), 8, || (), |_, n| n * n), squares);
+    // each worker has its own state
+    let counts = parallel_map(
+        items,
+        4,
+        || 0,
+        |count, _| {
+            *count += 1;
+            *count
+        },
+    );
+    assert!(counts.iter().all(|count| (1..=100).contains(count)));
+}
+
 #[test]
 fn test_safe_buffer() {
     use std::io::Cursor;